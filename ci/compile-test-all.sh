@@ -5,5 +5,22 @@ set -o errexit -o nounset
 
 export RUSTFLAGS="--deny warnings"
 
+# All features which work on the stable compiler.
+STABLE_FEATURES="arbitrary bytemuck defmt proptest quickcheck rand rayon serde stats"
+
 cargo build --verbose
+cargo build --verbose --no-default-features
+
+# Each optional feature has to compile on its own.
+for feature in $STABLE_FEATURES; do
+    cargo build --verbose --no-default-features --features "$feature"
+done
+
 cargo test --verbose
+cargo test --verbose --no-default-features
+cargo test --verbose --features "$STABLE_FEATURES"
+
+# `allocator_api` needs a nightly compiler.
+if rustc --version | grep -q nightly; then
+    cargo test --verbose --all-features
+fi
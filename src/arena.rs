@@ -0,0 +1,218 @@
+//! An arena with reclaimable slots based on the segmented storage.
+//!
+//! See [`StableArena`](struct.StableArena.html) for more information.
+
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::ops::{Index, IndexMut};
+
+use {IterMut, SegmentedStableVec};
+use storage::{SegmentedStorage, Storage};
+
+
+/// An arena which can allocate through a shared reference and reuses the
+/// slots of removed values.
+///
+/// [`alloc()`](#method.alloc) only needs `&self` and returns a reference to
+/// the new value, which stays valid for as long as the arena is borrowed:
+/// the arena is backed by a
+/// [`SegmentedStableVec`](../type.SegmentedStableVec.html), which never moves
+/// its elements when growing. Removing values needs `&mut self`, so no
+/// reference handed out by `alloc()` can outlive its value. Unlike with an
+/// arena backed by a `Vec`, the slots of removed values are reused by later
+/// allocations.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::StableArena;
+///
+/// let mut arena = StableArena::new();
+/// let (a, first) = arena.alloc(String::from("first"));
+/// let (_, second) = arena.alloc(String::from("second"));
+///
+/// // Both references are valid at the same time.
+/// assert_eq!(first.len() + second.len(), 11);
+///
+/// assert_eq!(arena.remove(a), Some(String::from("first")));
+/// let (c, _) = arena.alloc(String::from("third"));
+/// assert_eq!(c, a); // the slot was reused
+/// ```
+pub struct StableArena<T> {
+    /// Only accessed mutably in `alloc()` (which never touches existing
+    /// elements) or through `&mut self`.
+    inner: UnsafeCell<SegmentedStableVec<T>>,
+}
+
+impl<T> StableArena<T> {
+    /// Constructs a new, empty arena. Doesn't allocate until a value is
+    /// allocated.
+    pub fn new() -> Self {
+        Self::from_stable_vec(SegmentedStableVec::new())
+    }
+
+    /// Wraps the given stable vector.
+    pub fn from_stable_vec(sv: SegmentedStableVec<T>) -> Self {
+        Self {
+            inner: UnsafeCell::new(sv),
+        }
+    }
+
+    /// Unwraps the underlying stable vector.
+    pub fn into_stable_vec(self) -> SegmentedStableVec<T> {
+        self.inner.into_inner()
+    }
+
+    /// Returns the underlying stable vector. The returned reference must not
+    /// be held across a call to `alloc()`, as that might reallocate the
+    /// bookkeeping of the stable vector; that's why this isn't public.
+    fn sv(&self) -> &SegmentedStableVec<T> {
+        unsafe { &*self.inner.get() }
+    }
+
+    /// Returns the underlying stable vector mutably.
+    pub fn as_stable_vec_mut(&mut self) -> &mut SegmentedStableVec<T> {
+        self.inner.get_mut()
+    }
+
+    /// Stores `value` in the arena (reusing the slot of a removed value, if
+    /// there is one) and returns its index and a reference to it.
+    pub fn alloc(&self, value: T) -> (usize, &T) {
+        // Pushing only writes into a hole or a new slot and never moves any
+        // elements, so references returned earlier stay valid. No other
+        // reference to the stable vector itself exists right now (see
+        // `sv()`).
+        let sv = unsafe { &mut *self.inner.get() };
+        let index = sv.push_reuse(value);
+        let value = unsafe { &*sv.get_ptr(index).unwrap() };
+        (index, value)
+    }
+
+    /// Removes and returns the value at the given index, or `None` if there
+    /// exists no value at that index. Its slot is reused by later
+    /// allocations.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        self.inner.get_mut().remove(index)
+    }
+
+    /// Returns a reference to the value at the given index, or `None` if
+    /// there exists no value at that index.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.sv().get(index)
+    }
+
+    /// Returns a mutable reference to the value at the given index, or `None`
+    /// if there exists no value at that index.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.inner.get_mut().get_mut(index)
+    }
+
+    /// Returns `true` if there exists a value at the given index.
+    pub fn has_element_at(&self, index: usize) -> bool {
+        self.sv().has_element_at(index)
+    }
+
+    /// Returns the number of values in the arena.
+    pub fn num_elements(&self) -> usize {
+        self.sv().num_elements()
+    }
+
+    /// Returns `true` if there are no values in the arena.
+    pub fn is_empty(&self) -> bool {
+        self.sv().is_empty()
+    }
+
+    /// Returns one more than the largest index of any value allocated so far.
+    pub fn next_index(&self) -> usize {
+        self.sv().next_index()
+    }
+
+    /// Returns an iterator over immutable references to all values.
+    ///
+    /// Values can be allocated while iterating; depending on their index,
+    /// they may or may not be visited.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter { arena: self, pos: 0 }
+    }
+
+    /// Returns an iterator over mutable references to all values.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, SegmentedStorage<T>> {
+        self.inner.get_mut().iter_mut()
+    }
+
+    /// Returns an iterator over the indices of all values. See
+    /// [`iter()`](#method.iter).
+    pub fn keys(&self) -> Keys<'_, T> {
+        Keys { arena: self, pos: 0 }
+    }
+
+    /// Returns the index of the next value at an index `>= *pos` and
+    /// advances `pos` past it.
+    fn next_key(&self, pos: &mut usize) -> Option<usize> {
+        let index = self.sv().storage.next_occupied(*pos)?;
+        *pos = index + 1;
+        Some(index)
+    }
+}
+
+impl<T> Index<usize> for StableArena<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.sv()[index]
+    }
+}
+
+impl<T> IndexMut<usize> for StableArena<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.inner.get_mut()[index]
+    }
+}
+
+impl<T> Default for StableArena<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for StableArena<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "StableArena ")?;
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over immutable references to the values of an arena.
+///
+/// Use the method [`StableArena::iter()`](struct.StableArena.html#method.iter)
+/// to obtain an iterator of this kind.
+pub struct Iter<'a, T: 'a> {
+    arena: &'a StableArena<T>,
+    pos: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let arena = self.arena;
+        arena.next_key(&mut self.pos).and_then(|i| arena.get(i))
+    }
+}
+
+/// Iterator over the indices of the values of an arena.
+///
+/// Use the method [`StableArena::keys()`](struct.StableArena.html#method.keys)
+/// to obtain an iterator of this kind.
+pub struct Keys<'a, T: 'a> {
+    arena: &'a StableArena<T>,
+    pos: usize,
+}
+
+impl<'a, T> Iterator for Keys<'a, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.arena.next_key(&mut self.pos)
+    }
+}
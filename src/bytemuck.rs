@@ -0,0 +1,65 @@
+//! Viewing plain old data elements as bytes (requires the `bytemuck`
+//! feature).
+//!
+//! For element types implementing [`bytemuck::Pod`], hole-free ranges of a
+//! [`StableVec`](../type.StableVec.html) can be viewed as raw bytes, e.g. to
+//! upload them to a GPU or write them to a file, without any unsafe code on
+//! the user side. For a compact stable vector, `slice_bytes(..)` returns all
+//! elements.
+//!
+//! [`bytemuck::Pod`]: https://docs.rs/bytemuck/1/bytemuck/trait.Pod.html
+//!
+//! ```
+//! # extern crate stable_vec;
+//! # use stable_vec::StableVec;
+//! # fn main() {
+//! let mut sv = StableVec::from(&[1u16, 2, 3]);
+//! assert_eq!(sv.slice_bytes(..).map(|b| b.len()), Some(6));
+//!
+//! sv.slice_bytes_mut(2..).unwrap().copy_from_slice(&[0xff; 2]);
+//! assert_eq!(sv[2], 0xffff);
+//!
+//! sv.remove(1);
+//! assert_eq!(sv.slice_bytes(..), None);
+//! # }
+//! ```
+
+use bytemuck_crate::{cast_slice, cast_slice_mut, Pod};
+
+use core::mem;
+use core::ops::RangeBounds;
+
+use StableVec;
+
+
+impl<T: Pod> StableVec<T> {
+    /// Returns the elements in the given index range as bytes, or `None` if
+    /// the range contains a hole or reaches beyond `next_index()`. See
+    /// [`slice()`](#method.slice) and [the `bytemuck`
+    /// module](bytemuck/index.html).
+    ///
+    /// For zero-sized `T`, the bytes of any hole-free range are empty.
+    pub fn slice_bytes<R>(&self, range: R) -> Option<&[u8]>
+        where R: RangeBounds<usize>
+    {
+        // Older `bytemuck` versions panic in `cast_slice` for zero-sized
+        // element types.
+        if mem::size_of::<T>() == 0 {
+            return self.slice(range).map(|_| &[][..]);
+        }
+        self.slice(range).map(cast_slice)
+    }
+
+    /// Returns the elements in the given index range as mutable bytes, or
+    /// `None` if the range contains a hole or reaches beyond `next_index()`.
+    /// See [`slice_mut()`](#method.slice_mut) and [the `bytemuck`
+    /// module](bytemuck/index.html).
+    pub fn slice_bytes_mut<R>(&mut self, range: R) -> Option<&mut [u8]>
+        where R: RangeBounds<usize>
+    {
+        if mem::size_of::<T>() == 0 {
+            return self.slice_mut(range).map(|_| &mut [][..]);
+        }
+        self.slice_mut(range).map(cast_slice_mut)
+    }
+}
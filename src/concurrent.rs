@@ -0,0 +1,353 @@
+//! A stable vector which allows concurrent pushes.
+//!
+//! See [`ConcurrentStableVec`](struct.ConcurrentStableVec.html) for more
+//! information.
+
+use alloc::boxed::Box;
+use core::cell::UnsafeCell;
+use core::fmt;
+use core::mem::MaybeUninit;
+use core::ptr;
+use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicUsize, Ordering};
+
+use StableVec;
+
+
+/// The number of slots in the first segment, as power of two.
+const FIRST_SEGMENT_BITS: u32 = 5;
+
+/// The number of slots in the first segment.
+const FIRST_SEGMENT_LEN: usize = 1 << FIRST_SEGMENT_BITS;
+
+/// The number of segments needed to address all of `usize`.
+const NUM_SEGMENTS: usize = (usize::BITS - FIRST_SEGMENT_BITS) as usize;
+
+
+/// A single slot of a segment.
+struct Slot<T> {
+    /// Whether `value` contains an initialized element.
+    present: AtomicBool,
+    value: UnsafeCell<MaybeUninit<T>>,
+}
+
+/// A stable vector which allows pushing elements concurrently from many
+/// threads via a shared reference.
+///
+/// Elements are stored in segments of exponentially growing size. A segment
+/// is never moved or deallocated while the vector lives, thus pushing (even
+/// concurrently) never invalidates references to existing elements. Reading
+/// is lock-free as well: [`get()`](#method.get) and [`iter()`](#method.iter)
+/// only perform a few atomic loads.
+///
+/// Removing elements and compacting requires `&mut self`. This guarantees that
+/// no other thread can hold a reference to a removed element.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::ConcurrentStableVec;
+/// use std::thread;
+///
+/// let sv = ConcurrentStableVec::new();
+/// let sv_ref = &sv;
+///
+/// thread::scope(|s| {
+///     for t in 0..4 {
+///         s.spawn(move || {
+///             for i in 0..100 {
+///                 let idx = sv_ref.push(t * 100 + i);
+///                 assert_eq!(sv_ref.get(idx), Some(&(t * 100 + i)));
+///             }
+///         });
+///     }
+/// });
+///
+/// assert_eq!(sv.num_elements(), 400);
+/// ```
+pub struct ConcurrentStableVec<T> {
+    /// Pointers to the segments. Segment `i` has `FIRST_SEGMENT_LEN << i`
+    /// slots. Null pointers represent segments that haven't been allocated
+    /// yet.
+    segments: [AtomicPtr<Slot<T>>; NUM_SEGMENTS],
+
+    /// The next index to be handed out by `push()`.
+    next_index: AtomicUsize,
+
+    /// The number of present elements.
+    used_count: AtomicUsize,
+}
+
+unsafe impl<T: Send> Send for ConcurrentStableVec<T> {}
+unsafe impl<T: Send + Sync> Sync for ConcurrentStableVec<T> {}
+
+impl<T> ConcurrentStableVec<T> {
+    /// Constructs a new, empty `ConcurrentStableVec<T>`.
+    ///
+    /// The vector will not allocate until elements are pushed onto it.
+    pub fn new() -> Self {
+        Self {
+            segments: ::core::array::from_fn(|_| AtomicPtr::new(ptr::null_mut())),
+            next_index: AtomicUsize::new(0),
+            used_count: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends a new element and returns its index. Can be called from many
+    /// threads at the same time.
+    ///
+    /// Indices are handed out in increasing order, but elements pushed
+    /// concurrently by different threads might become visible in a different
+    /// order.
+    pub fn push(&self, elem: T) -> usize {
+        let index = self.next_index.fetch_add(1, Ordering::Relaxed);
+        let slot = self.slot_or_alloc(index);
+
+        // We are the only thread which ever got this index, so nobody else
+        // accesses the value. Readers check `present` first.
+        unsafe {
+            (*slot.value.get()).as_mut_ptr().write(elem);
+        }
+        slot.present.store(true, Ordering::Release);
+        self.used_count.fetch_add(1, Ordering::Relaxed);
+
+        index
+    }
+
+    /// Returns a reference to the element at the given index, or `None` if
+    /// there exists no element at that index.
+    ///
+    /// An element pushed by another thread is visible as soon as that
+    /// thread's `push()` call returned.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.slot(index)
+            .filter(|slot| slot.present.load(Ordering::Acquire))
+            .map(|slot| unsafe { &*(*slot.value.get()).as_ptr() })
+    }
+
+    /// Returns a mutable reference to the element at the given index, or
+    /// `None` if there exists no element at that index.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.slot(index)
+            .filter(|slot| slot.present.load(Ordering::Relaxed))
+            .map(|slot| unsafe { &mut *(*slot.value.get()).as_mut_ptr() })
+    }
+
+    /// Returns `true` if there exists an element at the given index.
+    pub fn has_element_at(&self, index: usize) -> bool {
+        self.get(index).is_some()
+    }
+
+    /// Removes and returns the element at the given index, or `None` if there
+    /// exists no element at that index.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let elem = {
+            let slot = self.slot(index)?;
+            if !slot.present.swap(false, Ordering::Relaxed) {
+                return None;
+            }
+            unsafe { (*slot.value.get()).as_ptr().read() }
+        };
+
+        *self.used_count.get_mut() -= 1;
+        Some(elem)
+    }
+
+    /// Returns the number of existing elements.
+    ///
+    /// While other threads are pushing, the returned value might be outdated
+    /// immediately.
+    pub fn num_elements(&self) -> usize {
+        self.used_count.load(Ordering::Relaxed)
+    }
+
+    /// Returns `true` if there are no existing elements.
+    pub fn is_empty(&self) -> bool {
+        self.num_elements() == 0
+    }
+
+    /// Returns the index that will be returned by the next call to
+    /// [`push()`](#method.push) (if no other thread pushes first).
+    pub fn next_index(&self) -> usize {
+        self.next_index.load(Ordering::Relaxed)
+    }
+
+    /// Returns an iterator over the indices and references of all existing
+    /// elements.
+    ///
+    /// Elements pushed concurrently while iterating might or might not be
+    /// yielded.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            sv: self,
+            pos: 0,
+            end: self.next_index.load(Ordering::Acquire),
+        }
+    }
+
+    /// Rearranges elements such that all existing elements are stored
+    /// contiguously from the beginning, preserving their order.
+    /// **Invalidates indices!**
+    ///
+    /// This is the equivalent of
+    /// [`StableVec::make_compact()`](../struct.StableVecFacade.html#method.make_compact).
+    /// No memory is deallocated.
+    pub fn make_compact(&mut self) {
+        let len = *self.next_index.get_mut();
+        let mut target = 0;
+        for source in 0..len {
+            let moved = match self.slot(source) {
+                Some(slot) if slot.present.load(Ordering::Relaxed) => {
+                    slot.present.store(false, Ordering::Relaxed);
+                    unsafe { (*slot.value.get()).as_ptr().read() }
+                }
+                _ => continue,
+            };
+
+            // `target <= source`, so this slot was allocated already.
+            let slot = self.slot(target).unwrap();
+            unsafe {
+                (*slot.value.get()).as_mut_ptr().write(moved);
+            }
+            slot.present.store(true, Ordering::Relaxed);
+            target += 1;
+        }
+
+        *self.next_index.get_mut() = target;
+    }
+
+    /// Converts this vector into a `StableVec<T>` with the same elements at
+    /// the same indices.
+    pub fn into_stable_vec(mut self) -> StableVec<T> {
+        let len = *self.next_index.get_mut();
+        let mut out = StableVec::with_capacity(len);
+        for i in 0..len {
+            match self.remove(i) {
+                Some(elem) => { out.push(elem); }
+                None => out.push_hole(),
+            }
+        }
+        out
+    }
+
+    /// Returns the slot for the given index if its segment is allocated.
+    fn slot(&self, index: usize) -> Option<&Slot<T>> {
+        if index >= self.next_index.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let (segment, offset) = locate(index);
+        let ptr = self.segments[segment].load(Ordering::Acquire);
+        if ptr.is_null() {
+            None
+        } else {
+            Some(unsafe { &*ptr.add(offset) })
+        }
+    }
+
+    /// Returns the slot for the given index, allocating its segment if
+    /// necessary.
+    fn slot_or_alloc(&self, index: usize) -> &Slot<T> {
+        let (segment, offset) = locate(index);
+        let mut ptr = self.segments[segment].load(Ordering::Acquire);
+
+        if ptr.is_null() {
+            let new = alloc_segment::<T>(segment_len(segment));
+            match self.segments[segment].compare_exchange(
+                ptr::null_mut(),
+                new,
+                Ordering::AcqRel,
+                Ordering::Acquire,
+            ) {
+                Ok(_) => ptr = new,
+                Err(existing) => {
+                    // Another thread was faster. We free our segment.
+                    unsafe { free_segment(new, segment_len(segment)) };
+                    ptr = existing;
+                }
+            }
+        }
+
+        unsafe { &*ptr.add(offset) }
+    }
+}
+
+impl<T> Drop for ConcurrentStableVec<T> {
+    fn drop(&mut self) {
+        let len = *self.next_index.get_mut();
+        for i in 0..len {
+            self.remove(i);
+        }
+
+        for (i, segment) in self.segments.iter_mut().enumerate() {
+            let ptr = *segment.get_mut();
+            if !ptr.is_null() {
+                unsafe { free_segment(ptr, segment_len(i)) };
+            }
+        }
+    }
+}
+
+impl<T> Default for ConcurrentStableVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ConcurrentStableVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "ConcurrentStableVec ")?;
+        f.debug_list().entries(self.iter().map(|(_, e)| e)).finish()
+    }
+}
+
+/// Iterator over the indices and references of all existing elements of a
+/// `ConcurrentStableVec`.
+///
+/// Use the method [`ConcurrentStableVec::iter()`](struct.ConcurrentStableVec.html#method.iter)
+/// to obtain an iterator of this kind.
+pub struct Iter<'a, T: 'a> {
+    sv: &'a ConcurrentStableVec<T>,
+    pos: usize,
+    end: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = (usize, &'a T);
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.pos < self.end {
+            self.pos += 1;
+            if let Some(elem) = self.sv.get(self.pos - 1) {
+                return Some((self.pos - 1, elem));
+            }
+        }
+        None
+    }
+}
+
+
+/// Returns the segment and the offset within that segment for an index.
+fn locate(index: usize) -> (usize, usize) {
+    let shifted = index.checked_add(FIRST_SEGMENT_LEN).expect("index overflow");
+    let bit = usize::BITS - 1 - shifted.leading_zeros();
+    ((bit - FIRST_SEGMENT_BITS) as usize, shifted - (1 << bit))
+}
+
+/// Returns the number of slots in the given segment.
+fn segment_len(segment: usize) -> usize {
+    FIRST_SEGMENT_LEN << segment
+}
+
+fn alloc_segment<T>(len: usize) -> *mut Slot<T> {
+    let slots: Box<[Slot<T>]> = (0..len)
+        .map(|_| Slot {
+            present: AtomicBool::new(false),
+            value: UnsafeCell::new(MaybeUninit::uninit()),
+        })
+        .collect();
+    Box::into_raw(slots) as *mut Slot<T>
+}
+
+/// Frees a segment allocated by `alloc_segment()`. Doesn't drop any elements.
+unsafe fn free_segment<T>(ptr: *mut Slot<T>, len: usize) {
+    drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, len)));
+}
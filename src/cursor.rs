@@ -0,0 +1,137 @@
+//! A cursor for modifying a stable vector while walking over it.
+//!
+//! A [`CursorMut`](struct.CursorMut.html) points to one element of a stable
+//! vector (or to a "ghost" position before the first and after the last
+//! element). It can be moved in both directions and can remove or replace
+//! the element it points to, or push new elements, without being
+//! invalidated: as indices are stable, the cursor simply remembers the index
+//! of the current element.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! let mut sv = StableVec::from(&[1, 2, 3, 4, 5, 6]);
+//!
+//! let mut cursor = sv.cursor_mut();
+//! while let Some(&mut e) = cursor.current() {
+//!     if e % 2 == 0 {
+//!         cursor.remove_current();
+//!     } else {
+//!         assert_eq!(cursor.replace_current(e * 10), Ok(e));
+//!         cursor.move_next();
+//!     }
+//! }
+//!
+//! assert_eq!(sv, &[10, 30, 50] as &[_]);
+//! assert_eq!(sv.keys().collect::<Vec<_>>(), vec![0, 2, 4]);
+//! ```
+
+use core::mem;
+
+use StableVecFacade;
+use storage::{BitmapStorage, Storage};
+
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Returns a cursor pointing to the first element (or to the ghost
+    /// position if there are no elements). See
+    /// [`CursorMut`](cursor/struct.CursorMut.html).
+    pub fn cursor_mut(&mut self) -> CursorMut<'_, T, S> {
+        let current = self.storage.next_occupied(0);
+        CursorMut { sv: self, current }
+    }
+
+    /// Returns a cursor pointing to the element at the given index, or to the
+    /// ghost position if there is no element at that index.
+    pub fn cursor_mut_at(&mut self, index: usize) -> CursorMut<'_, T, S> {
+        let current = if self.has_element_at(index) { Some(index) } else { None };
+        CursorMut { sv: self, current }
+    }
+}
+
+/// A cursor over a stable vector which can remove, replace and push
+/// elements.
+///
+/// The cursor points either to an element or to the *ghost* position, which
+/// is before the first and after the last element. Moving forward from the
+/// last element or backward from the first one gets to the ghost position;
+/// moving from the ghost position gets to the first or the last element,
+/// respectively. See [the module documentation](index.html) for more
+/// information.
+///
+/// Created by
+/// [`StableVec::cursor_mut()`](../struct.StableVecFacade.html#method.cursor_mut).
+pub struct CursorMut<'a, T: 'a, S: 'a + Storage<T> = BitmapStorage<T>> {
+    sv: &'a mut StableVecFacade<T, S>,
+
+    /// The index of the current element, or `None` for the ghost position.
+    current: Option<usize>,
+}
+
+impl<'a, T, S: Storage<T>> CursorMut<'a, T, S> {
+    /// Returns the index of the current element, or `None` at the ghost
+    /// position.
+    pub fn index(&self) -> Option<usize> {
+        self.current
+    }
+
+    /// Returns a mutable reference to the current element, or `None` at the
+    /// ghost position.
+    pub fn current(&mut self) -> Option<&mut T> {
+        match self.current {
+            Some(i) => self.sv.get_mut(i),
+            None => None,
+        }
+    }
+
+    /// Moves the cursor to the next element (or to the ghost position).
+    pub fn move_next(&mut self) {
+        let from = match self.current {
+            Some(i) => i + 1,
+            None => 0,
+        };
+        self.current = self.sv.storage.next_occupied(from);
+    }
+
+    /// Moves the cursor to the previous element (or to the ghost position).
+    pub fn move_prev(&mut self) {
+        let before = self.current.unwrap_or_else(|| self.sv.next_index());
+        self.current = (0..before).rev().find(|&i| self.sv.has_element_at(i));
+    }
+
+    /// Removes and returns the current element and moves the cursor to the
+    /// next element. Returns `None` and does nothing at the ghost position.
+    pub fn remove_current(&mut self) -> Option<T> {
+        let index = self.current?;
+        let elem = self.sv.remove(index);
+        self.move_next();
+        elem
+    }
+
+    /// Replaces the current element with `elem` and returns the old element.
+    /// At the ghost position, `elem` is returned as `Err` instead.
+    pub fn replace_current(&mut self, elem: T) -> Result<T, T> {
+        match self.current() {
+            Some(current) => Ok(mem::replace(current, elem)),
+            None => Err(elem),
+        }
+    }
+
+    /// Inserts a new element with
+    /// [`StableVec::push()`](../struct.StableVecFacade.html#method.push) and
+    /// returns its index. The cursor is not moved.
+    ///
+    /// Depending on the push policy, the new element might be inserted
+    /// into a hole before or after the current element, so it may or may not
+    /// be visited when moving the cursor forward.
+    pub fn insert(&mut self, elem: T) -> usize {
+        self.sv.push(elem)
+    }
+
+    /// Returns the underlying stable vector, e.g. to look at other
+    /// elements.
+    pub fn as_stable_vec(&self) -> &StableVecFacade<T, S> {
+        self.sv
+    }
+}
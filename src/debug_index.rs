@@ -0,0 +1,205 @@
+//! Indices which are checked for validity in debug builds.
+//!
+//! Both index types in this module are checked only in builds with debug
+//! assertions. In release builds, they are just a `usize` and nothing is
+//! checked.
+//!
+//! # `VersionedIndex`
+//!
+//! Compacting a stable vector invalidates indices, but a plain `usize` can't
+//! tell whether it was obtained before or after the last compaction. A
+//! [`VersionedIndex`](struct.VersionedIndex.html) can: in builds with debug
+//! assertions, it records the number of compactions at the time it was
+//! created, and accessing the stable vector with it panics if it was created
+//! before the last compaction. Sorting counts as a compaction, too.
+//!
+//! ```should_panic
+//! # use stable_vec::StableVec;
+//! let mut sv = StableVec::from(&['a', 'b', 'c']);
+//! sv.remove(0);
+//! let c = sv.versioned_index(2);
+//! assert_eq!(sv[c], 'c');
+//!
+//! sv.make_compact();
+//! sv[c]; // panics in debug builds: `c` was created before `make_compact()`
+//! # #[cfg(not(debug_assertions))] panic!();
+//! ```
+//!
+//! # `TaggedIndex`
+//!
+//! An index of one stable vector is meaningless for another one. A
+//! [`TaggedIndex`](struct.TaggedIndex.html) records which stable vector it
+//! was created for (every instance has a unique ID in debug builds), and
+//! accessing another stable vector with it panics.
+//!
+//! ```should_panic
+//! # use stable_vec::StableVec;
+//! let mut a = StableVec::new();
+//! let b = StableVec::from(&['x', 'y']);
+//!
+//! let i = a.push('a');
+//! let i = a.tagged_index(i);
+//! assert_eq!(a[i], 'a');
+//! b[i]; // panics in debug builds: `i` belongs to `a`
+//! # #[cfg(not(debug_assertions))] panic!();
+//! ```
+
+use core::ops::{Index, IndexMut};
+#[cfg(debug_assertions)]
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use StableVecFacade;
+use storage::Storage;
+
+
+/// An index which remembers when it was created, so that using it after the
+/// stable vector was compacted is detected in debug builds.
+///
+/// Created by
+/// [`StableVec::versioned_index()`](../struct.StableVecFacade.html#method.versioned_index).
+/// See [the module documentation](index.html) for more information.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct VersionedIndex {
+    index: usize,
+
+    /// The number of compactions of the stable vector when this index was
+    /// created.
+    #[cfg(debug_assertions)]
+    compactions: usize,
+}
+
+impl VersionedIndex {
+    /// Returns the plain index, without any checks.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+/// An index which remembers the stable vector it was created for, so that
+/// using it with another stable vector is detected in debug builds.
+///
+/// Created by
+/// [`StableVec::tagged_index()`](../struct.StableVecFacade.html#method.tagged_index).
+/// See [the module documentation](index.html) for more information.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct TaggedIndex {
+    index: usize,
+
+    /// The ID of the stable vector this index was created for.
+    #[cfg(debug_assertions)]
+    owner: usize,
+}
+
+impl TaggedIndex {
+    /// Returns the plain index, without any checks.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+}
+
+/// Returns a new, unique ID for a stable vector instance.
+#[cfg(debug_assertions)]
+pub(crate) fn next_instance_id() -> usize {
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Wraps the given index in a [`VersionedIndex`](debug_index/struct.VersionedIndex.html),
+    /// which remembers the current number of compactions in debug builds.
+    pub fn versioned_index(&self, index: usize) -> VersionedIndex {
+        VersionedIndex {
+            index,
+            #[cfg(debug_assertions)]
+            compactions: self.compactions,
+        }
+    }
+
+    /// Returns the plain index of the given versioned index, to be passed to
+    /// any other method.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if `index` was created before the last
+    /// compaction (via [`make_compact()`](#method.make_compact) or
+    /// [`reordering_make_compact()`](#method.reordering_make_compact)).
+    pub fn resolve(&self, index: VersionedIndex) -> usize {
+        #[cfg(debug_assertions)]
+        {
+            assert!(
+                index.compactions == self.compactions,
+                "stale VersionedIndex: index {} was created before the stable vector was \
+                    compacted (after {} compactions, now {})",
+                index.index,
+                index.compactions,
+                self.compactions,
+            );
+        }
+
+        index.index
+    }
+
+    /// Wraps the given index in a [`TaggedIndex`](debug_index/struct.TaggedIndex.html),
+    /// which remembers this stable vector in debug builds.
+    ///
+    /// Every stable vector (including clones) is a different instance.
+    pub fn tagged_index(&self, index: usize) -> TaggedIndex {
+        TaggedIndex {
+            index,
+            #[cfg(debug_assertions)]
+            owner: self.id,
+        }
+    }
+
+    /// Returns the plain index of the given tagged index, to be passed to any
+    /// other method.
+    ///
+    /// # Panics
+    ///
+    /// In debug builds, panics if `index` was created for another stable
+    /// vector.
+    pub fn resolve_tagged(&self, index: TaggedIndex) -> usize {
+        #[cfg(debug_assertions)]
+        {
+            assert!(
+                index.owner == self.id,
+                "TaggedIndex {} belongs to another stable vector (instance #{}, this is #{})",
+                index.index,
+                index.owner,
+                self.id,
+            );
+        }
+
+        index.index
+    }
+}
+
+impl<T, S: Storage<T>> Index<VersionedIndex> for StableVecFacade<T, S> {
+    type Output = T;
+
+    fn index(&self, index: VersionedIndex) -> &T {
+        &self[self.resolve(index)]
+    }
+}
+
+impl<T, S: Storage<T>> IndexMut<VersionedIndex> for StableVecFacade<T, S> {
+    fn index_mut(&mut self, index: VersionedIndex) -> &mut T {
+        let index = self.resolve(index);
+        &mut self[index]
+    }
+}
+
+impl<T, S: Storage<T>> Index<TaggedIndex> for StableVecFacade<T, S> {
+    type Output = T;
+
+    fn index(&self, index: TaggedIndex) -> &T {
+        &self[self.resolve_tagged(index)]
+    }
+}
+
+impl<T, S: Storage<T>> IndexMut<TaggedIndex> for StableVecFacade<T, S> {
+    fn index_mut(&mut self, index: TaggedIndex) -> &mut T {
+        let index = self.resolve_tagged(index);
+        &mut self[index]
+    }
+}
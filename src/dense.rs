@@ -0,0 +1,233 @@
+//! A stable vector with a synchronized dense array of its elements.
+//!
+//! See [`DenseMirror`](struct.DenseMirror.html) for more information.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::Index;
+
+use StableVec;
+use secondary::SecondaryMap;
+
+
+/// A stable vector which additionally keeps a dense `Vec<U>` with one value
+/// per existing element, derived from the element by a function.
+///
+/// This combines stable indices with cache-friendly iteration: hand out the
+/// stable indices as handles and iterate over [`dense()`](#method.dense) in
+/// hot loops. Translating between stable indices and positions in the dense
+/// array is O(1) in both directions. Removing an element moves the last
+/// dense value into the gap (like `Vec::swap_remove()`), so the dense array
+/// isn't in index order.
+///
+/// The function deriving the dense values can be any `Fn(&T) -> U`, e.g. a
+/// closure capturing a lookup table. Use `DenseMirror::new(T::clone)` to
+/// mirror the elements themselves. The dense value of an element is derived
+/// again whenever the element is changed via [`update()`](#method.update).
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::DenseMirror;
+///
+/// let mut names = DenseMirror::new(|s: &String| s.len());
+/// let alice = names.push(String::from("Alice"));
+/// let bob = names.push(String::from("Bob"));
+/// names.push(String::from("Carol"));
+///
+/// names.remove(alice);
+/// assert_eq!(names.dense(), &[5, 3]);
+///
+/// let pos = names.dense_index(bob).unwrap();
+/// assert_eq!(names.dense()[pos], 3);
+/// assert_eq!(names.stable_index(pos), Some(bob));
+///
+/// names.update(bob, |s| s.push_str("by"));
+/// assert_eq!(names.dense()[pos], 5);
+/// ```
+pub struct DenseMirror<T, U = T, F = fn(&T) -> U> {
+    inner: StableVec<T>,
+
+    /// The derived values, one per existing element.
+    dense: Vec<U>,
+
+    /// The stable index of each dense value.
+    stable_indices: Vec<usize>,
+
+    /// The position in `dense` of each existing element.
+    dense_indices: SecondaryMap<usize>,
+
+    derive: F,
+}
+
+impl<T, U, F> DenseMirror<T, U, F>
+    where F: Fn(&T) -> U,
+{
+    /// Constructs a new, empty `DenseMirror` which derives the dense values
+    /// with the given function.
+    pub fn new(derive: F) -> Self {
+        Self::from_stable_vec(StableVec::new(), derive)
+    }
+
+    /// Wraps the given stable vector, deriving a dense value for each of its
+    /// elements. This is O(n).
+    pub fn from_stable_vec(sv: StableVec<T>, derive: F) -> Self {
+        let mut out = Self {
+            dense: Vec::with_capacity(sv.num_elements()),
+            stable_indices: Vec::with_capacity(sv.num_elements()),
+            dense_indices: SecondaryMap::with_capacity(sv.next_index()),
+            inner: sv,
+            derive,
+        };
+        for i in out.inner.keys() {
+            out.dense_indices.insert(i, out.dense.len());
+            out.dense.push((out.derive)(&out.inner[i]));
+            out.stable_indices.push(i);
+        }
+        out
+    }
+
+    /// Returns the underlying stable vector.
+    pub fn as_stable_vec(&self) -> &StableVec<T> {
+        &self.inner
+    }
+
+    /// Unwraps the underlying stable vector, dropping the dense values.
+    pub fn into_stable_vec(self) -> StableVec<T> {
+        self.inner
+    }
+
+    /// Inserts a new element, appends its derived value to the dense array
+    /// and returns the element's stable index. See
+    /// [`StableVec::push()`](../struct.StableVecFacade.html#method.push).
+    pub fn push(&mut self, elem: T) -> usize {
+        let index = self.inner.push(elem);
+        self.dense_indices.insert(index, self.dense.len());
+        self.dense.push((self.derive)(&self.inner[index]));
+        self.stable_indices.push(index);
+        index
+    }
+
+    /// Removes and returns the element at the given index, or returns `None`
+    /// if there exists no element at that index. Its dense value is replaced
+    /// by the last one. This is O(1).
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let elem = self.inner.remove(index)?;
+        let pos = self.dense_indices.remove(index).unwrap();
+        self.dense.swap_remove(pos);
+        self.stable_indices.swap_remove(pos);
+        if let Some(&moved) = self.stable_indices.get(pos) {
+            self.dense_indices[moved] = pos;
+        }
+        Some(elem)
+    }
+
+    /// Calls `f` with the element at the given index and derives its dense
+    /// value again. Returns `false` (without calling `f`) if there exists no
+    /// element at that index.
+    pub fn update<G>(&mut self, index: usize, f: G) -> bool
+        where G: FnOnce(&mut T),
+    {
+        match self.inner.get_mut(index) {
+            Some(elem) => {
+                f(elem);
+                let pos = self.dense_indices[index];
+                self.dense[pos] = (self.derive)(&self.inner[index]);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns a reference to the element at the given index, or `None` if
+    /// there exists no element at that index.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.inner.get(index)
+    }
+
+    /// Returns the dense values. The value at position `i` belongs to the
+    /// element at [`stable_index(i)`](#method.stable_index).
+    pub fn dense(&self) -> &[U] {
+        &self.dense
+    }
+
+    /// Returns the dense values mutably. Changes are overwritten when the
+    /// element is changed via [`update()`](#method.update).
+    pub fn dense_mut(&mut self) -> &mut [U] {
+        &mut self.dense
+    }
+
+    /// Returns the stable index of each dense value, i.e. a slice parallel
+    /// to [`dense()`](#method.dense).
+    pub fn stable_indices(&self) -> &[usize] {
+        &self.stable_indices
+    }
+
+    /// Returns the position of the dense value of the element at the given
+    /// stable index, or `None` if there exists no element at that index.
+    pub fn dense_index(&self, index: usize) -> Option<usize> {
+        if self.inner.has_element_at(index) {
+            self.dense_indices.get(index).cloned()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the stable index of the element whose dense value is at the
+    /// given position, or `None` if `pos` is out of bounds.
+    pub fn stable_index(&self, pos: usize) -> Option<usize> {
+        self.stable_indices.get(pos).cloned()
+    }
+
+    /// Returns `true` if there exists an element at the given index.
+    pub fn has_element_at(&self, index: usize) -> bool {
+        self.inner.has_element_at(index)
+    }
+
+    /// Returns the number of existing elements, which is also the length of
+    /// the dense array.
+    pub fn num_elements(&self) -> usize {
+        self.dense.len()
+    }
+
+    /// Returns `true` if there are no existing elements.
+    pub fn is_empty(&self) -> bool {
+        self.dense.is_empty()
+    }
+
+    /// Returns the number of slots (elements and holes) of the stable
+    /// vector. See
+    /// [`StableVec::next_index()`](../struct.StableVecFacade.html#method.next_index).
+    pub fn next_index(&self) -> usize {
+        self.inner.next_index()
+    }
+}
+
+impl<T, U, F> Index<usize> for DenseMirror<T, U, F> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.inner[index]
+    }
+}
+
+impl<T: Clone, U: Clone, F: Clone> Clone for DenseMirror<T, U, F> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            dense: self.dense.clone(),
+            stable_indices: self.stable_indices.clone(),
+            dense_indices: self.dense_indices.clone(),
+            derive: self.derive.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug, U: fmt::Debug, F> fmt::Debug for DenseMirror<T, U, F> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("DenseMirror")
+            .field("elements", &self.inner)
+            .field("dense", &self.dense)
+            .finish()
+    }
+}
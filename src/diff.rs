@@ -0,0 +1,114 @@
+//! Comparing two stable vectors index by index.
+//!
+//! [`StableVec::diff()`](../struct.StableVecFacade.html#method.diff) yields
+//! one [`Difference`](enum.Difference.html) for each index at which the two
+//! stable vectors don't agree. Unlike a [`Patch`](../patch/struct.Patch.html),
+//! this doesn't clone any elements, so it's cheap to use in tests or to
+//! reconcile two states.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! use stable_vec::diff::Difference;
+//!
+//! let old = StableVec::from(&['a', 'b', 'c']);
+//! let mut new = old.clone();
+//! new.remove(0);
+//! new[1] = 'x';
+//! new.push('d');
+//!
+//! let diff = old.diff(&new).collect::<Vec<_>>();
+//! assert_eq!(diff, vec![
+//!     (0, Difference::Removed(&'a')),
+//!     (1, Difference::Changed { old: &'b', new: &'x' }),
+//!     (3, Difference::Added(&'d')),
+//! ]);
+//! ```
+
+use StableVecFacade;
+use storage::Storage;
+
+
+/// How a single index differs between two stable vectors.
+///
+/// Yielded by [`Diff`](struct.Diff.html).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Difference<'a, T: 'a> {
+    /// Only the other stable vector has an element at this index.
+    Added(&'a T),
+
+    /// Only this stable vector has an element at this index.
+    Removed(&'a T),
+
+    /// Both stable vectors have an element at this index, but they are not
+    /// equal.
+    Changed {
+        /// The element of this stable vector.
+        old: &'a T,
+
+        /// The element of the other stable vector.
+        new: &'a T,
+    },
+}
+
+impl<T: PartialEq, S: Storage<T>> StableVecFacade<T, S> {
+    /// Returns an iterator over `(index, difference)` for all indices at
+    /// which `self` and `other` differ, in ascending order. Indices at which
+    /// both have equal elements or both have a hole are skipped.
+    ///
+    /// `self` is treated as the old state and `other` as the new one. The
+    /// iterator only visits occupied slots, so it runs in O(n) where n is
+    /// the larger of the two `next_index()` values. See [the `diff`
+    /// module](diff/index.html).
+    pub fn diff<'a, S2>(&'a self, other: &'a StableVecFacade<T, S2>) -> Diff<'a, T, S, S2>
+        where S2: Storage<T>,
+    {
+        Diff {
+            old: self,
+            new: other,
+            pos: 0,
+        }
+    }
+}
+
+/// Iterator over the differences between two stable vectors.
+///
+/// Use the method
+/// [`StableVec::diff()`](../struct.StableVecFacade.html#method.diff)
+/// to obtain an iterator of this kind.
+pub struct Diff<'a, T: 'a, SA: 'a + Storage<T>, SB: 'a + Storage<T>> {
+    old: &'a StableVecFacade<T, SA>,
+    new: &'a StableVecFacade<T, SB>,
+    pos: usize,
+}
+
+impl<'a, T, SA, SB> Iterator for Diff<'a, T, SA, SB>
+    where T: PartialEq,
+          SA: Storage<T>,
+          SB: Storage<T>,
+{
+    type Item = (usize, Difference<'a, T>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let i = match (
+                self.old.storage.next_occupied(self.pos),
+                self.new.storage.next_occupied(self.pos),
+            ) {
+                (None, None) => return None,
+                (Some(a), Some(b)) => a.min(b),
+                (Some(i), None) | (None, Some(i)) => i,
+            };
+            self.pos = i + 1;
+
+            let difference = match (self.old.get(i), self.new.get(i)) {
+                (Some(old), Some(new)) if old != new => Difference::Changed { old, new },
+                (Some(old), None) => Difference::Removed(old),
+                (None, Some(new)) => Difference::Added(new),
+                _ => continue,
+            };
+            return Some((i, difference));
+        }
+    }
+}
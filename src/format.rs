@@ -0,0 +1,32 @@
+//! `defmt::Format` impls (requires the `defmt` feature).
+//!
+//! The stable vectors are formatted like their `Debug` output, i.e. the
+//! type name followed by the list of existing elements, but without pulling
+//! in `core::fmt`.
+
+use defmt::{Format, Formatter};
+
+use StableVecFacade;
+use storage::Storage;
+
+
+impl<T: Format, S: Storage<T>> Format for StableVecFacade<T, S> {
+    fn format(&self, f: Formatter) {
+        format_list(f, "StableVec", self.iter());
+    }
+}
+
+/// Writes `name [a, b, ...]`.
+fn format_list<'a, T, I>(f: Formatter, name: &str, elems: I)
+    where T: 'a + Format,
+          I: Iterator<Item = &'a T>,
+{
+    defmt::write!(f, "{=str} [", name);
+    for (i, elem) in elems.enumerate() {
+        if i > 0 {
+            defmt::write!(f, ", ");
+        }
+        defmt::write!(f, "{}", elem);
+    }
+    defmt::write!(f, "]");
+}
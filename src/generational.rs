@@ -0,0 +1,304 @@
+//! A stable vector whose keys detect reuse of their slot.
+//!
+//! See [`GenStableVec`](struct.GenStableVec.html) for more information.
+//!
+//! # Handles
+//!
+//! The keys of a `GenStableVec` are plain values without lifetimes, so they
+//! can be stored in long-lived structs. All accesses are validated at
+//! runtime: a key only works while its element exists. The aliases
+//! [`HandleMap`](type.HandleMap.html) and [`Handle`](type.Handle.html) name
+//! the types after this use case.
+//!
+//! ```
+//! use stable_vec::{Handle, HandleMap};
+//!
+//! struct Player {
+//!     name: &'static str,
+//!     target: Option<Handle>,
+//! }
+//!
+//! let mut players = HandleMap::new();
+//! let alice = players.push(Player { name: "Alice", target: None });
+//! let bob = players.push(Player { name: "Bob", target: Some(alice) });
+//!
+//! players.remove(alice);
+//! players.push(Player { name: "Carol", target: None }); // reuses Alice's slot
+//!
+//! // Bob's target is gone, even though its slot is in use again.
+//! let target = players[bob].target.unwrap();
+//! assert!(players.get(target).is_none());
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::ops::{Index, IndexMut};
+
+use {Iter, IterMut, Keys, PushPolicy, StableVec};
+
+
+/// A key of a [`GenStableVec`](struct.GenStableVec.html): an index plus the
+/// generation of its slot at the time the element was inserted.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+pub struct GenKey {
+    index: usize,
+    generation: u32,
+}
+
+/// An opaque handle to an element of a [`HandleMap`](type.HandleMap.html).
+pub type Handle = GenKey;
+
+impl GenKey {
+    /// Returns the index of the slot this key refers to.
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Returns the generation of the slot this key was created for.
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+/// A stable vector which reuses holes and whose keys carry a per-slot
+/// generation counter.
+///
+/// Every slot has a generation which is incremented whenever its element is
+/// removed. A [`GenKey`](struct.GenKey.html) stores the generation of its
+/// slot at the time of insertion, so a key of a removed element doesn't refer
+/// to the element which was later inserted into the same slot. Instead, all
+/// methods treat such a stale key like a key of a removed element. This
+/// makes it safe to store keys long-term (e.g. as entity handles) although
+/// slots are reused.
+///
+/// [`push()`](#method.push) always fills the most recently created hole
+/// first (see [`PushPolicy::PreferHoles`](../enum.PushPolicy.html)). The
+/// generation counters are 32 bits wide and wrap around: a key becomes valid
+/// again after its slot was reused 2<sup>32</sup> times.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::GenStableVec;
+///
+/// let mut sv = GenStableVec::new();
+/// let a = sv.push('a');
+/// sv.remove(a);
+///
+/// // The slot of `a` is reused, but `a` doesn't refer to the new element.
+/// let b = sv.push('b');
+/// assert_eq!(a.index(), b.index());
+/// assert_eq!(sv.get(a), None);
+/// assert_eq!(sv.get(b), Some(&'b'));
+/// ```
+pub struct GenStableVec<T> {
+    inner: StableVec<T>,
+
+    /// The current generation of each slot of `inner`.
+    generations: Vec<u32>,
+}
+
+/// A map which hands out [`Handle`](type.Handle.html)s to its elements and
+/// validates them at runtime. See [the module documentation](index.html#handles).
+pub type HandleMap<T> = GenStableVec<T>;
+
+impl<T> GenStableVec<T> {
+    /// Constructs a new, empty `GenStableVec<T>`.
+    pub fn new() -> Self {
+        Self {
+            inner: StableVec::with_push_policy(PushPolicy::PreferHoles),
+            generations: Vec::new(),
+        }
+    }
+
+    /// Constructs a new, empty `GenStableVec<T>` with the specified capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let mut out = Self::new();
+        out.reserve(capacity);
+        out
+    }
+
+    /// Reserves capacity for at least `additional` more elements to be
+    /// inserted.
+    pub fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+        self.generations.reserve(additional);
+    }
+
+    /// Inserts a new element, reusing a hole if possible, and returns its
+    /// key.
+    pub fn push(&mut self, elem: T) -> GenKey {
+        let index = self.inner.push(elem);
+        if index == self.generations.len() {
+            self.generations.push(0);
+        }
+
+        GenKey {
+            index,
+            generation: self.generations[index],
+        }
+    }
+
+    /// Removes and returns the element with the given key, or `None` if there
+    /// exists no such element (or if the key is stale).
+    ///
+    /// This invalidates all keys referring to this slot.
+    pub fn remove(&mut self, key: GenKey) -> Option<T> {
+        if !self.contains_key(key) {
+            return None;
+        }
+
+        self.remove_at(key.index)
+    }
+
+    /// Returns a reference to the element with the given key, or `None` if
+    /// there exists no such element (or if the key is stale).
+    pub fn get(&self, key: GenKey) -> Option<&T> {
+        if self.contains_key(key) {
+            self.inner.get(key.index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns a mutable reference to the element with the given key, or
+    /// `None` if there exists no such element (or if the key is stale).
+    pub fn get_mut(&mut self, key: GenKey) -> Option<&mut T> {
+        if self.contains_key(key) {
+            self.inner.get_mut(key.index)
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the key refers to an existing element.
+    pub fn contains_key(&self, key: GenKey) -> bool {
+        self.inner.has_element_at(key.index)
+            && self.generations[key.index] == key.generation
+    }
+
+    /// Returns the key of the element currently stored at the given index, or
+    /// `None` if there exists no element at that index.
+    pub fn key_at(&self, index: usize) -> Option<GenKey> {
+        if self.inner.has_element_at(index) {
+            Some(GenKey {
+                index,
+                generation: self.generations[index],
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the number of existing elements.
+    pub fn num_elements(&self) -> usize {
+        self.inner.num_elements()
+    }
+
+    /// Returns `true` if there are no existing elements.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns an iterator over immutable references to the existing
+    /// elements.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.inner.iter()
+    }
+
+    /// Returns an iterator over mutable references to the existing elements.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        self.inner.iter_mut()
+    }
+
+    /// Returns an iterator over the keys of all existing elements.
+    pub fn keys(&self) -> GenKeys<'_, T> {
+        GenKeys {
+            inner: self.inner.keys(),
+            generations: &self.generations,
+        }
+    }
+
+    /// Retains only the elements specified by the given predicate. All keys
+    /// of removed elements become stale.
+    pub fn retain<P>(&mut self, mut predicate: P)
+        where P: FnMut(&T) -> bool,
+    {
+        for i in 0..self.inner.next_index() {
+            let keep = match self.inner.get(i) {
+                Some(e) => predicate(e),
+                None => true,
+            };
+            if !keep {
+                self.remove_at(i);
+            }
+        }
+    }
+
+    /// Removes the element at the given index (if any) and increments the
+    /// generation of its slot.
+    fn remove_at(&mut self, index: usize) -> Option<T> {
+        let elem = self.inner.remove(index);
+        if elem.is_some() {
+            self.generations[index] = self.generations[index].wrapping_add(1);
+        }
+        elem
+    }
+}
+
+impl<T> Index<GenKey> for GenStableVec<T> {
+    type Output = T;
+
+    fn index(&self, key: GenKey) -> &T {
+        assert!(self.contains_key(key));
+        &self.inner[key.index]
+    }
+}
+
+impl<T> IndexMut<GenKey> for GenStableVec<T> {
+    fn index_mut(&mut self, key: GenKey) -> &mut T {
+        assert!(self.contains_key(key));
+        &mut self.inner[key.index]
+    }
+}
+
+impl<T> Default for GenStableVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Clone for GenStableVec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            generations: self.generations.clone(),
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for GenStableVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "GenStableVec ")?;
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over the keys of all existing elements of a `GenStableVec`.
+///
+/// Use the method [`GenStableVec::keys()`](struct.GenStableVec.html#method.keys)
+/// to obtain an iterator of this kind.
+pub struct GenKeys<'a, T: 'a> {
+    inner: Keys<'a, T>,
+    generations: &'a [u32],
+}
+
+impl<'a, T> Iterator for GenKeys<'a, T> {
+    type Item = GenKey;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|index| GenKey {
+            index,
+            generation: self.generations[index],
+        })
+    }
+}
@@ -0,0 +1,31 @@
+//! A stable vector with `u32` indices.
+//!
+//! See [`StableVec32`](type.StableVec32.html) for more information.
+
+use typed::TypedStableVec;
+
+
+/// A stable vector whose indices are `u32` instead of `usize`.
+///
+/// If you store lots of indices in side tables, this halves their memory
+/// usage on 64 bit platforms. This is a
+/// [`TypedStableVec`](../typed/struct.TypedStableVec.html) with `u32` keys,
+/// so it stores its elements in exactly the same way as a
+/// [`StableVec<T>`](../type.StableVec.html). It can never contain more than
+/// `u32::MAX + 1` slots: `push()` panics if the new element wouldn't be
+/// addressable by a `u32`.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::index32::StableVec32;
+///
+/// let mut sv = StableVec32::new();
+/// let a: u32 = sv.push('a');
+/// let b = sv.push('b');
+/// sv.remove(a);
+///
+/// assert_eq!(sv[b], 'b');
+/// assert_eq!(sv.keys().collect::<Vec<u32>>(), vec![b]);
+/// ```
+pub type StableVec32<T> = TypedStableVec<T, u32>;
@@ -0,0 +1,218 @@
+//! Looking up the index of a value in O(1) (requires the `std` feature).
+//!
+//! Finding the index of a value with
+//! [`StableVec::contains()`](../struct.StableVecFacade.html#method.contains)
+//! or `iter().position()` is a linear scan.
+//! [`build_value_index()`](../struct.StableVecFacade.html#method.build_value_index)
+//! builds a lookup table once, while
+//! [`IndexedStableVec`](struct.IndexedStableVec.html) maintains one for every
+//! insertion and removal.
+
+use core::fmt;
+use core::iter::FromIterator;
+use core::ops::Index;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+use {Iter, Keys, StableVec, StableVecFacade};
+use storage::Storage;
+
+
+impl<T: Hash + Eq, S: Storage<T>> StableVecFacade<T, S> {
+    /// Returns a map from each existing element to its index. If there are
+    /// equal elements, the smallest index is stored. This is O(n).
+    ///
+    /// The map borrows the elements, so the stable vector can't be mutated
+    /// while the map is alive. Use
+    /// [`IndexedStableVec`](indexed/struct.IndexedStableVec.html) if you
+    /// need to look up values while adding and removing elements.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&["a", "b", "a"]);
+    /// sv.remove(0);
+    ///
+    /// let index = sv.build_value_index();
+    /// assert_eq!(index.get(&"a"), Some(&2));
+    /// assert_eq!(index.get(&"c"), None);
+    /// ```
+    pub fn build_value_index(&self) -> HashMap<&T, usize> {
+        let mut map = HashMap::with_capacity(self.num_elements());
+        for i in self.keys() {
+            map.entry(unsafe { self.storage.get_unchecked(i) }).or_insert(i);
+        }
+        map
+    }
+}
+
+/// A stable vector of distinct values which can look up the index of a
+/// value in O(1).
+///
+/// Inserting a value which is already contained returns the index of the
+/// existing element instead of adding a duplicate, which makes this useful
+/// to intern values. Each value is stored twice: in the stable vector and as
+/// key of a `HashMap` from values to indices. Since modifying a value would
+/// invalidate that map, there is no mutable access to the values.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::IndexedStableVec;
+///
+/// let mut names = IndexedStableVec::new();
+/// let alice = names.insert("Alice");
+/// let bob = names.insert("Bob");
+/// assert_eq!(names.insert("Alice"), alice);
+/// assert_eq!(names.num_elements(), 2);
+///
+/// assert_eq!(names.index_of(&"Bob"), Some(bob));
+/// names.remove(bob);
+/// assert_eq!(names.index_of(&"Bob"), None);
+/// ```
+pub struct IndexedStableVec<T> {
+    inner: StableVec<T>,
+    indices: HashMap<T, usize>,
+}
+
+impl<T: Clone + Hash + Eq> IndexedStableVec<T> {
+    /// Constructs a new, empty `IndexedStableVec<T>`.
+    pub fn new() -> Self {
+        Self {
+            inner: StableVec::new(),
+            indices: HashMap::new(),
+        }
+    }
+
+    /// Returns the index of the element equal to `elem`, inserting `elem`
+    /// first if there is none.
+    pub fn insert(&mut self, elem: T) -> usize {
+        if let Some(&index) = self.indices.get(&elem) {
+            return index;
+        }
+
+        let index = self.inner.push(elem.clone());
+        self.indices.insert(elem, index);
+        index
+    }
+
+    /// Removes and returns the element at the given index, or returns `None`
+    /// if there exists no element at that index.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let elem = self.inner.remove(index)?;
+        self.indices.remove(&elem);
+        Some(elem)
+    }
+
+    /// Returns the index of the element equal to `elem`, or `None` if there
+    /// is none. This is O(1).
+    pub fn index_of(&self, elem: &T) -> Option<usize> {
+        self.indices.get(elem).cloned()
+    }
+
+    /// Returns `true` if an element equal to `elem` exists.
+    pub fn contains(&self, elem: &T) -> bool {
+        self.indices.contains_key(elem)
+    }
+}
+
+impl<T> IndexedStableVec<T> {
+    /// Returns the underlying stable vector.
+    pub fn as_stable_vec(&self) -> &StableVec<T> {
+        &self.inner
+    }
+
+    /// Unwraps the underlying stable vector.
+    pub fn into_stable_vec(self) -> StableVec<T> {
+        self.inner
+    }
+
+    /// Returns a reference to the element at the given index, or `None` if
+    /// there exists no element at that index.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.inner.get(index)
+    }
+
+    /// Returns `true` if there exists an element at the given index.
+    pub fn has_element_at(&self, index: usize) -> bool {
+        self.inner.has_element_at(index)
+    }
+
+    /// Returns the number of existing elements.
+    pub fn num_elements(&self) -> usize {
+        self.inner.num_elements()
+    }
+
+    /// Returns `true` if there are no existing elements.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns the number of slots (elements and holes). See
+    /// [`StableVec::next_index()`](../struct.StableVecFacade.html#method.next_index).
+    pub fn next_index(&self) -> usize {
+        self.inner.next_index()
+    }
+
+    /// Returns an iterator over immutable references to the existing
+    /// elements, in index order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.inner.iter()
+    }
+
+    /// Returns an iterator over the indices of all existing elements.
+    pub fn keys(&self) -> Keys<'_, T> {
+        self.inner.keys()
+    }
+}
+
+impl<T> Index<usize> for IndexedStableVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.inner[index]
+    }
+}
+
+impl<T: Clone + Hash + Eq> Default for IndexedStableVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Clone for IndexedStableVec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            indices: self.indices.clone(),
+        }
+    }
+}
+
+impl<T: Clone + Hash + Eq> FromIterator<T> for IndexedStableVec<T> {
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item = T>,
+    {
+        let mut out = Self::new();
+        out.extend(iter);
+        out
+    }
+}
+
+impl<T: Clone + Hash + Eq> Extend<T> for IndexedStableVec<T> {
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = T>,
+    {
+        for elem in iter {
+            self.insert(elem);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for IndexedStableVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "IndexedStableVec ")?;
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
@@ -0,0 +1,118 @@
+//! Opt-in tracking of changed indices.
+//!
+//! To keep an external copy of a stable vector (e.g. a GPU buffer, a
+//! database table or the state of a network peer) up to date, you need to
+//! know which slots changed. Computing a [`Patch`](../patch/struct.Patch.html)
+//! needs a copy of the old state; a journal doesn't. Once enabled with
+//! [`enable_journal()`](../struct.StableVecFacade.html#method.enable_journal),
+//! the stable vector records the index of every slot which is changed,
+//! until [`take_changes()`](../struct.StableVecFacade.html#method.take_changes)
+//! returns and resets the recorded changes.
+//!
+//! Every method handing out a mutable reference to an element (including
+//! `get_mut()`, the `[]` operator and `iter_mut()`) records a modification,
+//! whether the element is actually changed or not. Changes of the same slot
+//! are combined: e.g. pushing and then modifying an element is recorded as
+//! an insertion, pushing and then removing it is not recorded at all.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! use stable_vec::journal::ChangeKind;
+//!
+//! let mut sv = StableVec::from(&['a', 'b', 'c']);
+//! sv.enable_journal();
+//!
+//! sv.remove(0);
+//! sv[1] = 'x';
+//! let d = sv.push('d');
+//! sv[d] = 'e';
+//! assert_eq!(sv.take_changes(), vec![
+//!     (0, ChangeKind::Removed),
+//!     (1, ChangeKind::Modified),
+//!     (3, ChangeKind::Inserted),
+//! ]);
+//!
+//! assert_eq!(sv.take_changes(), vec![]);
+//! ```
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+
+use StableVecFacade;
+use storage::Storage;
+
+
+/// What happened to a slot, as recorded in the journal of a stable vector.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum ChangeKind {
+    /// The slot contains an element now, but was a hole (or didn't exist).
+    Inserted,
+
+    /// The slot contained an element before and still does, but the element
+    /// might have been changed or replaced.
+    Modified,
+
+    /// The slot contained an element before and is a hole now (or doesn't
+    /// exist anymore because the stable vector was compacted).
+    Removed,
+}
+
+/// The changes recorded since the journal was enabled or last taken.
+pub(crate) type Journal = BTreeMap<usize, ChangeKind>;
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Starts recording changed indices. Does nothing if the journal is
+    /// already enabled. See [the `journal` module](journal/index.html).
+    pub fn enable_journal(&mut self) {
+        if self.journal.is_none() {
+            self.journal = Some(Box::new(Journal::new()));
+        }
+    }
+
+    /// Stops recording changed indices and discards all recorded changes.
+    pub fn disable_journal(&mut self) {
+        self.journal = None;
+    }
+
+    /// Returns `true` if changed indices are being recorded.
+    pub fn is_journal_enabled(&self) -> bool {
+        self.journal.is_some()
+    }
+
+    /// Returns all changes recorded since the journal was enabled or this
+    /// method was last called, sorted by index, and starts a new recording.
+    /// Returns an empty vector if the journal is disabled.
+    pub fn take_changes(&mut self) -> Vec<(usize, ChangeKind)> {
+        match self.journal {
+            Some(ref mut journal) => {
+                let changes = journal.iter().map(|(&i, &kind)| (i, kind)).collect();
+                journal.clear();
+                changes
+            }
+            None => Vec::new(),
+        }
+    }
+
+    /// Records a change of the given slot, if the journal is enabled.
+    pub(crate) fn record(&mut self, index: usize, kind: ChangeKind) {
+        let journal = match self.journal {
+            Some(ref mut journal) => journal,
+            None => return,
+        };
+
+        let combined = match (journal.get(&index).cloned(), kind) {
+            (None, kind) => Some(kind),
+            (Some(ChangeKind::Inserted), ChangeKind::Modified) => Some(ChangeKind::Inserted),
+            (Some(ChangeKind::Inserted), ChangeKind::Removed) => None,
+            (Some(ChangeKind::Removed), ChangeKind::Inserted) => Some(ChangeKind::Modified),
+            (Some(_), kind) => Some(kind),
+        };
+        match combined {
+            Some(kind) => { journal.insert(index, kind); }
+            None => { journal.remove(&index); }
+        }
+    }
+}
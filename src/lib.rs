@@ -3,7 +3,7 @@
 //!
 //! This crate provides a simple stable vector implementation. You can find
 //! nearly all the relevant documentation on
-//! [the type `StableVec`](struct.StableVec.html).
+//! [the type `StableVec`](struct.StableVecFacade.html).
 //!
 //! ---
 //!
@@ -21,27 +21,229 @@
 //!
 //! use stable_vec::StableVec;
 //! ```
+//!
+//! The crate supports `no_std` environments with an allocator: disable the
+//! default `std` feature to only depend on `alloc`. Without it, everything
+//! needing the standard library (the `HashMap` and `HashSet` integrations,
+//! `Error` impls, [snapshots](snapshot/index.html) and
+//! [`ShardedStableVec`](sharded/struct.ShardedStableVec.html)) is
+//! unavailable.
+//!
+//! # Cargo features
+//!
+//! All features are additive. Apart from `std`, none of them is enabled by
+//! default, so the core only depends on `bit-vec`.
+//!
+//! - `std` (default): everything which needs the standard library (see above)
+//! - `serde`: `Serialize` and `Deserialize` impls and [the `serde`
+//!   module](serde/index.html); works without `std`
+//! - `rayon`: parallel iterators, see [the `rayon` module](rayon/index.html);
+//!   implies `std`
+//! - `rand`: random sampling, see [the `rand` module](rand/index.html); works
+//!   without `std`
+//! - `bytemuck`: byte views of `Pod` elements, see [the `bytemuck`
+//!   module](bytemuck/index.html); works without `std`
+//! - `defmt`: a `defmt::Format` impl for `StableVecFacade` (with any storage,
+//!   e.g. `ArrayStableVec`) for logging on embedded targets; works without
+//!   `std`
+//! - `stats`: operation counters, see [the `stats` module](stats/index.html);
+//!   works without `std`
+//! - `arbitrary`, `proptest` and `quickcheck`: integrations with the
+//!   respective testing crates; imply `std`
+//! - `allocator_api`: `AllocStableVec` using the unstable `Allocator` trait;
+//!   requires a nightly compiler
+
+#![no_std]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 
-extern crate bit_vec;
-#[cfg(test)]
 #[macro_use]
-extern crate quickcheck;
-
-use bit_vec::BitVec;
-
-use std::fmt;
-use std::iter::FromIterator;
-use std::mem;
-use std::ops::{Index, IndexMut};
-use std::ptr;
+extern crate alloc;
+#[cfg(any(test, feature = "std"))]
+extern crate std;
 
+#[cfg(feature = "arbitrary")]
+extern crate arbitrary;
+extern crate bit_vec;
+#[cfg(feature = "bytemuck")]
+extern crate bytemuck as bytemuck_crate;
+#[cfg(feature = "defmt")]
+extern crate defmt;
+#[cfg(any(test, feature = "quickcheck"))]
+#[cfg_attr(test, macro_use)]
+extern crate quickcheck;
+#[cfg(feature = "proptest")]
+extern crate proptest as proptest_crate;
+#[cfg(feature = "rand")]
+extern crate rand as rand_crate;
+#[cfg(feature = "rayon")]
+extern crate rayon as rayon_crate;
+#[cfg(feature = "serde")]
+extern crate serde as serde_crate;
+#[cfg(all(test, feature = "serde"))]
+extern crate serde_json;
+
+use alloc::boxed::Box;
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+use core::cmp::Ordering;
+use core::fmt;
+use core::iter::FromIterator;
+use core::marker::PhantomData;
+use core::mem::{self, MaybeUninit};
+use core::ops::{Bound, Index, IndexMut, Range, RangeBounds};
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(feature = "std")]
+use std::hash::BuildHasher;
+
+pub mod arena;
+#[cfg(feature = "bytemuck")]
+pub mod bytemuck;
+pub mod concurrent;
+pub mod cursor;
+pub mod debug_index;
+pub mod dense;
+pub mod diff;
+#[cfg(feature = "defmt")]
+mod format;
+pub mod generational;
+pub mod index32;
+#[cfg(feature = "std")]
+pub mod indexed;
+pub mod journal;
+pub mod linked;
+pub mod occupancy;
+pub mod patch;
+#[cfg(feature = "proptest")]
+pub mod proptest;
+#[cfg(feature = "rand")]
+pub mod rand;
+pub mod rank;
+pub mod raw;
+#[cfg(feature = "rayon")]
+pub mod rayon;
+pub mod secondary;
+#[cfg(feature = "serde")]
+pub mod serde;
+#[cfg(feature = "std")]
+pub mod sharded;
+pub mod shared;
+#[cfg(feature = "std")]
+pub mod snapshot;
+#[cfg(feature = "stats")]
+pub mod stats;
+pub mod storage;
 #[cfg(test)]
 mod tests;
+pub mod transaction;
+pub mod typed;
+pub mod undo;
+pub mod validate;
+pub mod view;
+pub mod zip;
+
+pub use arena::StableArena;
+pub use concurrent::ConcurrentStableVec;
+pub use debug_index::{TaggedIndex, VersionedIndex};
+pub use dense::DenseMirror;
+pub use generational::{GenKey, GenStableVec, Handle, HandleMap};
+pub use index32::StableVec32;
+#[cfg(feature = "std")]
+pub use indexed::IndexedStableVec;
+pub use linked::LinkedStableVec;
+pub use secondary::SecondaryMap;
+#[cfg(feature = "std")]
+pub use secondary::SparseSecondaryMap;
+#[cfg(feature = "std")]
+pub use sharded::ShardedStableVec;
+pub use shared::SharedStableVec;
+pub use typed::{Key, TypedStableVec};
+pub use undo::UndoableStableVec;
+pub use validate::InvariantError;
+
+use journal::{ChangeKind, Journal};
+use rank::RankIndex;
+#[cfg(feature = "stats")]
+use stats::Metrics;
+#[cfg(feature = "allocator_api")]
+use storage::AllocStorage;
+use storage::{
+    ArrayStorage, BitmapStorage, NewStorage, OptionStorage, SegmentedStorage, SmallStorage,
+    Storage,
+};
+
 
+/// A `Vec<T>`-like collection which guarantees stable indices and features
+/// O(1) deletion of elements, using the default storage.
+///
+/// This is the type you usually want. See
+/// [`StableVecFacade`](struct.StableVecFacade.html) for the documentation of
+/// all methods.
+pub type StableVec<T> = StableVecFacade<T, BitmapStorage<T>>;
+
+/// A stable vector storing an `Option<T>` per slot. See
+/// [`OptionStorage`](storage/struct.OptionStorage.html).
+pub type OptionStableVec<T> = StableVecFacade<T, OptionStorage<T>>;
+
+/// A stable vector which never moves its elements, not even when growing.
+/// See [`SegmentedStorage`](storage/struct.SegmentedStorage.html).
+pub type SegmentedStableVec<T> = StableVecFacade<T, SegmentedStorage<T>>;
+
+/// A stable vector whose memory comes from the allocator `A`. See
+/// [`AllocStorage`](storage/struct.AllocStorage.html).
+#[cfg(feature = "allocator_api")]
+pub type AllocStableVec<T, A> = StableVecFacade<T, AllocStorage<T, A>>;
+
+/// A stable vector with a fixed capacity of `N` slots which never allocates.
+/// See [`ArrayStorage`](storage/struct.ArrayStorage.html).
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::ArrayStableVec;
+///
+/// let mut sv: ArrayStableVec<char, 2> = ArrayStableVec::new();
+/// let a = sv.push('a');
+/// sv.push('b');
+/// assert_eq!(sv.try_push('c'), Err('c'));
+///
+/// sv.remove(a);
+/// assert_eq!(sv.iter().collect::<Vec<_>>(), vec![&'b']);
+/// ```
+pub type ArrayStableVec<T, const N: usize> = StableVecFacade<T, ArrayStorage<T, N>>;
+
+/// A stable vector which stores up to `N` slots inline and only allocates
+/// when more slots are needed. See
+/// [`SmallStorage`](storage/struct.SmallStorage.html).
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::SmallStableVec;
+///
+/// let mut sv: SmallStableVec<char, 2> = SmallStableVec::new();
+/// let a = sv.push('a');
+/// sv.push('b');
+/// assert!(!sv.spilled());
+///
+/// sv.push('c');
+/// assert!(sv.spilled());
+///
+/// sv.remove(a);
+/// assert_eq!(sv.iter().collect::<Vec<_>>(), vec![&'b', &'c']);
+/// ```
+pub type SmallStableVec<T, const N: usize> = StableVecFacade<T, SmallStorage<T, N>>;
 
 /// A `Vec<T>`-like collection which guarantees stable indices and features
 /// O(1) deletion of elements.
 ///
+/// The second type parameter selects how elements are stored; see
+/// [the `storage` module](storage/index.html). Usually, you use this type
+/// through the alias [`StableVec<T>`](type.StableVec.html), which selects
+/// the default storage.
+///
 /// # Why?
 ///
 /// The standard `Vec<T>` always stores all elements contiguous. While this has
@@ -113,6 +315,16 @@ mod tests;
 /// [vec-doc]: https://doc.rust-lang.org/stable/std/vec/struct.Vec.html
 ///
 ///
+/// # Zero-sized types
+///
+/// If `T` is a zero-sized type (like `()`), no memory is allocated for the
+/// elements at all. With the default storage, only the occupancy bitmap (one
+/// bit per slot) is stored, which makes `StableVec<()>` a cheap way to keep
+/// track of used indices. All operations, including the compacting ones,
+/// work as usual. [`capacity()`](#method.capacity) returns `usize::MAX` in
+/// this case, as the elements never need to be reallocated.
+///
+///
 /// # Method overview
 ///
 /// (*there are more methods than mentioned in this overview*)
@@ -125,24 +337,40 @@ mod tests;
 /// **Adding and removing elements**
 ///
 /// - [`push()`](#method.push)
+/// - [`push_reuse()`](#method.push_reuse)
 /// - [`pop()`](#method.pop)
 /// - [`remove()`](#method.remove)
+/// - [`try_remove()`](#method.try_remove)
+/// - [`try_insert_at()`](#method.try_insert_at)
 ///
 /// **Accessing elements**
 ///
 /// - [`get()`](#method.get) (returns `Option<&T>`)
+/// - [`get_checked()`](#method.get_checked) (returns `Result<&T, AccessError>`)
 /// - [the `[]` index operator](#impl-Index<usize>) (returns `&T`)
 /// - [`get_mut()`](#method.get_mut) (returns `Option<&mut T>`)
 /// - [the mutable `[]` index operator](#impl-IndexMut<usize>) (returns `&mut T`)
 /// - [`remove()`](#method.remove) (returns `Option<T>`)
+/// - [`try_replace()`](#method.try_replace) (returns `Result<T, T>`)
+/// - [`slice()`](#method.slice) (returns `Option<&[T]>` for hole-free ranges)
+/// - [`as_ptr()`](#method.as_ptr) (returns `Option<*const T>`)
 ///
 /// **Stable vector specific**
 ///
 /// - [`has_element_at()`](#method.has_element_at)
 /// - [`next_index()`](#method.next_index)
+/// - [`next_push_index()`](#method.next_push_index)
+/// - [`set_push_policy()`](#method.set_push_policy)
 /// - [`is_compact()`](#method.is_compact)
 /// - [`make_compact()`](#method.make_compact)
 /// - [`reordering_make_compact()`](#method.reordering_make_compact)
+/// - [`sort()`](#method.sort)
+/// - [`generation()`](#method.generation)
+/// - [`view()`](#method.view)
+/// - [`rank_index()`](#method.rank_index)
+/// - [`occupancy()`](#method.occupancy)
+/// - [`validate()`](#method.validate)
+/// - [`diff()`](#method.diff)
 ///
 /// **Number of elements**
 ///
@@ -155,28 +383,54 @@ mod tests;
 /// - [`shrink_to_fit()`](#method.shrink_to_fit)
 /// - [`reserve()`](#method.reserve)
 ///
-#[derive(Clone, PartialEq, Eq)]
-pub struct StableVec<T> {
-    /// Storing the actual data.
-    data: Vec<T>,
-
-    /// A flag for each element saying whether the element was removed.
-    deleted: BitVec,
+pub struct StableVecFacade<T, S: Storage<T>> {
+    /// Storing the actual data and which slots are holes.
+    storage: S,
 
-    /// A cached value equal to `self.deleted.iter().filter(|&b| !b).count()`
+    /// A cached value equal to the number of slots which are not holes.
     used_count: usize,
+
+    /// Incremented whenever an element is removed or moved. See
+    /// [`generation()`](#method.generation).
+    generation: usize,
+
+    /// Indices of holes, the most recently created one last. Used by
+    /// [`push_reuse()`](#method.push_reuse). Entries might be outdated (the
+    /// slot was filled or truncated in the meantime); those are skipped when
+    /// popping and pruned when they make up more than half of the list, see
+    /// `push_free_slot()`.
+    free_slots: Vec<usize>,
+
+    /// Where `push()` inserts new elements.
+    push_policy: PushPolicy,
+
+    /// The number of compactions (and sorts) so far. Only used to check
+    /// [`VersionedIndex`](debug_index/struct.VersionedIndex.html)es.
+    #[cfg(debug_assertions)]
+    compactions: usize,
+
+    /// A unique ID of this instance. Only used to check
+    /// [`TaggedIndex`](debug_index/struct.TaggedIndex.html)es.
+    #[cfg(debug_assertions)]
+    id: usize,
+
+    /// The changes recorded since the last `take_changes()`, or `None` if
+    /// the journal is disabled. See [the `journal` module](journal/index.html).
+    journal: Option<Box<Journal>>,
+
+    /// The operation counters. See [the `stats` module](stats/index.html).
+    #[cfg(feature = "stats")]
+    metrics: Metrics,
+
+    marker: PhantomData<T>,
 }
 
-impl<T> StableVec<T> {
+impl<T, S: NewStorage<T>> StableVecFacade<T, S> {
     /// Constructs a new, empty `StableVec<T>`.
     ///
     /// The stable-vector will not allocate until elements are pushed onto it.
     pub fn new() -> Self {
-        Self {
-            data: Vec::new(),
-            deleted: BitVec::new(),
-            used_count: 0,
-        }
+        Self::from_storage(S::new())
     }
 
     /// Constructs a new, empty `StableVec<T>` with the specified capacity.
@@ -185,22 +439,80 @@ impl<T> StableVec<T> {
     /// without reallocating. If `capacity` is 0, the stable-vector will not
     /// allocate any memory.
     pub fn with_capacity(capacity: usize) -> Self {
+        Self::from_storage(S::with_capacity(capacity))
+    }
+
+    /// Constructs a new, empty `StableVec<T>` whose [`push()`](#method.push)
+    /// follows the given policy.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use stable_vec::{PushPolicy, StableVec};
+    ///
+    /// let mut sv = StableVec::with_push_policy(PushPolicy::PreferLowest);
+    /// sv.extend(vec!['a', 'b', 'c']);
+    /// sv.remove(2);
+    /// sv.remove(0);
+    ///
+    /// assert_eq!(sv.push('x'), 0);
+    /// assert_eq!(sv.push('y'), 2);
+    /// assert_eq!(sv.push('z'), 3);
+    /// ```
+    pub fn with_push_policy(policy: PushPolicy) -> Self {
+        let mut out = Self::new();
+        out.push_policy = policy;
+        out
+    }
+}
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Creates a stable vector using the given (empty) storage.
+    fn from_storage(storage: S) -> Self {
         Self {
-            data: Vec::with_capacity(capacity),
-            deleted: BitVec::with_capacity(capacity),
+            storage,
             used_count: 0,
+            generation: 0,
+            free_slots: Vec::new(),
+            push_policy: PushPolicy::Append,
+            #[cfg(debug_assertions)]
+            compactions: 0,
+            #[cfg(debug_assertions)]
+            id: debug_index::next_instance_id(),
+            journal: None,
+            #[cfg(feature = "stats")]
+            metrics: Metrics::default(),
+            marker: PhantomData,
         }
     }
 
+    /// Returns the policy which determines where [`push()`](#method.push)
+    /// inserts new elements.
+    pub fn push_policy(&self) -> PushPolicy {
+        self.push_policy
+    }
+
+    /// Changes the policy which determines where [`push()`](#method.push)
+    /// inserts new elements. This only affects future calls to `push()`.
+    pub fn set_push_policy(&mut self, policy: PushPolicy) {
+        self.push_policy = policy;
+    }
+
     /// Reserves capacity for at least `additional` more elements to be
     /// inserted.
     pub fn reserve(&mut self, additional: usize) {
-        self.data.reserve(additional);
-        self.deleted.reserve(additional);
+        #[cfg(feature = "stats")]
+        let capacity = self.storage.capacity();
+        self.storage.reserve(additional);
+        #[cfg(feature = "stats")]
+        self.count_reallocation(capacity);
     }
 
-    /// Appends a new element to the back of the collection and returns the
-    /// index of the inserted element.
+    /// Inserts a new element and returns its index.
+    ///
+    /// With the default [`PushPolicy::Append`](enum.PushPolicy.html), the
+    /// element is appended to the back of the collection; see
+    /// [`push_policy()`](#method.push_policy) for the alternatives.
     ///
     /// The inserted element will always be accessable via the returned index.
     ///
@@ -220,10 +532,205 @@ impl<T> StableVec<T> {
     /// assert_eq!(sv.get(heart_idx), Some(&'♥'));
     /// ```
     pub fn push(&mut self, elem: T) -> usize {
-        self.data.push(elem);
-        self.deleted.push(false);
+        match self.push_policy {
+            PushPolicy::Append => self.push_at_end(elem),
+            PushPolicy::PreferHoles => self.push_reuse(elem),
+            PushPolicy::PreferLowest => match self.storage.next_hole(0) {
+                Some(index) => {
+                    unsafe {
+                        self.storage.insert_at(index, elem);
+                    }
+                    self.used_count += 1;
+                    #[cfg(feature = "stats")]
+                    {
+                        self.metrics.pushes += 1;
+                        self.metrics.hole_reuses += 1;
+                    }
+                    self.record(index, ChangeKind::Inserted);
+                    index
+                }
+                None => self.push_at_end(elem),
+            },
+        }
+    }
+
+    /// Appends the element to the back, regardless of the push policy.
+    fn push_at_end(&mut self, elem: T) -> usize {
+        #[cfg(feature = "stats")]
+        let capacity = self.storage.capacity();
+        self.storage.push(elem);
         self.used_count += 1;
-        self.data.len() - 1
+        #[cfg(feature = "stats")]
+        {
+            self.metrics.pushes += 1;
+            self.count_reallocation(capacity);
+        }
+        self.record(self.storage.len() - 1, ChangeKind::Inserted);
+        self.storage.len() - 1
+    }
+
+    /// Inserts the element into a hole and returns its index. If there are no
+    /// holes, the element is appended to the back.
+    ///
+    /// The most recently created hole is filled first. This is O(1)
+    /// (amortized): the stable vector keeps a stack of holes for this
+    /// purpose, which needs about two `usize` per hole at most. The
+    /// [`ArrayStorage`](storage/struct.ArrayStorage.html) doesn't keep that
+    /// stack; there, the hole with the largest index is filled in O(n).
+    ///
+    /// Unlike `push()`, the returned index might be smaller than the indices
+    /// of existing elements.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&['a', 'b', 'c']);
+    /// sv.remove(0);
+    /// sv.remove(2);
+    ///
+    /// assert_eq!(sv.push_reuse('x'), 2);
+    /// assert_eq!(sv.push_reuse('y'), 0);
+    /// assert_eq!(sv.push_reuse('z'), 3);
+    /// assert_eq!(sv, vec!['y', 'b', 'x', 'z']);
+    /// ```
+    pub fn push_reuse(&mut self, elem: T) -> usize {
+        match self.pop_free_slot() {
+            Some(index) => {
+                unsafe {
+                    self.storage.insert_at(index, elem);
+                }
+                self.used_count += 1;
+                #[cfg(feature = "stats")]
+                {
+                    self.metrics.pushes += 1;
+                    self.metrics.hole_reuses += 1;
+                }
+                self.record(index, ChangeKind::Inserted);
+                index
+            }
+            None => self.push_at_end(elem),
+        }
+    }
+
+    /// Removes and returns the hole `push_reuse()` fills next, skipping
+    /// outdated entries of `free_slots`.
+    fn pop_free_slot(&mut self) -> Option<usize> {
+        if !S::HOLE_STACK {
+            return self.last_hole();
+        }
+
+        while let Some(index) = self.free_slots.pop() {
+            if index < self.storage.len() && !self.storage.has_element_at(index) {
+                return Some(index);
+            }
+        }
+        None
+    }
+
+    /// Returns the hole with the largest index.
+    fn last_hole(&self) -> Option<usize> {
+        (0..self.storage.len()).rev().find(|&i| !self.storage.has_element_at(i))
+    }
+
+    /// Appends a hole to the back of the collection, i.e. an empty slot
+    /// without an element.
+    ///
+    /// This is only useful to restore a stable vector with a given layout of
+    /// holes (e.g. when deserializing).
+    fn push_hole(&mut self) {
+        self.storage.push_hole();
+        self.push_free_slot(self.storage.len() - 1);
+    }
+
+    /// Pushes the index of a new hole onto `free_slots`.
+    ///
+    /// Filling a hole via anything but `push_reuse()` leaves an outdated
+    /// entry behind. To keep the list from growing without bound, it's
+    /// pruned first if it has more than two entries per hole. After
+    /// pruning, at most half of the entries are left, so this is O(1)
+    /// amortized (ignoring a logarithmic factor for sorting).
+    fn push_free_slot(&mut self, index: usize) {
+        if !S::HOLE_STACK {
+            return;
+        }
+        if self.free_slots_prune_due() {
+            self.prune_free_slots();
+        }
+        self.free_slots.push(index);
+    }
+
+    /// Returns `true` if the next `push_free_slot()` prunes `free_slots`.
+    fn free_slots_prune_due(&self) -> bool {
+        self.free_slots.len() > 2 * (self.storage.len() - self.used_count)
+    }
+
+    /// Removes all outdated entries from `free_slots`: indices which are no
+    /// holes (anymore) and all but the most recent entry of each hole. The
+    /// order of the remaining entries is kept, so this doesn't change which
+    /// holes `push_reuse()` fills.
+    fn prune_free_slots(&mut self) {
+        let len = self.storage.len();
+        let mut entries = self.free_slots
+            .iter()
+            .cloned()
+            .enumerate()
+            .filter(|&(_, i)| i < len && !self.storage.has_element_at(i))
+            .map(|(pos, i)| (i, pos))
+            .collect::<Vec<_>>();
+
+        // Sorting by index and descending position puts the most recent
+        // entry of each hole first.
+        entries.sort_unstable_by(|a, b| a.0.cmp(&b.0).then(b.1.cmp(&a.1)));
+        entries.dedup_by_key(|entry| entry.0);
+        entries.sort_unstable_by_key(|entry| entry.1);
+
+        self.free_slots.clear();
+        self.free_slots.extend(entries.into_iter().map(|(i, _)| i));
+    }
+
+    /// Stores `elem` at the given index, dropping the element previously
+    /// stored there (if any). If `index` is out of bounds, the stable vector
+    /// is grown with holes first.
+    fn set_slot(&mut self, index: usize, elem: T) {
+        while self.storage.len() < index {
+            self.push_hole();
+        }
+
+        if index == self.storage.len() {
+            self.storage.push(elem);
+            self.used_count += 1;
+            self.record(index, ChangeKind::Inserted);
+        } else if self.storage.has_element_at(index) {
+            unsafe {
+                *self.storage.get_unchecked_mut(index) = elem;
+            }
+            self.record(index, ChangeKind::Modified);
+        } else {
+            // The slot is a hole, thus there is no old value to drop.
+            unsafe {
+                self.storage.insert_at(index, elem);
+            }
+            self.used_count += 1;
+            self.record(index, ChangeKind::Inserted);
+        }
+    }
+
+    /// Shortens the stable vector such that `next_index()` returns `len`
+    /// afterwards, dropping all elements at indices `>= len`. Does nothing if
+    /// `len >= next_index()`.
+    fn truncate(&mut self, len: usize) {
+        for i in len..self.storage.len() {
+            self.remove(i);
+        }
+
+        if len < self.storage.len() {
+            // All slots from `len` on are holes now, so nothing needs to be
+            // dropped.
+            unsafe {
+                self.storage.truncate(len);
+            }
+        }
     }
 
     /// Removes and returns the last element from this collection, or `None` if
@@ -238,11 +745,9 @@ impl<T> StableVec<T> {
     /// has a worst case time complexity of O(n). If you already know the
     /// index, use [`remove()`](#method.remove) instead.
     pub fn pop(&mut self) -> Option<T> {
-        let last_index = self.deleted.iter()
-            .enumerate()
+        let last_index = (0..self.storage.len())
             .rev()
-            .find(|&(_, deleted)| !deleted)
-            .map(|(i, _)| i)
+            .find(|&i| self.storage.has_element_at(i))
             .unwrap_or(0);
         self.remove(last_index)
     }
@@ -274,18 +779,15 @@ impl<T> StableVec<T> {
     /// ```
     pub fn remove(&mut self, index: usize) -> Option<T> {
         if self.has_element_at(index) {
-            // We move the requested element out of our `data` vector. Usually,
-            // it's impossible to move out of a vector without removing the
-            // element in the vector. We can achieve it by using unsafe code:
-            // We just read the value from the vector without changing
-            // anything. This is dangerous if we try to access this element
-            // in the vector later. To prevent any access, we mark the element
-            // as deleted.
-            let elem = unsafe {
-                self.deleted.set(index, true);
-                ptr::read(&self.data[index])
-            };
+            let elem = unsafe { self.storage.remove_at(index) };
             self.used_count -= 1;
+            #[cfg(feature = "stats")]
+            {
+                self.metrics.removals += 1;
+            }
+            self.generation = self.generation.wrapping_add(1);
+            self.push_free_slot(index);
+            self.record(index, ChangeKind::Removed);
             Some(elem)
         } else {
             None
@@ -299,7 +801,7 @@ impl<T> StableVec<T> {
     /// rather use the index operator instead: `stable_vec[index]`.
     pub fn get(&self, index: usize) -> Option<&T> {
         if self.has_element_at(index) {
-            Some(&self.data[index])
+            Some(unsafe { self.storage.get_unchecked(index) })
         } else {
             None
         }
@@ -312,12 +814,189 @@ impl<T> StableVec<T> {
     /// rather use the index operator instead: `stable_vec[index]`.
     pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
         if self.has_element_at(index) {
-            Some(&mut self.data[index])
+            self.record(index, ChangeKind::Modified);
+            Some(unsafe { self.storage.get_unchecked_mut(index) })
         } else {
             None
         }
     }
 
+    /// Returns a raw pointer to the element at the given index, or `None` if
+    /// there exists no element at that index.
+    ///
+    /// The pointer stays valid until one of the following happens:
+    ///
+    /// - the element is removed or replaced,
+    /// - the element is moved by one of the compacting or reordering methods
+    ///   (e.g. [`make_compact()`](#method.make_compact) or
+    ///   [`sort()`](#method.sort)),
+    /// - the storage reallocates, e.g. because an element is appended while
+    ///   `next_index() == capacity()` or because of
+    ///   [`reserve()`](#method.reserve) or
+    ///   [`shrink_to_fit()`](#method.shrink_to_fit) (the segmented storage
+    ///   never reallocates, see
+    ///   [`SegmentedStableVec::get_ptr()`](#method.get_ptr)),
+    /// - the stable vector is dropped.
+    ///
+    /// Reading through the pointer is only allowed while no mutable reference
+    /// to the same element exists. The pointer must not be used for writing;
+    /// use [`as_mut_ptr()`](#method.as_mut_ptr) for that.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[1, 2, 3]);
+    /// sv.remove(1);
+    ///
+    /// let ptr = sv.as_ptr(2).unwrap();
+    /// assert_eq!(unsafe { *ptr }, 3);
+    /// assert_eq!(sv.as_ptr(1), None);
+    /// ```
+    pub fn as_ptr(&self, index: usize) -> Option<*const T> {
+        self.get(index).map(|elem| elem as *const T)
+    }
+
+    /// Returns a raw pointer to the element at the given index which may be
+    /// used for writing, or `None` if there exists no element at that index.
+    ///
+    /// The pointer stays valid under the same conditions as described for
+    /// [`as_ptr()`](#method.as_ptr). Additionally, it must only be used while
+    /// no reference into the stable vector exists, as those might alias the
+    /// element.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[1, 2, 3]);
+    ///
+    /// let ptr = sv.as_mut_ptr(0).unwrap();
+    /// unsafe { *ptr = 10; }
+    /// assert_eq!(sv, &[10, 2, 3] as &[_]);
+    /// ```
+    pub fn as_mut_ptr(&mut self, index: usize) -> Option<*mut T> {
+        self.get_mut(index).map(|elem| elem as *mut T)
+    }
+
+    /// Returns a reference to the element at the given index, or an error
+    /// describing why there is no element at that index.
+    ///
+    /// This is useful if you need to distinguish stale indices (which point
+    /// to removed elements) from indices that were never valid in the first
+    /// place.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::{AccessError, StableVec};
+    /// let mut sv = StableVec::from(&['a', 'b']);
+    /// sv.remove(0);
+    ///
+    /// assert_eq!(sv.get_checked(1), Ok(&'b'));
+    /// assert_eq!(sv.get_checked(0), Err(AccessError::Hole));
+    /// assert_eq!(sv.get_checked(2), Err(AccessError::OutOfBounds));
+    /// ```
+    pub fn get_checked(&self, index: usize) -> Result<&T, AccessError> {
+        self.check_access(index)?;
+        Ok(unsafe { self.storage.get_unchecked(index) })
+    }
+
+    /// Returns a mutable reference to the element at the given index, or an
+    /// error describing why there is no element at that index.
+    ///
+    /// See [`get_checked()`](#method.get_checked) for more information.
+    pub fn get_mut_checked(&mut self, index: usize) -> Result<&mut T, AccessError> {
+        self.check_access(index)?;
+        self.record(index, ChangeKind::Modified);
+        Ok(unsafe { self.storage.get_unchecked_mut(index) })
+    }
+
+    /// Returns `Ok(())` if there exists an element at the given index and the
+    /// fitting `AccessError` otherwise.
+    fn check_access(&self, index: usize) -> Result<(), AccessError> {
+        if index >= self.storage.len() {
+            Err(AccessError::OutOfBounds)
+        } else if !self.storage.has_element_at(index) {
+            Err(AccessError::Hole)
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Removes and returns the element at the given index, or returns an
+    /// error describing why there is no element at that index.
+    ///
+    /// Like [`remove()`](#method.remove), but distinguishes stale indices
+    /// from indices that were never valid (see
+    /// [`get_checked()`](#method.get_checked)).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::{AccessError, StableVec};
+    /// let mut sv = StableVec::from(&['a', 'b']);
+    ///
+    /// assert_eq!(sv.try_remove(0), Ok('a'));
+    /// assert_eq!(sv.try_remove(0), Err(AccessError::Hole));
+    /// assert_eq!(sv.try_remove(2), Err(AccessError::OutOfBounds));
+    /// ```
+    pub fn try_remove(&mut self, index: usize) -> Result<T, AccessError> {
+        self.check_access(index)?;
+        Ok(self.remove(index).unwrap())
+    }
+
+    /// Replaces the element at the given index with `elem` and returns the
+    /// old element. If there is no element at that index, nothing is changed
+    /// and `elem` is returned as `Err` instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&['a', 'b']);
+    /// sv.remove(1);
+    ///
+    /// assert_eq!(sv.try_replace(0, 'x'), Ok('a'));
+    /// assert_eq!(sv.try_replace(1, 'y'), Err('y'));
+    /// assert_eq!(sv.try_replace(5, 'z'), Err('z'));
+    /// assert_eq!(sv, &['x'] as &[_]);
+    /// ```
+    pub fn try_replace(&mut self, index: usize, elem: T) -> Result<T, T> {
+        match self.get_mut(index) {
+            Some(slot) => Ok(mem::replace(slot, elem)),
+            None => Err(elem),
+        }
+    }
+
+    /// Inserts `elem` into the hole at the given index. If the index is out of
+    /// bounds or there already is an element at that index, nothing is
+    /// changed and `elem` is returned as `Err` instead.
+    ///
+    /// This never grows the stable vector, so it's safe to call with
+    /// untrusted indices.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&['a', 'b']);
+    /// sv.remove(0);
+    ///
+    /// assert_eq!(sv.try_insert_at(0, 'x'), Ok(()));
+    /// assert_eq!(sv.try_insert_at(1, 'y'), Err('y'));
+    /// assert_eq!(sv.try_insert_at(2, 'z'), Err('z'));
+    /// assert_eq!(sv, &['x', 'b'] as &[_]);
+    /// ```
+    pub fn try_insert_at(&mut self, index: usize, elem: T) -> Result<(), T> {
+        if index >= self.storage.len() || self.storage.has_element_at(index) {
+            return Err(elem);
+        }
+
+        self.set_slot(index, elem);
+        Ok(())
+    }
+
     /// Returns `true` if there exists an element at the given index, `false`
     /// otherwise.
     ///
@@ -338,19 +1017,24 @@ impl<T> StableVec<T> {
     /// assert!(!sv.has_element_at(heart_idx)); // no: was removed
     /// ```
     pub fn has_element_at(&self, index: usize) -> bool {
-        index < self.data.len() && !self.deleted[index]
+        self.storage.has_element_at(index)
     }
 
-    /// Calls `shrink_to_fit()` on the underlying `Vec<T>`.
+    /// Calls `shrink_to_fit()` on the underlying storage.
     ///
     /// Note that this does not move existing elements around and thus does
     /// not invalidate indices. It only calls `shrink_to_fit()` on the
-    /// `Vec<T>` that holds the actual data.
+    /// `Vec` that holds the actual data.
     ///
     /// If you want to compact this `StableVec` by removing deleted elements,
     /// use the method [`make_compact()`](#method.make_compact) instead.
     pub fn shrink_to_fit(&mut self) {
-        self.data.shrink_to_fit();
+        #[cfg(feature = "stats")]
+        let capacity = self.storage.capacity();
+        self.storage.shrink_to_fit();
+        #[cfg(feature = "stats")]
+        self.count_reallocation(capacity);
+        self.free_slots.shrink_to_fit();
     }
 
     /// Rearranges elements to reclaim memory. **Invalidates indices!**
@@ -370,15 +1054,49 @@ impl<T> StableVec<T> {
     /// This method invalidates the indices of all elements that are stored
     /// after the first hole in the stable vector!
     pub fn make_compact(&mut self) {
+        self.make_compact_with(|_, _| {});
+    }
+
+    /// Like [`make_compact()`](#method.make_compact), but calls
+    /// `on_move(from, to)` for every element which is moved from index `from`
+    /// to index `to`.
+    ///
+    /// This is useful to update indices stored elsewhere, or to mirror the
+    /// changes in an external copy (e.g. a GPU buffer).
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&['a', 'b', 'c', 'd']);
+    /// sv.remove(1);
+    ///
+    /// let mut moves = Vec::new();
+    /// sv.make_compact_with(|from, to| moves.push((from, to)));
+    /// assert_eq!(moves, vec![(2, 1), (3, 2)]);
+    /// ```
+    pub fn make_compact_with<F>(&mut self, mut on_move: F)
+        where F: FnMut(usize, usize),
+    {
         if self.is_compact() {
             return;
         }
+        self.generation = self.generation.wrapping_add(1);
+        self.free_slots.clear();
+        #[cfg(debug_assertions)]
+        {
+            self.compactions += 1;
+        }
+        #[cfg(feature = "stats")]
+        {
+            self.metrics.compactions += 1;
+        }
 
         // We only have to move elements, if we have any.
         if self.used_count > 0 {
             // We have to find the position of the first hole. We know that
             // there is at least one hole, so we can unwrap.
-            let first_hole_index = self.deleted.iter().position(|d| d).unwrap();
+            let first_hole_index = self.storage.next_hole(0).unwrap();
 
             // This variable will store the first possible index of an element
             // which can be inserted in the hole.
@@ -392,23 +1110,27 @@ impl<T> StableVec<T> {
                 // of bounds. This will never happen! We do have enough
                 // elements to fill all holes. And once all holes are filled,
                 // the outer loop will stop.
-                while self.deleted[element_index] {
+                while !self.storage.has_element_at(element_index) {
                     element_index += 1;
                 }
 
                 // So at this point `hole_index` points to a valid hole and
                 // `element_index` points to a valid element. Time to swap!
-                self.data.swap(hole_index, element_index);
-                self.deleted.set(hole_index, false);
-                self.deleted.set(element_index, true);
+                self.storage.swap(hole_index, element_index);
+                self.record(element_index, ChangeKind::Removed);
+                self.record(hole_index, ChangeKind::Inserted);
+                #[cfg(feature = "stats")]
+                {
+                    self.metrics.elements_moved += 1;
+                }
+                on_move(element_index, hole_index);
             }
         }
 
-        // We can safely call `set_len()` here: all elements that still need
-        // to be dropped are in the range 0..self.used_count.
+        // We can safely truncate here: all elements that still need to be
+        // dropped are in the range 0..self.used_count.
         unsafe {
-            self.data.set_len(self.used_count);
-            self.deleted.set_len(self.used_count);
+            self.storage.truncate(self.used_count);
         }
     }
 
@@ -430,9 +1152,29 @@ impl<T> StableVec<T> {
     /// This method invalidates the indices of all elements that are stored
     /// after the first hole and it does not preserve the order of elements!
     pub fn reordering_make_compact(&mut self) {
+        self.reordering_make_compact_with(|_, _| {});
+    }
+
+    /// Like [`reordering_make_compact()`](#method.reordering_make_compact),
+    /// but calls `on_move(from, to)` for every element which is moved from
+    /// index `from` to index `to`. See
+    /// [`make_compact_with()`](#method.make_compact_with).
+    pub fn reordering_make_compact_with<F>(&mut self, mut on_move: F)
+        where F: FnMut(usize, usize),
+    {
         if self.is_compact() {
             return;
         }
+        self.generation = self.generation.wrapping_add(1);
+        self.free_slots.clear();
+        #[cfg(debug_assertions)]
+        {
+            self.compactions += 1;
+        }
+        #[cfg(feature = "stats")]
+        {
+            self.metrics.compactions += 1;
+        }
 
         // We only have to move elements, if we have any.
         if self.used_count > 0 {
@@ -442,17 +1184,17 @@ impl<T> StableVec<T> {
             //   that can be filled with an element.
             // - `element_index` starts from the back and searches for an
             //   element.
-            let len = self.data.len();
+            let len = self.storage.len();
             let mut element_index = len - 1;
             let mut hole_index = 0;
             loop {
                 // Advance `element_index` until we found an element.
-                while element_index > 0 && self.deleted[element_index] {
+                while element_index > 0 && !self.storage.has_element_at(element_index) {
                     element_index -= 1;
                 }
 
                 // Advance `hole_index` until we found a hole.
-                while hole_index < len && !self.deleted[hole_index] {
+                while hole_index < len && self.storage.has_element_at(hole_index) {
                     hole_index += 1;
                 }
 
@@ -465,17 +1207,102 @@ impl<T> StableVec<T> {
 
                 // We found an element and a hole left of the element. That
                 // means that we can swap.
-                self.data.swap(hole_index, element_index);
-                self.deleted.set(hole_index, false);
-                self.deleted.set(element_index, true);
+                self.storage.swap(hole_index, element_index);
+                self.record(element_index, ChangeKind::Removed);
+                self.record(hole_index, ChangeKind::Inserted);
+                #[cfg(feature = "stats")]
+                {
+                    self.metrics.elements_moved += 1;
+                }
+                on_move(element_index, hole_index);
             }
         }
 
-        // We can safely call `set_len()` here: all elements that still need
-        // to be dropped are in the range 0..self.used_count.
+        // We can safely truncate here: all elements that still need to be
+        // dropped are in the range 0..self.used_count.
         unsafe {
-            self.data.set_len(self.used_count);
-            self.deleted.set_len(self.used_count);
+            self.storage.truncate(self.used_count);
+        }
+    }
+
+    /// Sorts the existing elements. **Invalidates indices!**
+    ///
+    /// The sort is stable. Holes stay where they are: afterwards, the
+    /// elements are in ascending order when iterating with
+    /// [`iter()`](#method.iter), but occupy the same indices as before. Thus,
+    /// an index refers to another element afterwards; if you need to keep
+    /// track of elements, store keys within the elements themselves.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[3, 1, 4, 1, 5]);
+    /// sv.remove(2);
+    /// sv.sort();
+    ///
+    /// assert_eq!(sv.iter().cloned().collect::<Vec<_>>(), vec![1, 1, 3, 5]);
+    /// assert_eq!(sv.keys().collect::<Vec<_>>(), vec![0, 1, 3, 4]);
+    /// ```
+    pub fn sort(&mut self)
+        where T: Ord,
+    {
+        self.sort_by(Ord::cmp);
+    }
+
+    /// Sorts the existing elements with the given comparator function.
+    /// **Invalidates indices!**
+    ///
+    /// The sort is stable. See [`sort()`](#method.sort) for more information.
+    pub fn sort_by<F>(&mut self, mut compare: F)
+        where F: FnMut(&T, &T) -> Ordering,
+    {
+        let keys = self.keys().collect::<Vec<_>>();
+        let mut order = (0..keys.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| compare(&self[keys[a]], &self[keys[b]]));
+        self.apply_order(&keys, &order);
+    }
+
+    /// Sorts the existing elements with the given comparator function, but
+    /// might not preserve the order of equal elements. **Invalidates
+    /// indices!**
+    ///
+    /// See [`sort()`](#method.sort) for more information.
+    pub fn sort_unstable_by<F>(&mut self, mut compare: F)
+        where F: FnMut(&T, &T) -> Ordering,
+    {
+        let keys = self.keys().collect::<Vec<_>>();
+        let mut order = (0..keys.len()).collect::<Vec<_>>();
+        order.sort_unstable_by(|&a, &b| compare(&self[keys[a]], &self[keys[b]]));
+        self.apply_order(&keys, &order);
+    }
+
+    /// Moves the element at `keys[order[i]]` to `keys[i]`, for all `i`.
+    /// `keys` has to contain the indices of all existing elements and `order`
+    /// has to be a permutation of `0..keys.len()`.
+    fn apply_order(&mut self, keys: &[usize], order: &[usize]) {
+        self.generation = self.generation.wrapping_add(1);
+        // Like compacting, this changes which element an index refers to.
+        #[cfg(debug_assertions)]
+        {
+            self.compactions += 1;
+        }
+
+        // Every permutation is a set of disjoint cycles. Along a cycle, each
+        // swap moves one element to its final slot.
+        let mut done = vec![false; order.len()];
+        for start in 0..order.len() {
+            let mut current = start;
+            while !done[current] {
+                done[current] = true;
+                let source = order[current];
+                if source != start {
+                    self.storage.swap(keys[current], keys[source]);
+                    self.record(keys[current], ChangeKind::Modified);
+                    self.record(keys[source], ChangeKind::Modified);
+                }
+                current = source;
+            }
         }
     }
 
@@ -496,7 +1323,7 @@ impl<T> StableVec<T> {
     /// assert!(!sv.is_compact());
     /// ```
     pub fn is_compact(&self) -> bool {
-        self.used_count == self.data.len()
+        self.used_count == self.storage.len()
     }
 
     /// Returns the number of existing elements in this collection.
@@ -548,11 +1375,16 @@ impl<T> StableVec<T> {
     /// Returns the number of elements the stable-vector can hold without
     /// reallocating.
     pub fn capacity(&self) -> usize {
-        self.data.capacity()
+        self.storage.capacity()
     }
 
-    /// Returns the index that would be returned by calling
-    /// [`push()`](#method.push).
+    /// Returns the number of slots (elements and holes), i.e. the index at
+    /// which the next element would be appended.
+    ///
+    /// With the default [`PushPolicy::Append`](enum.PushPolicy.html), this is
+    /// the index that would be returned by calling [`push()`](#method.push).
+    /// With the other policies, `push()` might return a smaller index; use
+    /// [`next_push_index()`](#method.next_push_index) for that.
     ///
     /// # Example
     ///
@@ -566,25 +1398,84 @@ impl<T> StableVec<T> {
     /// assert_eq!(next_index, index_of_d);
     /// ```
     pub fn next_index(&self) -> usize {
-        self.data.len()
+        self.storage.len()
     }
 
-    /// Returns an iterator over immutable references to the existing elements
-    /// of this stable vector.
-    ///
-    /// Note that you can also use the `IntoIterator` implementation of
-    /// `&StableVec` to obtain the same iterator.
+    /// Returns the index that would be returned by calling
+    /// [`push()`](#method.push), taking the
+    /// [push policy](#method.push_policy) into account.
     ///
     /// # Example
     ///
     /// ```
-    /// # use stable_vec::StableVec;
-    /// let mut sv = StableVec::from(&[0, 1, 2, 3, 4]);
+    /// use stable_vec::{PushPolicy, StableVec};
+    ///
+    /// let mut sv = StableVec::from(&['a', 'b', 'c']);
     /// sv.remove(1);
+    /// assert_eq!(sv.next_push_index(), 3);
     ///
-    /// // Using the `iter()` method to apply a `filter()`.
-    /// let mut it = sv.iter().filter(|&&n| n <= 3);
-    /// assert_eq!(it.next(), Some(&0));
+    /// sv.set_push_policy(PushPolicy::PreferHoles);
+    /// assert_eq!(sv.next_push_index(), 1);
+    /// assert_eq!(sv.push('d'), 1);
+    /// ```
+    pub fn next_push_index(&self) -> usize {
+        let len = self.storage.len();
+        match self.push_policy {
+            PushPolicy::Append => len,
+            PushPolicy::PreferHoles if !S::HOLE_STACK => self.last_hole().unwrap_or(len),
+            PushPolicy::PreferHoles => self.free_slots
+                .iter()
+                .rev()
+                .cloned()
+                .find(|&i| i < len && !self.storage.has_element_at(i))
+                .unwrap_or(len),
+            PushPolicy::PreferLowest => self.storage.next_hole(0).unwrap_or(len),
+        }
+    }
+
+    /// Returns a counter which changes every time an element is removed or
+    /// moved to another index (e.g. by [`remove()`](#method.remove),
+    /// [`retain()`](#method.retain) or [`make_compact()`](#method.make_compact)).
+    ///
+    /// Pushing elements and modifying elements in place don't change the
+    /// generation. Thus, if the generation didn't change, all indices you
+    /// obtained earlier still refer to the same element. This is used by
+    /// [`View`](view/struct.View.html) to detect stale views.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&['a', 'b']);
+    /// let generation = sv.generation();
+    ///
+    /// sv.push('c');
+    /// sv[0] = 'x';
+    /// assert_eq!(sv.generation(), generation);
+    ///
+    /// sv.remove(1);
+    /// assert_ne!(sv.generation(), generation);
+    /// ```
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// Returns an iterator over immutable references to the existing elements
+    /// of this stable vector.
+    ///
+    /// Note that you can also use the `IntoIterator` implementation of
+    /// `&StableVec` to obtain the same iterator.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[0, 1, 2, 3, 4]);
+    /// sv.remove(1);
+    ///
+    /// // Using the `iter()` method to apply a `filter()`.
+    /// let mut it = sv.iter().filter(|&&n| n <= 3);
+    /// assert_eq!(it.next(), Some(&0));
     /// assert_eq!(it.next(), Some(&2));
     /// assert_eq!(it.next(), Some(&3));
     /// assert_eq!(it.next(), None);
@@ -595,7 +1486,7 @@ impl<T> StableVec<T> {
     ///     println!("{:?}", e);
     /// }
     /// ```
-    pub fn iter(&self) -> Iter<T> {
+    pub fn iter(&self) -> Iter<'_, T, S> {
         Iter {
             sv: self,
             pos: 0,
@@ -609,8 +1500,8 @@ impl<T> StableVec<T> {
     /// `&mut StableVec` to obtain the same iterator.
     ///
     /// Through this iterator, the elements within the stable vector can be
-    /// mutated. Furthermore, you can remove elements from the stable vector
-    /// during iteration by calling
+    /// mutated. Furthermore, with the default storage, you can remove elements
+    /// from the stable vector during iteration by calling
     /// [`remove_current()`](struct.IterMut.html#method.remove_current) on the
     /// iterator object.
     ///
@@ -646,11 +1537,9 @@ impl<T> StableVec<T> {
     ///
     /// assert_eq!(sv, &[2.0, 6.0] as &[_]);
     /// ```
-    pub fn iter_mut(&mut self) -> IterMut<T> {
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, S> {
         IterMut {
-            deleted: &mut self.deleted,
-            used_count: &mut self.used_count,
-            vec_iter: self.data.iter_mut(),
+            sv: self,
             pos: 0,
         }
     }
@@ -681,13 +1570,68 @@ impl<T> StableVec<T> {
     ///     println!("index: {}", index);
     /// }
     /// ```
-    pub fn keys(&self) -> Keys {
+    pub fn keys(&self) -> Keys<'_, T, S> {
         Keys {
-            deleted: &self.deleted,
+            sv: self,
             pos: 0,
         }
     }
 
+    /// Returns a watermark to pass to [`iter_from()`](#method.iter_from) or
+    /// [`keys_from()`](#method.keys_from) later, in order to only visit the
+    /// elements pushed after this call. This is simply
+    /// [`next_index()`](#method.next_index).
+    ///
+    /// This only works as expected with
+    /// [`PushPolicy::Append`](enum.PushPolicy.html#variant.Append) (the
+    /// default): elements pushed into holes below the watermark are not
+    /// visited. Calling [`make_compact()`](#method.make_compact) or similar
+    /// methods moves elements and thus invalidates the watermark.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut log = StableVec::from(&["start"]);
+    /// let mut seen = log.watermark();
+    ///
+    /// log.push("a");
+    /// log.push("b");
+    /// assert_eq!(log.iter_from(seen).collect::<Vec<_>>(), vec![&"a", &"b"]);
+    /// seen = log.watermark();
+    ///
+    /// let c = log.push("c");
+    /// log.push("d");
+    /// log.remove(c);
+    /// assert_eq!(log.keys_from(seen).collect::<Vec<_>>(), vec![4]);
+    /// ```
+    pub fn watermark(&self) -> usize {
+        self.next_index()
+    }
+
+    /// Returns an iterator over immutable references to all existing elements
+    /// at an index `>= watermark`, in index order.
+    ///
+    /// The iterator skips holes and stops at the current
+    /// [`next_index()`](#method.next_index). See
+    /// [`watermark()`](#method.watermark) for an example.
+    pub fn iter_from(&self, watermark: usize) -> Iter<'_, T, S> {
+        Iter {
+            sv: self,
+            pos: watermark,
+        }
+    }
+
+    /// Returns an iterator over the indices of all existing elements at an
+    /// index `>= watermark`, in ascending order. See
+    /// [`iter_from()`](#method.iter_from).
+    pub fn keys_from(&self, watermark: usize) -> Keys<'_, T, S> {
+        Keys {
+            sv: self,
+            pos: watermark,
+        }
+    }
+
     /// Returns `true` if the stable vector contains an element with the given
     /// value, `false` otherwise.
     ///
@@ -717,7 +1661,7 @@ impl<T> StableVec<T> {
     /// [`make_compact()`](#method.make_compact); see that method's
     /// documentation to learn about the effects on indices.
     ///
-    /// This method does not allocate memory.
+    /// With the default storage, this method does not allocate memory.
     ///
     /// # Note
     ///
@@ -735,18 +1679,52 @@ impl<T> StableVec<T> {
     /// assert_eq!(sv.into_vec(), vec!['a', 'c']);
     /// ```
     pub fn into_vec(mut self) -> Vec<T> {
-        // Compact the stable vec to prepare the `data` vector for moving.
+        // Compact the stable vec to prepare the storage for moving. After
+        // that, there are no holes left.
         self.make_compact();
+        unsafe { self.storage.into_vec() }
+    }
 
-        // We reset all values to the "empty state" here. This is necessary to
-        // make sure the `drop()` impl doesn't do anything (except for actually
-        // freeing the memory of `deleted`).
-        self.used_count = 0;
-        self.deleted.truncate(0);
+    /// Returns a `Vec<T>` with clones of all existing elements, in index
+    /// order. Unlike [`into_vec()`](#method.into_vec), this doesn't consume
+    /// or compact the stable vector.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&['a', 'b', 'c']);
+    /// sv.remove(1);
+    ///
+    /// assert_eq!(sv.to_vec(), vec!['a', 'c']);
+    /// assert_eq!(sv.next_index(), 3);
+    /// ```
+    pub fn to_vec(&self) -> Vec<T>
+        where T: Clone,
+    {
+        let mut out = Vec::with_capacity(self.num_elements());
+        out.extend(self.iter().cloned());
+        out
+    }
 
-        // The `data` vector is moved out of this data structure and replaced
-        // with an empty vector. After this line, `self` is dropped.
-        mem::replace(&mut self.data, Vec::new())
+    /// Returns a `Vec<(usize, T)>` with the indices and clones of all
+    /// existing elements, in index order. The stable vector is not changed.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&['a', 'b', 'c']);
+    /// sv.remove(1);
+    ///
+    /// assert_eq!(sv.to_indexed_vec(), vec![(0, 'a'), (2, 'c')]);
+    /// ```
+    pub fn to_indexed_vec(&self) -> Vec<(usize, T)>
+        where T: Clone,
+    {
+        let mut out = Vec::with_capacity(self.num_elements());
+        out.extend(self.keys().map(|i| (i, unsafe { self.storage.get_unchecked(i) }.clone())));
+        out
     }
 
     /// Retains only the elements specified by the given predicate.
@@ -766,120 +1744,897 @@ impl<T> StableVec<T> {
     pub fn retain<P>(&mut self, mut predicate: P)
         where P: FnMut(&T) -> bool,
     {
-        let mut it = self.iter_mut();
-        while let Some(e) = it.next() {
-            if !predicate(e) {
-                it.remove_current();
+        for i in 0..self.storage.len() {
+            let keep = match self.get(i) {
+                Some(e) => predicate(e),
+                None => true,
+            };
+            if !keep {
+                self.remove(i);
+            }
+        }
+    }
+
+    /// Like [`retain()`](#method.retain), but passes each removed element to
+    /// `on_remove(index, elem)` instead of dropping it.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[1, 2, 3, 4, 5]);
+    ///
+    /// let mut removed = Vec::new();
+    /// sv.retain_with(|&e| e % 2 == 0, |i, e| removed.push((i, e)));
+    /// assert_eq!(removed, vec![(0, 1), (2, 3), (4, 5)]);
+    /// ```
+    pub fn retain_with<P, F>(&mut self, mut predicate: P, mut on_remove: F)
+        where P: FnMut(&T) -> bool,
+              F: FnMut(usize, T),
+    {
+        for i in 0..self.storage.len() {
+            let keep = match self.get(i) {
+                Some(e) => predicate(e),
+                None => true,
+            };
+            if !keep {
+                if let Some(elem) = self.remove(i) {
+                    on_remove(i, elem);
+                }
+            }
+        }
+    }
+
+    /// Like [`retain()`](#method.retain), but only visits the elements in the
+    /// given index range; all other elements are kept. The predicate gets a
+    /// mutable reference to each element, like `Vec::retain_mut()`.
+    ///
+    /// Parts of the range beyond [`next_index()`](#method.next_index) are
+    /// ignored. Holes are skipped efficiently, so the runtime only depends on
+    /// the length of the range, not on the length of the stable vector.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[1, 2, 3, 4, 5, 6]);
+    /// sv.retain_in_range(2..5, |e| {
+    ///     *e *= 10;
+    ///     *e != 40
+    /// });
+    ///
+    /// assert_eq!(sv, &[1, 2, 30, 50, 6] as &[_]);
+    /// assert_eq!(sv.get(3), None);
+    /// ```
+    pub fn retain_in_range<R, P>(&mut self, range: R, mut predicate: P)
+        where R: RangeBounds<usize>,
+              P: FnMut(&mut T) -> bool,
+    {
+        let mut pos = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i.saturating_add(1),
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => self.next_index(),
+        };
+        let end = end.min(self.next_index());
+
+        while pos < end {
+            let i = match self.storage.next_occupied(pos) {
+                Some(i) if i < end => i,
+                _ => break,
+            };
+            pos = i + 1;
+
+            let keep = predicate(self.get_mut(i).unwrap());
+            if !keep {
+                self.remove(i);
+            }
+        }
+    }
+
+    /// Removes consecutive equal elements and returns the number of removed
+    /// elements.
+    ///
+    /// Holes are skipped: two elements are consecutive if there are only
+    /// holes between them. The first element of each run of equal elements
+    /// is kept; the others are removed, leaving holes.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[1, 1, 2, 3, 2, 2]);
+    /// sv.remove(2);
+    ///
+    /// assert_eq!(sv.dedup(), 2);
+    /// assert_eq!(sv.keys().collect::<Vec<_>>(), vec![0, 3, 4]);
+    /// assert_eq!(sv, &[1, 3, 2] as &[_]);
+    /// ```
+    pub fn dedup(&mut self) -> usize
+        where T: PartialEq,
+    {
+        self.dedup_by(|a, b| a == b)
+    }
+
+    /// Removes consecutive elements which map to the same key and returns
+    /// the number of removed elements. See [`dedup()`](#method.dedup).
+    pub fn dedup_by_key<K, F>(&mut self, mut key: F) -> usize
+        where F: FnMut(&T) -> K,
+              K: PartialEq,
+    {
+        self.dedup_by(|a, b| key(a) == key(b))
+    }
+
+    /// Removes consecutive elements for which `same_bucket` returns `true`
+    /// and returns the number of removed elements. See
+    /// [`dedup()`](#method.dedup).
+    ///
+    /// `same_bucket(a, b)` is called with an element `a` and the last kept
+    /// element `b` before it. If it returns `true`, `a` is removed.
+    pub fn dedup_by<F>(&mut self, mut same_bucket: F) -> usize
+        where F: FnMut(&T, &T) -> bool,
+    {
+        let mut removed = 0;
+        let mut last = match self.storage.next_occupied(0) {
+            Some(i) => i,
+            None => return 0,
+        };
+        while let Some(i) = self.storage.next_occupied(last + 1) {
+            if same_bucket(&self[i], &self[last]) {
+                self.remove(i);
+                removed += 1;
+            } else {
+                last = i;
+            }
+        }
+
+        removed
+    }
+
+    /// Binary searches the existing elements for the given one and returns
+    /// its index. The elements have to be sorted (in the order of
+    /// [`iter()`](#method.iter)).
+    ///
+    /// See [`binary_search_by()`](#method.binary_search_by) for more
+    /// information.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[1, 2, 3, 5, 8]);
+    /// sv.remove(1);
+    ///
+    /// assert_eq!(sv.binary_search(&5), Ok(3));
+    /// assert_eq!(sv.binary_search(&4), Err(3));
+    /// assert_eq!(sv.binary_search(&9), Err(5));
+    /// ```
+    pub fn binary_search(&self, elem: &T) -> Result<usize, usize>
+        where T: Ord,
+    {
+        self.binary_search_by(|e| e.cmp(elem))
+    }
+
+    /// Binary searches the existing elements with the given comparator
+    /// function. The comparator has to return the order of its argument
+    /// relative to the target, and the elements have to be sorted
+    /// accordingly.
+    ///
+    /// If a matching element is found, `Ok` with its index is returned (if
+    /// there are multiple matches, any one of them). Otherwise, `Err` is
+    /// returned with the index of the first element greater than the target,
+    /// or [`next_index()`](#method.next_index) if there is no such element.
+    ///
+    /// If this stable vector is compact, this is O(log n). Otherwise, a
+    /// [`RankIndex`](rank/struct.RankIndex.html) is built first, which is
+    /// O(n).
+    pub fn binary_search_by<F>(&self, mut f: F) -> Result<usize, usize>
+        where F: FnMut(&T) -> Ordering,
+    {
+        let ranks = self.search_ranks();
+        let (mut low, mut high) = (0, self.used_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            match f(&self[self.nth_index(&ranks, mid)]) {
+                Ordering::Less => low = mid + 1,
+                Ordering::Greater => high = mid,
+                Ordering::Equal => return Ok(self.nth_index(&ranks, mid)),
+            }
+        }
+
+        Err(self.nth_index(&ranks, low))
+    }
+
+    /// Binary searches the existing elements with the given key extraction
+    /// function. See [`binary_search_by()`](#method.binary_search_by).
+    pub fn binary_search_by_key<K, F>(&self, key: &K, mut f: F) -> Result<usize, usize>
+        where F: FnMut(&T) -> K,
+              K: Ord,
+    {
+        self.binary_search_by(|e| f(e).cmp(key))
+    }
+
+    /// Returns the index of the first element for which the predicate
+    /// returns `false`, or [`next_index()`](#method.next_index) if there is
+    /// no such element.
+    ///
+    /// The existing elements have to be partitioned: the predicate returns
+    /// `true` for all elements before the returned index and `false` for all
+    /// elements after it. See [`binary_search_by()`](#method.binary_search_by)
+    /// for the time complexity.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[1, 2, 3, 5, 8]);
+    /// sv.remove(2);
+    ///
+    /// assert_eq!(sv.partition_point(|&e| e < 3), 3);
+    /// assert_eq!(sv.partition_point(|&e| e < 10), 5);
+    /// ```
+    pub fn partition_point<P>(&self, mut predicate: P) -> usize
+        where P: FnMut(&T) -> bool,
+    {
+        let ranks = self.search_ranks();
+        let (mut low, mut high) = (0, self.used_count);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if predicate(&self[self.nth_index(&ranks, mid)]) {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+
+        self.nth_index(&ranks, low)
+    }
+
+    /// Returns a rank index for the binary searches, or `None` if this stable
+    /// vector is compact and positions equal indices.
+    fn search_ranks(&self) -> Option<RankIndex> {
+        if self.is_compact() {
+            None
+        } else {
+            Some(self.rank_index())
+        }
+    }
+
+    /// Returns the index of the `n`-th existing element, using the result of
+    /// `search_ranks()`, or `next_index()` if there are no more than `n`
+    /// elements.
+    fn nth_index(&self, ranks: &Option<RankIndex>, n: usize) -> usize {
+        match *ranks {
+            None => n,
+            Some(ref ranks) => ranks.nth_present(n).unwrap_or_else(|| self.next_index()),
+        }
+    }
+
+    /// Converts each element with the given function and returns a stable
+    /// vector of the results, which has the exact same holes and indices as
+    /// this one. The function is called with the index and the element.
+    ///
+    /// As indices stay the same, all indices of this stable vector can be
+    /// used with the returned one.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&["a", "bb", "ccc"]);
+    /// sv.remove(1);
+    ///
+    /// let lengths = sv.map(|_, s| s.len());
+    /// assert_eq!(lengths.keys().collect::<Vec<_>>(), vec![0, 2]);
+    /// assert_eq!(lengths[2], 3);
+    /// ```
+    pub fn map<U, F>(mut self, mut f: F) -> StableVec<U>
+        where F: FnMut(usize, T) -> U,
+    {
+        // `self` is dropped afterwards, so the elements are moved out of the
+        // storage directly, without the bookkeeping of `remove()` (hole
+        // list, generation, journal and counters). The storage doesn't drop
+        // removed slots again.
+        let mut out = StableVec::with_capacity(self.next_index());
+        for i in 0..self.next_index() {
+            if self.storage.has_element_at(i) {
+                let elem = unsafe { self.storage.remove_at(i) };
+                out.push_at_end(f(i, elem));
+            } else {
+                out.push_hole();
+            }
+        }
+        out.push_policy = self.push_policy;
+        out
+    }
+
+    /// Like [`map()`](#method.map), but takes the elements by reference and
+    /// thus leaves this stable vector untouched.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut positions = StableVec::from(&[(1.0, 2.0), (3.0, 4.0)]);
+    /// let id = positions.push((5.0, 6.0));
+    ///
+    /// let sprites = positions.map_ref(|_, &(x, y)| (x as i32 * 16, y as i32 * 16));
+    /// assert_eq!(sprites[id], (80, 96));
+    /// ```
+    pub fn map_ref<U, F>(&self, mut f: F) -> StableVec<U>
+        where F: FnMut(usize, &T) -> U,
+    {
+        let mut out = StableVec::with_capacity(self.next_index());
+        for i in 0..self.next_index() {
+            match self.get(i) {
+                Some(elem) => { out.push_at_end(f(i, elem)); }
+                None => out.push_hole(),
+            }
+        }
+        out.push_policy = self.push_policy;
+        out
+    }
+
+    /// Returns a stable vector with clones of the elements at the given
+    /// indices, which keep their indices. All other slots are holes.
+    ///
+    /// Indices without an element are skipped and duplicates are ignored.
+    /// The `next_index()` of the returned stable vector is one more than the
+    /// largest index with an element, so it might be smaller than the one of
+    /// this stable vector. Use
+    /// [`clone_subset_compact()`](#method.clone_subset_compact) to get a
+    /// stable vector without holes instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let sv = StableVec::from(&['a', 'b', 'c', 'd']);
+    ///
+    /// let subset = sv.clone_subset(vec![2, 0, 7]);
+    /// assert_eq!(subset.keys().collect::<Vec<_>>(), vec![0, 2]);
+    /// assert_eq!(subset[2], 'c');
+    /// assert_eq!(subset.next_index(), 3);
+    /// ```
+    pub fn clone_subset<I>(&self, indices: I) -> StableVec<T>
+        where I: IntoIterator<Item = usize>,
+              T: Clone,
+    {
+        let mut out = StableVec::new();
+        for i in self.subset_indices(indices) {
+            out.set_slot(i, self[i].clone());
+        }
+        out.push_policy = self.push_policy;
+        out
+    }
+
+    /// Like [`clone_subset()`](#method.clone_subset), but returns a compact
+    /// stable vector together with a remap: the element at index `i` of the
+    /// returned stable vector is a clone of the element at index `remap[i]`
+    /// of this one. The elements keep their relative order.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let sv = StableVec::from(&['a', 'b', 'c', 'd']);
+    ///
+    /// let (subset, remap) = sv.clone_subset_compact(vec![3, 1, 3]);
+    /// assert_eq!(subset, &['b', 'd'] as &[_]);
+    /// assert_eq!(remap, vec![1, 3]);
+    /// ```
+    pub fn clone_subset_compact<I>(&self, indices: I) -> (StableVec<T>, Vec<usize>)
+        where I: IntoIterator<Item = usize>,
+              T: Clone,
+    {
+        let remap = self.subset_indices(indices);
+        let mut out = StableVec::with_capacity(remap.len());
+        for &i in &remap {
+            out.push_at_end(self[i].clone());
+        }
+        out.push_policy = self.push_policy;
+        (out, remap)
+    }
+
+    /// Returns the given indices which point to an element, sorted and
+    /// without duplicates.
+    fn subset_indices<I>(&self, indices: I) -> Vec<usize>
+        where I: IntoIterator<Item = usize>,
+    {
+        let mut indices = indices
+            .into_iter()
+            .filter(|&i| self.has_element_at(i))
+            .collect::<Vec<_>>();
+        indices.sort_unstable();
+        indices.dedup();
+        indices
+    }
+
+    /// Moves all elements of `other` into this stable vector, at the same
+    /// indices. At indices where both stable vectors have an element,
+    /// `resolver(index, mine, theirs)` is called and its result is stored.
+    ///
+    /// Holes of this stable vector are filled with the elements of `other`
+    /// and this stable vector is grown if `other` has more slots.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut local = StableVec::from(&[1, 2, 3]);
+    /// local.remove(1);
+    /// let mut remote = StableVec::from(&[10, 20, 30, 40]);
+    /// remote.remove(2);
+    ///
+    /// local.merge_with(remote, |_, mine, theirs| mine.max(theirs));
+    /// assert_eq!(local, &[10, 20, 3, 40] as &[_]);
+    /// ```
+    pub fn merge_with<S2, F>(&mut self, mut other: StableVecFacade<T, S2>, mut resolver: F)
+        where S2: Storage<T>,
+              F: FnMut(usize, T, T) -> T,
+    {
+        while self.storage.len() < other.next_index() {
+            self.push_hole();
+        }
+
+        for i in 0..other.next_index() {
+            if let Some(theirs) = other.remove(i) {
+                if !self.storage.has_element_at(i) {
+                    self.set_slot(i, theirs);
+                    continue;
+                }
+
+                // The resolver takes our element by value, so the slot is a
+                // hole (outside of `free_slots`) until it's refilled.
+                let mine = unsafe { self.storage.remove_at(i) };
+                self.used_count -= 1;
+                let elem = resolver(i, mine, theirs);
+                unsafe {
+                    self.storage.insert_at(i, elem);
+                }
+                self.used_count += 1;
+                self.record(i, ChangeKind::Modified);
             }
         }
     }
 }
 
-impl<T> Drop for StableVec<T> {
-    fn drop(&mut self) {
-        // We need to drop all elements that have not been removed. We can't
-        // just run Vec's drop impl for `self.data` because this would attempt
-        // to drop already dropped values. However, the Vec still needs to
-        // free its memory.
-        //
-        // To achieve all this, we manually drop all remaining elements, then
-        // tell the Vec that its length is 0 (its capacity stays the same!) and
-        // let the Vec drop itself in the end.
-        let living_indices = self.deleted.iter()
-            .enumerate()
-            .filter_map(|(i, deleted)| if deleted { None } else { Some(i) });
-        for i in living_indices {
-            unsafe {
-                ptr::drop_in_place(&mut self.data[i]);
+/// Methods which are only available with the default storage.
+impl<T> StableVec<T> {
+    /// Returns the elements in the given index range as a contiguous slice,
+    /// or `None` if the range contains a hole or reaches beyond
+    /// [`next_index()`](#method.next_index).
+    ///
+    /// Other parts of the stable vector may very well contain holes: only the
+    /// requested range has to be free of them. This method is only available
+    /// with the default storage.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[0, 1, 2, 3, 4, 5]);
+    /// sv.remove(1);
+    ///
+    /// assert_eq!(sv.slice(2..5), Some(&[2, 3, 4][..]));
+    /// assert_eq!(sv.slice(2..), Some(&[2, 3, 4, 5][..]));
+    /// assert_eq!(sv.slice(0..3), None); // contains the hole at index 1
+    /// assert_eq!(sv.slice(4..9), None); // out of bounds
+    /// ```
+    pub fn slice<R>(&self, range: R) -> Option<&[T]>
+        where R: RangeBounds<usize>
+    {
+        // The range doesn't contain any holes, so all slots are initialized.
+        // `MaybeUninit<T>` has the same layout as `T`.
+        self.hole_free_range(range).map(move |r| unsafe {
+            &*(&self.storage.data[r] as *const [MaybeUninit<T>] as *const [T])
+        })
+    }
+
+    /// Returns the elements in the given index range as a contiguous mutable
+    /// slice, or `None` if the range contains a hole or reaches beyond
+    /// [`next_index()`](#method.next_index).
+    ///
+    /// See [`slice()`](#method.slice) for more information.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// let mut sv = StableVec::from(&[0, 1, 2, 3]);
+    /// sv.remove(0);
+    ///
+    /// for e in sv.slice_mut(1..).unwrap() {
+    ///     *e *= 10;
+    /// }
+    /// assert_eq!(sv, &[10, 20, 30] as &[_]);
+    /// ```
+    pub fn slice_mut<R>(&mut self, range: R) -> Option<&mut [T]>
+        where R: RangeBounds<usize>
+    {
+        match self.hole_free_range(range) {
+            // See `slice()`.
+            Some(r) => {
+                for i in r.clone() {
+                    self.record(i, ChangeKind::Modified);
+                }
+                Some(unsafe {
+                    &mut *(&mut self.storage.data[r] as *mut [MaybeUninit<T>] as *mut [T])
+                })
             }
+            None => None,
         }
+    }
 
-        unsafe {
-            self.data.set_len(0);
+    /// Converts the given range bounds into a `Range<usize>`, but only if this
+    /// range is in bounds and does not contain any holes.
+    fn hole_free_range<R>(&self, range: R) -> Option<Range<usize>>
+        where R: RangeBounds<usize>
+    {
+        let start = match range.start_bound() {
+            Bound::Included(&i) => i,
+            Bound::Excluded(&i) => i.checked_add(1)?,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&i) => i.checked_add(1)?,
+            Bound::Excluded(&i) => i,
+            Bound::Unbounded => self.next_index(),
+        };
+
+        if start > end || end > self.next_index() {
+            return None;
+        }
+
+        // If all elements exist, we don't have to check each index.
+        if self.is_compact() || (start..end).all(|i| self.has_element_at(i)) {
+            Some(start..end)
+        } else {
+            None
+        }
+    }
+}
+
+/// Determines where [`StableVec::push()`](struct.StableVecFacade.html#method.push)
+/// inserts new elements.
+///
+/// Set it with
+/// [`StableVec::with_push_policy()`](struct.StableVecFacade.html#method.with_push_policy)
+/// or [`StableVec::set_push_policy()`](struct.StableVecFacade.html#method.set_push_policy).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum PushPolicy {
+    /// Always append to the back (the default). The indices returned by
+    /// `push()` are strictly increasing and equal to
+    /// [`next_index()`](struct.StableVecFacade.html#method.next_index).
+    #[default]
+    Append,
+
+    /// Fill the most recently created hole first, like
+    /// [`push_reuse()`](struct.StableVecFacade.html#method.push_reuse). This
+    /// is O(1) (amortized).
+    PreferHoles,
+
+    /// Fill the hole with the lowest index first. This keeps the elements
+    /// dense at the front, but has to search for the first hole, which is
+    /// O(n) in the worst case.
+    PreferLowest,
+}
+
+/// Methods which are only available with the segmented storage.
+impl<T> SegmentedStableVec<T> {
+    /// Returns a pointer to the element at the given index, or `None` if
+    /// there exists no element at that index.
+    ///
+    /// As the segmented storage never moves elements, the pointer stays valid
+    /// until the element is removed (or moved by one of the compacting
+    /// methods) or the stable vector is dropped. In particular, it stays
+    /// valid while other elements are pushed. You must not dereference it
+    /// while a mutable reference to the same element exists, though.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::SegmentedStableVec;
+    /// let mut sv = SegmentedStableVec::new();
+    /// let first = sv.push(1);
+    /// let ptr = sv.get_ptr(first).unwrap();
+    ///
+    /// for i in 0..1000 {
+    ///     sv.push(i);
+    /// }
+    ///
+    /// assert_eq!(unsafe { *ptr }, 1);
+    /// ```
+    pub fn get_ptr(&self, index: usize) -> Option<*const T> {
+        self.get(index).map(|elem| elem as *const T)
+    }
+
+    /// Returns a mutable pointer to the element at the given index, or `None`
+    /// if there exists no element at that index. The pointer stays valid as
+    /// described for [`get_ptr()`](#method.get_ptr).
+    pub fn get_mut_ptr(&mut self, index: usize) -> Option<*mut T> {
+        self.get_mut(index).map(|elem| elem as *mut T)
+    }
+}
+
+/// Methods which are only available with the array storage.
+impl<T, const N: usize> ArrayStableVec<T, N> {
+    /// Returns `true` if all `N` slots are in use (by elements or holes).
+    ///
+    /// Pushing might still succeed if the push policy allows filling holes;
+    /// use [`try_push()`](#method.try_push) to find out.
+    pub fn is_full(&self) -> bool {
+        self.storage.len() == N
+    }
+
+    /// Like [`push()`](#method.push), but returns the element instead of
+    /// panicking if there is no slot left for it.
+    pub fn try_push(&mut self, elem: T) -> Result<usize, T> {
+        if self.next_push_index() == N {
+            return Err(elem);
+        }
+        Ok(self.push(elem))
+    }
+}
+
+/// Methods which are only available with the small storage.
+impl<T, const N: usize> SmallStableVec<T, N> {
+    /// Returns `true` if the slots have been moved to the heap. Once spilled,
+    /// they stay there.
+    pub fn spilled(&self) -> bool {
+        self.storage.spilled()
+    }
+}
+
+/// Methods which are only available with a custom allocator.
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator> AllocStableVec<T, A> {
+    /// Constructs a new, empty stable vector which allocates its memory from
+    /// `alloc`. Doesn't allocate until elements are pushed onto it.
+    ///
+    /// Only available with the `allocator_api` feature, which requires a
+    /// nightly compiler.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// #![feature(allocator_api)]
+    /// # extern crate stable_vec;
+    /// use stable_vec::AllocStableVec;
+    /// use std::alloc::Global;
+    ///
+    /// # fn main() {
+    /// let mut sv = AllocStableVec::new_in(Global);
+    /// let a = sv.push('a');
+    /// assert_eq!(sv[a], 'a');
+    /// # }
+    /// ```
+    pub fn new_in(alloc: A) -> Self {
+        Self::from_storage(AllocStorage::new_in(alloc))
+    }
+
+    /// Constructs a new, empty stable vector with the specified capacity,
+    /// which allocates its memory from `alloc`.
+    pub fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self::from_storage(AllocStorage::with_capacity_in(capacity, alloc))
+    }
+
+    /// Returns a reference to the allocator.
+    pub fn allocator(&self) -> &A {
+        self.storage.allocator()
+    }
+}
+
+/// The reason why there is no element at a given index.
+///
+/// Returned by [`StableVec::get_checked()`](struct.StableVecFacade.html#method.get_checked)
+/// and [`StableVec::get_mut_checked()`](struct.StableVecFacade.html#method.get_mut_checked).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AccessError {
+    /// The index is in bounds, but the element at that index was removed.
+    Hole,
+
+    /// The index is not smaller than
+    /// [`next_index()`](struct.StableVecFacade.html#method.next_index).
+    OutOfBounds,
+}
+
+impl fmt::Display for AccessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            AccessError::Hole => write!(f, "the element at this index was removed"),
+            AccessError::OutOfBounds => write!(f, "index out of bounds"),
         }
     }
 }
 
-impl<T> Index<usize> for StableVec<T> {
+#[cfg(feature = "std")]
+impl ::std::error::Error for AccessError {}
+
+impl<T, S: Storage<T>> Index<usize> for StableVecFacade<T, S> {
     type Output = T;
 
     fn index(&self, index: usize) -> &T {
         assert!(self.has_element_at(index));
 
-        &self.data[index]
+        unsafe { self.storage.get_unchecked(index) }
     }
 }
 
-impl<T> IndexMut<usize> for StableVec<T> {
+impl<T, S: Storage<T>> IndexMut<usize> for StableVecFacade<T, S> {
     fn index_mut(&mut self, index: usize) -> &mut T {
         assert!(self.has_element_at(index));
+        self.record(index, ChangeKind::Modified);
 
-        &mut self.data[index]
+        unsafe { self.storage.get_unchecked_mut(index) }
     }
 }
 
-impl<T> Default for StableVec<T> {
+impl<T, S: NewStorage<T>> Default for StableVecFacade<T, S> {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl<T, S> From<S> for StableVec<T>
-    where S: AsRef<[T]>,
-          T: Clone
-{
-    fn from(slice: S) -> Self {
-        let len = slice.as_ref().len();
-        Self {
-            data: slice.as_ref().into(),
-            deleted: BitVec::from_elem(len, false),
-            used_count: len,
+impl<T: Clone, S: NewStorage<T>> Clone for StableVecFacade<T, S> {
+    fn clone(&self) -> Self {
+        // We can't just clone the storage: the removed elements in there must
+        // not be touched anymore.
+        let mut out = Self::with_capacity(self.next_index());
+        for i in 0..self.next_index() {
+            match self.get(i) {
+                Some(elem) => { out.push_at_end(elem.clone()); }
+                None => out.push_hole(),
+            }
         }
+        out.push_policy = self.push_policy;
+        out
     }
 }
 
-impl<T> FromIterator<T> for StableVec<T> {
+/// Two stable vectors are equal if they have the same `next_index()` and
+/// equal elements at the same indices. In particular, holes have to be at the
+/// same positions.
+impl<T: PartialEq, S: Storage<T>> PartialEq for StableVecFacade<T, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.next_index() == other.next_index()
+            && self.used_count == other.used_count
+            && (0..self.next_index()).all(|i| self.get(i) == other.get(i))
+    }
+}
+
+impl<T: Eq, S: Storage<T>> Eq for StableVecFacade<T, S> {}
+
+impl<T, S, A> From<A> for StableVecFacade<T, S>
+    where A: AsRef<[T]>,
+          T: Clone,
+          S: NewStorage<T>,
+{
+    fn from(slice: A) -> Self {
+        slice.as_ref().iter().cloned().collect()
+    }
+}
+
+impl<T, S: NewStorage<T>> FromIterator<T> for StableVecFacade<T, S> {
     fn from_iter<I>(iter: I) -> Self
         where I: IntoIterator<Item = T>
     {
-        let data = Vec::from_iter(iter);
-        Self {
-            used_count: data.len(),
-            deleted: BitVec::from_elem(data.len(), false),
-            data,
-        }
+        let mut out = Self::new();
+        out.extend(iter);
+        out
     }
 }
 
-impl<T> Extend<T> for StableVec<T> {
+impl<T, S: Storage<T>> Extend<T> for StableVecFacade<T, S> {
     fn extend<I>(&mut self, iter: I)
         where I: IntoIterator<Item = T>
     {
-        // This implementation is not completely exception safe. If the
-        // `self.data.extend()` call panics, we won't drop any of the new
-        // elements. This is "safe" in the Rust meaning of the word: not
-        // calling `drop()` on values is not desireable but not considered
-        // *unsafe*.
-        let len_before = self.data.len();
-        self.data.extend(iter);
-
-        let additional_count = self.data.len() - len_before;
-        self.deleted.grow(additional_count, false);
-        self.used_count += additional_count;
+        let iter = iter.into_iter();
+        self.reserve(iter.size_hint().0);
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+/// Extends the stable vector with copies of the referenced elements, just like
+/// the corresponding impl for `Vec<T>`.
+///
+/// # Example
+///
+/// ```
+/// # use stable_vec::StableVec;
+/// let mut sv = StableVec::from(&[1, 2]);
+/// sv.extend(&[3, 4]);
+///
+/// assert_eq!(sv, &[1, 2, 3, 4] as &[_]);
+/// ```
+impl<'a, T, S> Extend<&'a T> for StableVecFacade<T, S>
+    where T: 'a + Copy,
+          S: Storage<T>,
+{
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = &'a T>
+    {
+        self.extend(iter.into_iter().cloned());
     }
 }
 
-impl<'a, T> IntoIterator for &'a StableVec<T> {
+/// Converts the stable vector into a map from the stable indices to the
+/// existing elements. Holes are simply not present in the map.
+///
+/// # Example
+///
+/// ```
+/// # use stable_vec::StableVec;
+/// use std::collections::HashMap;
+///
+/// let mut sv = StableVec::from(&['a', 'b', 'c']);
+/// sv.remove(1);
+///
+/// let map: HashMap<_, _> = sv.into();
+/// assert_eq!(map.len(), 2);
+/// assert_eq!(map[&0], 'a');
+/// assert_eq!(map[&2], 'c');
+/// ```
+#[cfg(feature = "std")]
+impl<T, S, H> From<StableVecFacade<T, S>> for HashMap<usize, T, H>
+    where S: Storage<T>,
+          H: BuildHasher + Default,
+{
+    fn from(mut sv: StableVecFacade<T, S>) -> Self {
+        let mut map = HashMap::with_capacity_and_hasher(sv.num_elements(), H::default());
+        for i in 0..sv.next_index() {
+            if let Some(elem) = sv.remove(i) {
+                map.insert(i, elem);
+            }
+        }
+        map
+    }
+}
+
+/// Converts the stable vector into a map from the stable indices to the
+/// existing elements. Holes are simply not present in the map.
+///
+/// # Example
+///
+/// ```
+/// # use stable_vec::StableVec;
+/// use std::collections::BTreeMap;
+///
+/// let mut sv = StableVec::from(&['a', 'b', 'c']);
+/// sv.remove(1);
+///
+/// let map = BTreeMap::from(sv);
+/// assert_eq!(map.into_iter().collect::<Vec<_>>(), vec![(0, 'a'), (2, 'c')]);
+/// ```
+impl<T, S: Storage<T>> From<StableVecFacade<T, S>> for BTreeMap<usize, T> {
+    fn from(mut sv: StableVecFacade<T, S>) -> Self {
+        let mut map = BTreeMap::new();
+        for i in 0..sv.next_index() {
+            if let Some(elem) = sv.remove(i) {
+                map.insert(i, elem);
+            }
+        }
+        map
+    }
+}
+
+impl<'a, T, S: Storage<T>> IntoIterator for &'a StableVecFacade<T, S> {
     type Item = &'a T;
-    type IntoIter = Iter<'a, T>;
+    type IntoIter = Iter<'a, T, S>;
     fn into_iter(self) -> Self::IntoIter {
         self.iter()
     }
 }
 
-impl<'a, T> IntoIterator for &'a mut StableVec<T> {
+impl<'a, T, S: Storage<T>> IntoIterator for &'a mut StableVecFacade<T, S> {
     type Item = &'a mut T;
-    type IntoIter = IterMut<'a, T>;
+    type IntoIter = IterMut<'a, T, S>;
     fn into_iter(self) -> Self::IntoIter {
         self.iter_mut()
     }
@@ -887,37 +2642,38 @@ impl<'a, T> IntoIterator for &'a mut StableVec<T> {
 
 /// Iterator over immutable references to the elements of a `StableVec`.
 ///
-/// Use the method [`StableVec::iter()`](struct.StableVec.html#method.iter) or
+/// Use the method [`StableVec::iter()`](struct.StableVecFacade.html#method.iter) or
 /// the `IntoIterator` implementation of `&StableVec` to obtain an iterator
 /// of this kind.
-pub struct Iter<'a, T: 'a> {
-    sv: &'a StableVec<T>,
+pub struct Iter<'a, T: 'a, S: 'a + Storage<T> = BitmapStorage<T>> {
+    sv: &'a StableVecFacade<T, S>,
     pos: usize,
 }
 
-impl<'a, T: 'a> Iterator for Iter<'a, T> {
+impl<'a, T: 'a, S: Storage<T>> Iterator for Iter<'a, T, S> {
     type Item = &'a T;
     fn next(&mut self) -> Option<Self::Item> {
-        next_valid_index(&mut self.pos, &self.sv.deleted)
-            .map(|i| &self.sv.data[i])
+        let sv = self.sv;
+        next_valid_index(&mut self.pos, &sv.storage)
+            .map(|i| unsafe { sv.storage.get_unchecked(i) })
     }
 }
 
 /// Iterator over mutable references to the elements of a `StableVec`.
 ///
-/// Use the method [`StableVec::iter_mut()`](struct.StableVec.html#method.iter_mut)
+/// Use the method [`StableVec::iter_mut()`](struct.StableVecFacade.html#method.iter_mut)
 /// or the `IntoIterator` implementation of `&mut StableVec` to obtain an
 /// iterator of this kind.
-pub struct IterMut<'a, T: 'a> {
-    deleted: &'a mut BitVec,
-    used_count: &'a mut usize,
-    vec_iter: ::std::slice::IterMut<'a, T>,
+pub struct IterMut<'a, T: 'a, S: 'a + Storage<T> = BitmapStorage<T>> {
+    sv: &'a mut StableVecFacade<T, S>,
     pos: usize,
 }
 
 impl<'a, T: 'a> IterMut<'a, T> {
     /// Removes the element that was returned by the last `next()` call from
-    /// the underlying stable vector.
+    /// the underlying stable vector. The element is not dropped. This is only
+    /// available with the default storage, as other storages can't mark a
+    /// slot as removed without touching the element.
     ///
     /// # Panic
     ///
@@ -926,84 +2682,74 @@ impl<'a, T: 'a> IterMut<'a, T> {
     pub fn remove_current(&mut self) {
         assert!(self.pos != 0);
 
-        self.deleted.set(self.pos - 1, true);
-        *self.used_count -= 1;
+        self.sv.storage.deleted.set(self.pos - 1, true);
+        self.sv.used_count -= 1;
+        #[cfg(feature = "stats")]
+        {
+            self.sv.metrics.removals += 1;
+        }
+        self.sv.generation = self.sv.generation.wrapping_add(1);
+        self.sv.push_free_slot(self.pos - 1);
+        self.sv.record(self.pos - 1, ChangeKind::Removed);
     }
 }
 
-impl<'a, T> Iterator for IterMut<'a, T> {
+impl<'a, T, S: Storage<T>> Iterator for IterMut<'a, T, S> {
     type Item = &'a mut T;
 
     fn next(&mut self) -> Option<Self::Item> {
-        // First, we advance until we have found an existing element or until
-        // we have reached the end of all elements.
-        while self.pos < self.deleted.len() && self.deleted[self.pos] {
-            self.pos += 1;
-            self.vec_iter.next();
-        }
+        next_valid_index(&mut self.pos, &self.sv.storage).map(|i| {
+            self.sv.record(i, ChangeKind::Modified);
 
-        // Next, we check whether we are at the very end.
-        if self.pos == self.deleted.len() {
-            None
-        } else {
-            // Advance the iterator by one and return current element.
-            self.pos += 1;
-            self.vec_iter.next()
-        }
+            // Each slot is yielded at most once, so the returned references
+            // never alias.
+            unsafe { &mut *(self.sv.storage.get_unchecked_mut(i) as *mut T) }
+        })
     }
 }
 
 /// Iterator over all valid indices of a `StableVec`.
 ///
-/// Use the method [`StableVec::keys()`](struct.StableVec.html#method.keys) to
+/// Use the method [`StableVec::keys()`](struct.StableVecFacade.html#method.keys) to
 /// obtain an iterator of this kind.
-pub struct Keys<'a> {
-    deleted: &'a BitVec,
+pub struct Keys<'a, T: 'a, S: 'a + Storage<T> = BitmapStorage<T>> {
+    sv: &'a StableVecFacade<T, S>,
     pos: usize,
 }
 
-impl<'a> Iterator for Keys<'a> {
+impl<'a, T, S: Storage<T>> Iterator for Keys<'a, T, S> {
     type Item = usize;
     fn next(&mut self) -> Option<Self::Item> {
-        next_valid_index(&mut self.pos, self.deleted)
+        next_valid_index(&mut self.pos, &self.sv.storage)
     }
 }
 
-/// Advances the index `pos` while it points to a deleted element. Stops
-/// advancing once an existing element is found or the end is reached. In the
-/// former case, this element's index is returned; in the latter case, `None`
-/// is returned.
-///
-/// After this function was called, the value of `pos` is:
-///
-/// - `i + 1` if `Some(i)` was returned
-/// - `deleted.len()` if `None` was returned
-fn next_valid_index(pos: &mut usize, deleted: &BitVec) -> Option<usize> {
-    // First, we advance until we have found an existing element or until
-    // we have reached the end of all elements.
-    while *pos < deleted.len() && deleted[*pos] {
-        *pos += 1;
-    }
-
-    // Next, we check whether we are at the very end.
-    if *pos == deleted.len() {
-        None
-    } else {
-        // Advance by one and return current position.
-        *pos += 1;
-        Some(*pos - 1)
+/// Returns the index of the first existing element at an index `>= pos` and
+/// sets `pos` to the index after it. If there is no such element, `None` is
+/// returned and `pos` is set to the number of slots.
+fn next_valid_index<T, S: Storage<T>>(pos: &mut usize, storage: &S) -> Option<usize> {
+    match storage.next_occupied(*pos) {
+        Some(i) => {
+            *pos = i + 1;
+            Some(i)
+        }
+        None => {
+            *pos = storage.len();
+            None
+        }
     }
 }
 
-impl<T: fmt::Debug> fmt::Debug for StableVec<T> {
+impl<T: fmt::Debug, S: Storage<T>> fmt::Debug for StableVecFacade<T, S> {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(f, "StableVec ")?;
         f.debug_list().entries(self).finish()
     }
 }
 
-impl<A, B> PartialEq<[B]> for StableVec<A>
+impl<A, B, S> PartialEq<[B]> for StableVecFacade<A, S>
     where A: PartialEq<B>,
+          S: Storage<A>,
 {
     fn eq(&self, other: &[B]) -> bool {
         for (i, e) in self.iter().enumerate() {
@@ -1015,18 +2761,96 @@ impl<A, B> PartialEq<[B]> for StableVec<A>
     }
 }
 
-impl<'other, A, B> PartialEq<&'other [B]> for StableVec<A>
+impl<'other, A, B, S> PartialEq<&'other [B]> for StableVecFacade<A, S>
     where A: PartialEq<B>,
+          S: Storage<A>,
 {
     fn eq(&self, other: & &'other [B]) -> bool {
         self == *other
     }
 }
 
-impl<A, B> PartialEq<Vec<B>> for StableVec<A>
+impl<A, B, S> PartialEq<Vec<B>> for StableVecFacade<A, S>
     where A: PartialEq<B>,
+          S: Storage<A>,
 {
     fn eq(&self, other: &Vec<B>) -> bool {
         self == &other[..]
     }
 }
+
+/// Generates stable vectors with a random hole pattern (requires the
+/// `arbitrary` feature).
+///
+/// First, a hole ratio is chosen, then each slot is a hole with that
+/// probability. This results in compact as well as very sparse stable
+/// vectors, with holes at the beginning, in the middle and at the end.
+#[cfg(feature = "arbitrary")]
+impl<'a, T> arbitrary::Arbitrary<'a> for StableVec<T>
+    where T: arbitrary::Arbitrary<'a>
+{
+    fn arbitrary(u: &mut arbitrary::Unstructured<'a>) -> arbitrary::Result<Self> {
+        let hole_ratio: u8 = u.arbitrary()?;
+        let len = u.arbitrary_len::<T>()?;
+
+        let mut sv = StableVec::with_capacity(len);
+        for _ in 0..len {
+            if hole_ratio > 0 && u.ratio(hole_ratio, u8::MAX)? {
+                sv.push_hole();
+            } else {
+                sv.push(T::arbitrary(u)?);
+            }
+        }
+
+        Ok(sv)
+    }
+}
+
+/// Generates stable vectors with a random hole pattern (requires the
+/// `quickcheck` feature).
+///
+/// Shrinking first removes single elements (turning their slots into holes,
+/// thus all other indices stay the same) and then shrinks single elements.
+#[cfg(feature = "quickcheck")]
+impl<T> quickcheck::Arbitrary for StableVec<T>
+    where T: quickcheck::Arbitrary
+{
+    fn arbitrary<G: quickcheck::Gen>(g: &mut G) -> Self {
+        let size = g.size();
+        let len = g.gen_range(0, size + 1);
+        let hole_ratio: f64 = g.gen();
+
+        let mut sv = StableVec::with_capacity(len);
+        for _ in 0..len {
+            if g.gen::<f64>() < hole_ratio {
+                sv.push_hole();
+            } else {
+                sv.push(T::arbitrary(g));
+            }
+        }
+        sv
+    }
+
+    fn shrink(&self) -> Box<dyn Iterator<Item = Self>> {
+        let indices: Vec<_> = self.keys().collect();
+
+        let sv = self.clone();
+        let removals = indices.clone().into_iter().map(move |i| {
+            let mut out = sv.clone();
+            out.remove(i);
+            out
+        });
+
+        let sv = self.clone();
+        let shrunk_elements = indices.into_iter().flat_map(move |i| {
+            let sv = sv.clone();
+            sv[i].shrink().map(move |elem| {
+                let mut out = sv.clone();
+                out[i] = elem;
+                out
+            })
+        });
+
+        Box::new(removals.chain(shrunk_elements))
+    }
+}
@@ -0,0 +1,322 @@
+//! A stable vector which remembers the insertion order of its elements.
+//!
+//! See [`LinkedStableVec`](struct.LinkedStableVec.html) for more
+//! information.
+
+use core::fmt;
+use core::iter::FromIterator;
+use core::ops::{Index, IndexMut};
+
+use {PushPolicy, StableVec};
+
+
+/// An element together with its neighbours in insertion order.
+#[derive(Clone)]
+struct Entry<T> {
+    elem: T,
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+/// A stable vector whose iterators visit the elements in insertion order.
+///
+/// As soon as holes are reused (see
+/// [`PushPolicy`](../enum.PushPolicy.html)), index order doesn't match
+/// insertion order anymore. This stable vector threads a doubly linked list
+/// through its slots, so that [`iter()`](#method.iter) and
+/// [`keys()`](#method.keys) visit the elements in the order they were
+/// pushed, similar to `IndexMap` but with stable indices. Removing an element
+/// is still O(1). The links need two additional `Option<usize>` per slot.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::{LinkedStableVec, PushPolicy};
+///
+/// let mut sv = LinkedStableVec::with_push_policy(PushPolicy::PreferHoles);
+/// let a = sv.push('a');
+/// sv.push('b');
+/// sv.remove(a);
+/// let c = sv.push('c');
+/// assert_eq!(c, a); // the hole was reused...
+///
+/// // ... but 'c' is still visited last.
+/// assert_eq!(sv.iter().collect::<String>(), "bc");
+/// assert_eq!(sv.keys().collect::<Vec<_>>(), vec![1, 0]);
+/// ```
+pub struct LinkedStableVec<T> {
+    inner: StableVec<Entry<T>>,
+
+    /// The oldest and the newest element.
+    head: Option<usize>,
+    tail: Option<usize>,
+}
+
+impl<T> LinkedStableVec<T> {
+    /// Constructs a new, empty `LinkedStableVec<T>`.
+    pub fn new() -> Self {
+        Self::with_push_policy(PushPolicy::Append)
+    }
+
+    /// Constructs a new, empty `LinkedStableVec<T>` with the given push
+    /// policy.
+    pub fn with_push_policy(policy: PushPolicy) -> Self {
+        Self {
+            inner: StableVec::with_push_policy(policy),
+            head: None,
+            tail: None,
+        }
+    }
+
+    /// Inserts a new element as the newest one and returns its index. See
+    /// [`StableVec::push()`](../struct.StableVecFacade.html#method.push).
+    pub fn push(&mut self, elem: T) -> usize {
+        let index = self.inner.push(Entry {
+            elem,
+            prev: self.tail,
+            next: None,
+        });
+
+        match self.tail {
+            Some(tail) => self.inner[tail].next = Some(index),
+            None => self.head = Some(index),
+        }
+        self.tail = Some(index);
+        index
+    }
+
+    /// Removes and returns the element at the given index, or returns `None`
+    /// if there exists no element at that index. This is O(1).
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        let Entry { elem, prev, next } = self.inner.remove(index)?;
+        match prev {
+            Some(prev) => self.inner[prev].next = next,
+            None => self.head = next,
+        }
+        match next {
+            Some(next) => self.inner[next].prev = prev,
+            None => self.tail = prev,
+        }
+        Some(elem)
+    }
+
+    /// Returns the index of the oldest element, or `None` if there are no
+    /// elements.
+    pub fn first_index(&self) -> Option<usize> {
+        self.head
+    }
+
+    /// Returns the index of the newest element, or `None` if there are no
+    /// elements.
+    pub fn last_index(&self) -> Option<usize> {
+        self.tail
+    }
+
+    /// Returns a reference to the element at the given index, or `None` if
+    /// there exists no element at that index.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.inner.get(index).map(|e| &e.elem)
+    }
+
+    /// Returns a mutable reference to the element at the given index, or
+    /// `None` if there exists no element at that index.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        self.inner.get_mut(index).map(|e| &mut e.elem)
+    }
+
+    /// Returns `true` if there exists an element at the given index.
+    pub fn has_element_at(&self, index: usize) -> bool {
+        self.inner.has_element_at(index)
+    }
+
+    /// Returns the number of existing elements.
+    pub fn num_elements(&self) -> usize {
+        self.inner.num_elements()
+    }
+
+    /// Returns `true` if there are no existing elements.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns the number of slots (elements and holes). See
+    /// [`StableVec::next_index()`](../struct.StableVecFacade.html#method.next_index).
+    pub fn next_index(&self) -> usize {
+        self.inner.next_index()
+    }
+
+    /// Returns an iterator over immutable references to the existing
+    /// elements, in insertion order.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            sv: self,
+            next: self.head,
+            remaining: self.num_elements(),
+        }
+    }
+
+    /// Returns an iterator over mutable references to the existing elements,
+    /// in insertion order.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        IterMut {
+            next: self.head,
+            remaining: self.num_elements(),
+            sv: self,
+        }
+    }
+
+    /// Returns an iterator over the indices of all existing elements, in
+    /// insertion order.
+    pub fn keys(&self) -> Keys<'_, T> {
+        Keys {
+            sv: self,
+            next: self.head,
+            remaining: self.num_elements(),
+        }
+    }
+}
+
+impl<T> Index<usize> for LinkedStableVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.inner[index].elem
+    }
+}
+
+impl<T> IndexMut<usize> for LinkedStableVec<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        &mut self.inner[index].elem
+    }
+}
+
+impl<T> Default for LinkedStableVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone> Clone for LinkedStableVec<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            head: self.head,
+            tail: self.tail,
+        }
+    }
+}
+
+impl<T> FromIterator<T> for LinkedStableVec<T> {
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item = T>,
+    {
+        let mut out = Self::new();
+        out.extend(iter);
+        out
+    }
+}
+
+impl<T> Extend<T> for LinkedStableVec<T> {
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = T>,
+    {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for LinkedStableVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "LinkedStableVec ")?;
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over immutable references to the elements of a
+/// `LinkedStableVec`, in insertion order.
+///
+/// Use the method [`LinkedStableVec::iter()`](struct.LinkedStableVec.html#method.iter)
+/// to obtain an iterator of this kind.
+pub struct Iter<'a, T: 'a> {
+    sv: &'a LinkedStableVec<T>,
+    next: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = &self.sv.inner[self.next?];
+        self.next = entry.next;
+        self.remaining -= 1;
+        Some(&entry.elem)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Iter<'a, T> {}
+
+/// Iterator over mutable references to the elements of a `LinkedStableVec`,
+/// in insertion order.
+///
+/// Use the method
+/// [`LinkedStableVec::iter_mut()`](struct.LinkedStableVec.html#method.iter_mut)
+/// to obtain an iterator of this kind.
+pub struct IterMut<'a, T: 'a> {
+    sv: &'a mut LinkedStableVec<T>,
+    next: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for IterMut<'a, T> {
+    type Item = &'a mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.sv.inner.get_mut(self.next?).unwrap();
+        self.next = entry.next;
+        self.remaining -= 1;
+
+        // The list visits each slot at most once, so the returned references
+        // never alias.
+        Some(unsafe { &mut *(&mut entry.elem as *mut T) })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for IterMut<'a, T> {}
+
+/// Iterator over the indices of the elements of a `LinkedStableVec`, in
+/// insertion order.
+///
+/// Use the method [`LinkedStableVec::keys()`](struct.LinkedStableVec.html#method.keys)
+/// to obtain an iterator of this kind.
+pub struct Keys<'a, T: 'a> {
+    sv: &'a LinkedStableVec<T>,
+    next: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Keys<'a, T> {
+    type Item = usize;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let index = self.next?;
+        self.next = self.sv.inner[index].next;
+        self.remaining -= 1;
+        Some(index)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, T> ExactSizeIterator for Keys<'a, T> {}
@@ -0,0 +1,285 @@
+//! The set of indices at which a stable vector has elements.
+//!
+//! [`StableVec::occupancy()`](../struct.StableVecFacade.html#method.occupancy)
+//! returns an [`Occupancy`](struct.Occupancy.html), a bitset with one bit
+//! per slot. Bitsets of different stable vectors can be combined with the
+//! usual set operations. This is useful if several stable vectors share the
+//! same indices, e.g. one vector per component type in an entity component
+//! system.
+//!
+//! Like a [`RankIndex`](../rank/struct.RankIndex.html), the bitset captures
+//! the occupancy at the time it was created.
+//!
+//! Any [`IndexSet`](trait.IndexSet.html), including an `Occupancy`, can be
+//! passed to
+//! [`retain_indices()`](../struct.StableVecFacade.html#method.retain_indices)
+//! to remove all elements whose index is not in the set.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! // Entity 1 was never given a position; entity 2 has no velocity.
+//! let mut positions = StableVec::from(&[(0, 0), (1, 1), (2, 2)]);
+//! positions.remove(1);
+//! let mut velocities = StableVec::from(&[(1, 0), (0, 1), (1, 1)]);
+//! velocities.remove(2);
+//!
+//! let moving = positions.occupancy().intersection(&velocities.occupancy());
+//! assert_eq!(moving.iter().collect::<Vec<_>>(), vec![0]);
+//!
+//! let any = positions.occupancy().union(&velocities.occupancy());
+//! assert_eq!(any.iter().collect::<Vec<_>>(), vec![0, 1, 2]);
+//! ```
+
+use alloc::collections::BTreeSet;
+use alloc::vec::Vec;
+#[cfg(feature = "std")]
+use std::collections::HashSet;
+#[cfg(feature = "std")]
+use std::hash::BuildHasher;
+
+use StableVecFacade;
+use storage::Storage;
+
+
+/// The number of slots covered by one block.
+pub(crate) const BLOCK_BITS: usize = u64::BITS as usize;
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Returns the set of indices at which this stable vector has elements, as
+    /// an [`Occupancy`](occupancy/struct.Occupancy.html) bitset. This is O(n)
+    /// and needs one bit per slot.
+    pub fn occupancy(&self) -> Occupancy {
+        let len = self.next_index();
+        let mut blocks = vec![0u64; len.div_ceil(BLOCK_BITS)];
+        let mut index = 0;
+        while let Some(i) = self.storage.next_occupied(index) {
+            blocks[i / BLOCK_BITS] |= 1 << (i % BLOCK_BITS);
+            index = i + 1;
+        }
+
+        Occupancy { blocks, len }
+    }
+
+    /// Removes all elements whose index is not in the given set, in one pass
+    /// over the existing elements.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// # use stable_vec::StableVec;
+    /// use std::collections::BTreeSet;
+    ///
+    /// let mut sv = StableVec::from(&['a', 'b', 'c', 'd']);
+    /// let selected = [1, 3, 7].iter().cloned().collect::<BTreeSet<usize>>();
+    /// sv.retain_indices(&selected);
+    ///
+    /// assert_eq!(sv.keys().collect::<Vec<_>>(), vec![1, 3]);
+    /// ```
+    pub fn retain_indices<I>(&mut self, set: &I)
+        where I: IndexSet + ?Sized,
+    {
+        let mut index = 0;
+        while let Some(i) = self.storage.next_occupied(index) {
+            if !set.contains_index(i) {
+                self.remove(i);
+            }
+            index = i + 1;
+        }
+    }
+}
+
+/// A set of indices, e.g. to be passed to
+/// [`StableVec::retain_indices()`](../struct.StableVecFacade.html#method.retain_indices).
+pub trait IndexSet {
+    /// Returns `true` if the given index is in this set.
+    fn contains_index(&self, index: usize) -> bool;
+}
+
+impl IndexSet for Occupancy {
+    fn contains_index(&self, index: usize) -> bool {
+        self.contains(index)
+    }
+}
+
+/// The indices of all `true` values.
+impl IndexSet for [bool] {
+    fn contains_index(&self, index: usize) -> bool {
+        self.get(index).cloned().unwrap_or(false)
+    }
+}
+
+/// The indices of all `true` values.
+impl IndexSet for Vec<bool> {
+    fn contains_index(&self, index: usize) -> bool {
+        self[..].contains_index(index)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<H: BuildHasher> IndexSet for HashSet<usize, H> {
+    fn contains_index(&self, index: usize) -> bool {
+        self.contains(&index)
+    }
+}
+
+impl IndexSet for BTreeSet<usize> {
+    fn contains_index(&self, index: usize) -> bool {
+        self.contains(&index)
+    }
+}
+
+/// A set of indices, stored as a bitset.
+///
+/// Created by
+/// [`StableVec::occupancy()`](../struct.StableVecFacade.html#method.occupancy).
+/// See [the module documentation](index.html) for more information.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub struct Occupancy {
+    /// One bit per slot. All bits at indices `>= len` are zero.
+    pub(crate) blocks: Vec<u64>,
+
+    /// The number of slots covered by this set.
+    pub(crate) len: usize,
+}
+
+impl Occupancy {
+    /// Creates a set covering `len` slots from its raw bitset: index `i` is in
+    /// the set if bit `i % 64` of `blocks[i / 64]` is set. Bits at indices `>=
+    /// len` are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `blocks` doesn't contain exactly `ceil(len / 64)` blocks.
+    pub fn from_blocks(mut blocks: Vec<u64>, len: usize) -> Self {
+        assert_eq!(blocks.len(), len.div_ceil(BLOCK_BITS), "wrong number of blocks");
+        let unused = blocks.len() * BLOCK_BITS - len;
+        if let Some(last) = blocks.last_mut() {
+            *last &= !0 >> unused;
+        }
+
+        Occupancy { blocks, len }
+    }
+
+    /// Returns the raw bitset, in the format described for
+    /// [`from_blocks()`](#method.from_blocks).
+    pub fn as_blocks(&self) -> &[u64] {
+        &self.blocks
+    }
+
+    /// Returns the number of slots covered by this set, i.e. the
+    /// `next_index()` of the stable vector it was created for. All indices in
+    /// the set are smaller than this.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if this set covers no slots.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of indices in this set.
+    pub fn count(&self) -> usize {
+        self.blocks.iter().map(|b| b.count_ones() as usize).sum()
+    }
+
+    /// Returns `true` if the given index is in this set.
+    pub fn contains(&self, index: usize) -> bool {
+        index < self.len && self.blocks[index / BLOCK_BITS] & (1 << (index % BLOCK_BITS)) != 0
+    }
+
+    /// Returns the set of indices which are in both `self` and `other`.
+    pub fn intersection(&self, other: &Occupancy) -> Occupancy {
+        let mut out = self.clone();
+        out.intersect_with(other);
+        out
+    }
+
+    /// Returns the set of indices which are in `self`, `other` or both.
+    pub fn union(&self, other: &Occupancy) -> Occupancy {
+        let mut out = self.clone();
+        out.union_with(other);
+        out
+    }
+
+    /// Returns the set of indices which are in `self` but not in `other`.
+    pub fn difference(&self, other: &Occupancy) -> Occupancy {
+        let mut out = self.clone();
+        out.difference_with(other);
+        out
+    }
+
+    /// Removes all indices which are not in `other` from this set.
+    pub fn intersect_with(&mut self, other: &Occupancy) {
+        self.len = self.len.min(other.len);
+        self.blocks.truncate(other.blocks.len());
+        for (a, b) in self.blocks.iter_mut().zip(&other.blocks) {
+            *a &= *b;
+        }
+    }
+
+    /// Adds all indices of `other` to this set.
+    pub fn union_with(&mut self, other: &Occupancy) {
+        self.len = self.len.max(other.len);
+        if self.blocks.len() < other.blocks.len() {
+            self.blocks.resize(other.blocks.len(), 0);
+        }
+        for (a, b) in self.blocks.iter_mut().zip(&other.blocks) {
+            *a |= *b;
+        }
+    }
+
+    /// Removes all indices of `other` from this set.
+    pub fn difference_with(&mut self, other: &Occupancy) {
+        for (a, b) in self.blocks.iter_mut().zip(&other.blocks) {
+            *a &= !*b;
+        }
+    }
+
+    /// Returns an iterator over all indices in this set, in ascending order.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            blocks: &self.blocks,
+            block: 0,
+            bits: self.blocks.first().cloned().unwrap_or(0),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Occupancy {
+    type Item = usize;
+    type IntoIter = Iter<'a>;
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Iterator over the indices in an `Occupancy`.
+///
+/// Use the method [`Occupancy::iter()`](struct.Occupancy.html#method.iter)
+/// to obtain an iterator of this kind.
+pub struct Iter<'a> {
+    blocks: &'a [u64],
+
+    /// The block `bits` belongs to.
+    block: usize,
+
+    /// The bits of the current block which haven't been yielded yet.
+    bits: u64,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = usize;
+    fn next(&mut self) -> Option<Self::Item> {
+        while self.bits == 0 {
+            self.block += 1;
+            self.bits = *self.blocks.get(self.block)?;
+        }
+
+        let index = self.block * BLOCK_BITS + self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1;
+        Some(index)
+    }
+}
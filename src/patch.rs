@@ -0,0 +1,189 @@
+//! Compact patches between two states of a stable vector.
+//!
+//! A [`Patch`](struct.Patch.html) records which indices were inserted,
+//! removed or modified between a baseline and a current state. Applying the
+//! patch to a copy of the baseline restores the current state, including all
+//! holes. This is useful to replicate a stable vector without resending the
+//! full data every time.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! use stable_vec::patch::{Change, Patch};
+//!
+//! let baseline = StableVec::from(&['a', 'b', 'c']);
+//! let mut replica = baseline.clone();
+//!
+//! let mut current = baseline.clone();
+//! current.remove(0);
+//! current[1] = 'x';
+//! current.push('d');
+//!
+//! let patch = Patch::between(&baseline, &current);
+//! assert_eq!(patch.changes(), &[
+//!     (0, Change::Removed),
+//!     (1, Change::Modified('x')),
+//!     (3, Change::Inserted('d')),
+//! ]);
+//!
+//! patch.apply_to(&mut replica);
+//! assert_eq!(replica.get(0), None);
+//! assert_eq!(replica, &['x', 'c', 'd'] as &[_]);
+//! ```
+
+use alloc::vec::Vec;
+use core::cmp;
+#[cfg(feature = "std")]
+use std::io::{self, Read, Write};
+
+#[cfg(feature = "std")]
+use snapshot::{Decode, Encode};
+use StableVec;
+
+
+/// A change of a single slot, as recorded in a [`Patch`](struct.Patch.html).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Change<T> {
+    /// The slot was empty in the baseline and now contains the given element.
+    Inserted(T),
+
+    /// The slot contained an element in the baseline and is empty now.
+    Removed,
+
+    /// The slot contains an element in both states, but the element is not
+    /// equal to the baseline element anymore.
+    Modified(T),
+}
+
+/// The differences between two states of a stable vector.
+///
+/// See [the module documentation](index.html) for more information.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Patch<T> {
+    /// The `next_index()` of the current state.
+    next_index: usize,
+
+    /// All changes, sorted by index.
+    changes: Vec<(usize, Change<T>)>,
+}
+
+impl<T: Clone + PartialEq> Patch<T> {
+    /// Computes the patch that turns `baseline` into `current`.
+    ///
+    /// This has a time complexity of O(n) where n is the larger of the two
+    /// `next_index()` values.
+    pub fn between(baseline: &StableVec<T>, current: &StableVec<T>) -> Self {
+        let len = cmp::max(baseline.next_index(), current.next_index());
+        let changes = (0..len)
+            .filter_map(|i| {
+                let change = match (baseline.get(i), current.get(i)) {
+                    (None, Some(new)) => Change::Inserted(new.clone()),
+                    (Some(_), None) => Change::Removed,
+                    (Some(old), Some(new)) if old != new => Change::Modified(new.clone()),
+                    _ => return None,
+                };
+                Some((i, change))
+            })
+            .collect();
+
+        Self {
+            next_index: current.next_index(),
+            changes,
+        }
+    }
+}
+
+impl<T> Patch<T> {
+    /// Returns all recorded changes, sorted by index.
+    pub fn changes(&self) -> &[(usize, Change<T>)] {
+        &self.changes
+    }
+
+    /// Returns `true` if this patch doesn't contain any changes.
+    ///
+    /// Note that applying an empty patch might still change the
+    /// `next_index()` of the target: the current state might have been
+    /// compacted or trailing holes might have been added.
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+
+    /// Returns the `next_index()` the target will have after applying this
+    /// patch.
+    pub fn next_index(&self) -> usize {
+        self.next_index
+    }
+
+    /// Applies all changes of this patch to `target`.
+    ///
+    /// If `target` is equal to the baseline this patch was computed from,
+    /// `target` is equal to the current state afterwards. Otherwise, each
+    /// change is applied as well as possible: removing a hole does nothing
+    /// and inserting into an occupied slot overwrites the element.
+    pub fn apply_to(self, target: &mut StableVec<T>) {
+        for (i, change) in self.changes {
+            match change {
+                Change::Inserted(elem) | Change::Modified(elem) => target.set_slot(i, elem),
+                Change::Removed => { target.remove(i); }
+            }
+        }
+
+        target.truncate(self.next_index);
+        while target.next_index() < self.next_index {
+            target.push_hole();
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Encode> Encode for Patch<T> {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.next_index.encode(writer)?;
+        self.changes.len().encode(writer)?;
+        for &(i, ref change) in &self.changes {
+            match *change {
+                Change::Inserted(ref elem) => {
+                    0u8.encode(writer)?;
+                    i.encode(writer)?;
+                    elem.encode(writer)?;
+                }
+                Change::Removed => {
+                    1u8.encode(writer)?;
+                    i.encode(writer)?;
+                }
+                Change::Modified(ref elem) => {
+                    2u8.encode(writer)?;
+                    i.encode(writer)?;
+                    elem.encode(writer)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Decode> Decode for Patch<T> {
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let next_index = usize::decode(reader)?;
+        let count = usize::decode(reader)?;
+
+        // We don't trust `count` to preallocate memory: it might be garbage.
+        let mut changes = Vec::new();
+        for _ in 0..count {
+            let tag = u8::decode(reader)?;
+            let i = usize::decode(reader)?;
+            let change = match tag {
+                0 => Change::Inserted(T::decode(reader)?),
+                1 => Change::Removed,
+                2 => Change::Modified(T::decode(reader)?),
+                _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid change tag")),
+            };
+            changes.push((i, change));
+        }
+
+        Ok(Self { next_index, changes })
+    }
+}
@@ -0,0 +1,198 @@
+//! Proptest strategies (requires the `proptest` feature).
+//!
+//! The main entry point is the function [`stable_vec()`](fn.stable_vec.html)
+//! which creates stable vectors with a controllable amount of holes.
+//!
+//! ```
+//! # #[macro_use] extern crate proptest;
+//! # extern crate stable_vec;
+//! use proptest::prelude::*;
+//! use stable_vec::StableVec;
+//!
+//! proptest! {
+//!     # #![proptest_config(ProptestConfig::with_cases(16))]
+//!     fn compact_keeps_elements(
+//!         mut sv in stable_vec::proptest::stable_vec(any::<u8>(), 0..50, 0.3),
+//!     ) {
+//!         let before: Vec<_> = sv.iter().cloned().collect();
+//!         sv.make_compact();
+//!         prop_assert_eq!(sv.into_vec(), before);
+//!     }
+//! }
+//! # fn main() { compact_keeps_elements(); }
+//! ```
+
+use proptest_crate::collection::SizeRange;
+use proptest_crate::prelude::Rng;
+use proptest_crate::strategy::{NewTree, Strategy, ValueTree};
+use proptest_crate::test_runner::TestRunner;
+
+use alloc::vec::Vec;
+use core::fmt;
+
+use StableVec;
+
+
+/// Creates a strategy generating stable vectors whose
+/// [`next_index()`](../struct.StableVecFacade.html#method.next_index) lies in
+/// `size` and whose slots are holes with a probability of `hole_ratio`.
+/// Existing elements are generated by `element`.
+///
+/// When shrinking, elements are first removed one after another (turning
+/// their slots into holes) and then shrunk individually. Shrinking never
+/// changes `next_index()` or the index of any remaining element, so indices
+/// your test stored while generating inputs stay meaningful.
+///
+/// # Panics
+///
+/// Panics if `size` is empty or if `hole_ratio` is not between 0 and 1.
+pub fn stable_vec<S>(element: S, size: impl Into<SizeRange>, hole_ratio: f64)
+    -> StableVecStrategy<S>
+    where S: Strategy
+{
+    let size = size.into();
+    assert!(size.start() <= size.end_incl(), "size range is empty");
+    assert!(
+        (0.0..=1.0).contains(&hole_ratio),
+        "hole_ratio has to be between 0 and 1, but is {}",
+        hole_ratio,
+    );
+
+    StableVecStrategy { element, size, hole_ratio }
+}
+
+/// Strategy to create stable vectors. Created by
+/// [`stable_vec()`](fn.stable_vec.html).
+#[derive(Clone, Debug)]
+pub struct StableVecStrategy<S> {
+    element: S,
+    size: SizeRange,
+    hole_ratio: f64,
+}
+
+impl<S: Strategy> Strategy for StableVecStrategy<S> {
+    type Tree = StableVecValueTree<S::Tree>;
+    type Value = StableVec<S::Value>;
+
+    fn new_tree(&self, runner: &mut TestRunner) -> NewTree<Self> {
+        let (start, end) = self.size.start_end_incl();
+        let len = runner.rng().random_range(start..=end);
+
+        let mut slots = Vec::with_capacity(len);
+        for _ in 0..len {
+            if runner.rng().random_bool(self.hole_ratio) {
+                slots.push(None);
+            } else {
+                slots.push(Some(self.element.new_tree(runner)?));
+            }
+        }
+
+        Ok(StableVecValueTree {
+            slots,
+            shrink: Shrink::RemoveElement(0),
+            prev_shrink: None,
+        })
+    }
+}
+
+/// The next shrinking step of a `StableVecValueTree`.
+#[derive(Clone, Copy, Debug)]
+enum Shrink {
+    /// Try to remove the first element at an index `>= .0`.
+    RemoveElement(usize),
+
+    /// Try to shrink the first element at an index `>= .0`.
+    ShrinkElement(usize),
+}
+
+/// The last successful shrinking step of a `StableVecValueTree`, used to undo
+/// that step in `complicate()`.
+#[derive(Clone, Debug)]
+enum PrevShrink<T> {
+    Removed(usize, T),
+    Shrunk(usize),
+}
+
+/// `ValueTree` corresponding to `StableVecStrategy`.
+#[derive(Clone)]
+pub struct StableVecValueTree<T> {
+    /// `None` represents a hole.
+    slots: Vec<Option<T>>,
+    shrink: Shrink,
+    prev_shrink: Option<PrevShrink<T>>,
+}
+
+impl<T: ValueTree> ValueTree for StableVecValueTree<T> {
+    type Value = StableVec<T::Value>;
+
+    fn current(&self) -> Self::Value {
+        let mut sv = StableVec::with_capacity(self.slots.len());
+        for slot in &self.slots {
+            match *slot {
+                Some(ref tree) => { sv.push(tree.current()); }
+                None => sv.push_hole(),
+            }
+        }
+        sv
+    }
+
+    fn simplify(&mut self) -> bool {
+        if let Shrink::RemoveElement(start) = self.shrink {
+            if let Some(i) = self.next_element_from(start) {
+                let tree = self.slots[i].take().unwrap();
+                self.prev_shrink = Some(PrevShrink::Removed(i, tree));
+                self.shrink = Shrink::RemoveElement(i + 1);
+                return true;
+            }
+
+            self.shrink = Shrink::ShrinkElement(0);
+        }
+
+        if let Shrink::ShrinkElement(mut start) = self.shrink {
+            while let Some(i) = self.next_element_from(start) {
+                if self.slots[i].as_mut().unwrap().simplify() {
+                    self.prev_shrink = Some(PrevShrink::Shrunk(i));
+                    self.shrink = Shrink::ShrinkElement(i);
+                    return true;
+                }
+                start = i + 1;
+            }
+
+            self.shrink = Shrink::ShrinkElement(self.slots.len());
+        }
+
+        false
+    }
+
+    fn complicate(&mut self) -> bool {
+        match self.prev_shrink.take() {
+            // The removed element was necessary for the failure. We keep it
+            // and continue with the next element in `simplify()`.
+            Some(PrevShrink::Removed(i, tree)) => {
+                self.slots[i] = Some(tree);
+                true
+            }
+            Some(PrevShrink::Shrunk(i)) if self.slots[i].as_mut().unwrap().complicate() => {
+                self.prev_shrink = Some(PrevShrink::Shrunk(i));
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+impl<T> StableVecValueTree<T> {
+    /// Returns the first index `>= start` which is not a hole.
+    fn next_element_from(&self, start: usize) -> Option<usize> {
+        (start..self.slots.len()).find(|&i| self.slots[i].is_some())
+    }
+}
+
+impl<T> fmt::Debug for StableVecValueTree<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StableVecValueTree")
+            .field("len", &self.slots.len())
+            .field("shrink", &self.shrink)
+            .finish()
+    }
+}
@@ -0,0 +1,99 @@
+//! Random sampling of existing elements (requires the `rand` feature).
+//!
+//! Picking a random index and retrying while it points to a hole is only
+//! fast if there are few holes, and picking a random element of `iter()`
+//! needs to count the elements first. The methods in this module sample
+//! uniformly among the existing elements, choosing the fastest strategy for
+//! the given hole density.
+//!
+//! ```
+//! # extern crate rand;
+//! # extern crate stable_vec;
+//! # use stable_vec::StableVec;
+//! use rand::SeedableRng;
+//! use rand::rngs::SmallRng;
+//!
+//! # fn main() {
+//! let mut rng = SmallRng::seed_from_u64(7);
+//! let mut sv = StableVec::from(&['a', 'b', 'c', 'd']);
+//! sv.remove(1);
+//!
+//! let (index, elem) = sv.choose(&mut rng).unwrap();
+//! assert_eq!(sv.get(index), Some(elem));
+//!
+//! let chosen = sv.choose_multiple(&mut rng, 2);
+//! assert_eq!(chosen.len(), 2);
+//! assert!(chosen.iter().all(|&(i, _)| i != 1));
+//! # }
+//! ```
+
+use rand_crate::Rng;
+use rand_crate::seq::index;
+
+use alloc::vec::Vec;
+
+use StableVecFacade;
+use storage::Storage;
+
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Returns a uniformly chosen existing element together with its index,
+    /// or `None` if there are no elements.
+    ///
+    /// This is O(1) on average if at least half of the slots contain
+    /// elements and O(n) otherwise. See [the `rand` module](rand/index.html).
+    pub fn choose<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(usize, &T)> {
+        let index = self.choose_index(rng)?;
+        Some((index, unsafe { self.storage.get_unchecked(index) }))
+    }
+
+    /// Like [`choose()`](#method.choose), but returns a mutable reference.
+    pub fn choose_mut<R: Rng + ?Sized>(&mut self, rng: &mut R) -> Option<(usize, &mut T)> {
+        let index = self.choose_index(rng)?;
+        self.get_mut(index).map(|elem| (index, elem))
+    }
+
+    /// Returns `amount` distinct existing elements (or all of them, if there
+    /// are fewer) together with their indices, in ascending index order. Each
+    /// subset of that size is equally likely. This is O(n).
+    pub fn choose_multiple<R>(&self, rng: &mut R, amount: usize) -> Vec<(usize, &T)>
+        where R: Rng + ?Sized,
+    {
+        let num_elements = self.num_elements();
+        let amount = amount.min(num_elements);
+        let mut ranks = index::sample(rng, num_elements, amount).into_vec();
+        ranks.sort_unstable();
+
+        // Walk over the elements once, picking those with a chosen rank.
+        let mut chosen = Vec::with_capacity(amount);
+        let mut ranks = ranks.into_iter().peekable();
+        for (rank, index) in self.keys().enumerate() {
+            if ranks.peek() == Some(&rank) {
+                ranks.next();
+                chosen.push((index, unsafe { self.storage.get_unchecked(index) }));
+            }
+        }
+        chosen
+    }
+
+    /// Returns the index of a uniformly chosen existing element.
+    fn choose_index<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<usize> {
+        let num_elements = self.num_elements();
+        if num_elements == 0 {
+            return None;
+        }
+
+        if 2 * num_elements >= self.next_index() {
+            // At least half of all slots are occupied, so we need at most two
+            // tries on average.
+            loop {
+                let index = rng.random_range(0..self.next_index());
+                if self.storage.has_element_at(index) {
+                    return Some(index);
+                }
+            }
+        } else {
+            self.keys().nth(rng.random_range(0..num_elements))
+        }
+    }
+}
@@ -0,0 +1,131 @@
+//! Mapping between stable indices and positions among the existing elements.
+//!
+//! A stable vector with holes has two kinds of positions for each element:
+//! its stable index and its position in the sequence of existing elements
+//! (e.g. its position in a dense copy like a GPU buffer). A
+//! [`RankIndex`](struct.RankIndex.html) converts between both in O(1)
+//! ([`rank()`](struct.RankIndex.html#method.rank)) and O(log n)
+//! ([`nth_present()`](struct.RankIndex.html#method.nth_present)).
+//!
+//! The index is built on demand and captures the occupancy at that time.
+//! After inserting or removing elements, build a new one.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! let mut sv = StableVec::from(&['a', 'b', 'c', 'd']);
+//! sv.remove(1);
+//!
+//! let ranks = sv.rank_index();
+//! assert_eq!(ranks.nth_present(1), Some(2)); // 'c' is the second element
+//! assert_eq!(ranks.rank(3), 2); // 'd' is preceded by two elements
+//! assert_eq!(ranks.nth_present(3), None);
+//! ```
+
+use alloc::vec::Vec;
+
+use StableVecFacade;
+use occupancy::{Occupancy, BLOCK_BITS};
+use storage::Storage;
+
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Builds a [`RankIndex`](rank/struct.RankIndex.html) for the current
+    /// occupancy of this stable vector. This is O(n) and needs about two bits
+    /// per slot.
+    pub fn rank_index(&self) -> RankIndex {
+        let Occupancy { blocks, len } = self.occupancy();
+
+        let mut preceding = Vec::with_capacity(blocks.len());
+        let mut count = 0;
+        for block in &blocks {
+            preceding.push(count);
+            count += block.count_ones() as usize;
+        }
+
+        RankIndex {
+            blocks,
+            preceding,
+            len,
+            num_elements: count,
+        }
+    }
+}
+
+/// Answers rank and select queries about the occupancy of a stable vector.
+///
+/// Created by
+/// [`StableVec::rank_index()`](../struct.StableVecFacade.html#method.rank_index).
+/// See [the module documentation](index.html) for more information.
+#[derive(Clone, Debug)]
+pub struct RankIndex {
+    /// One bit per slot, set for existing elements.
+    blocks: Vec<u64>,
+
+    /// The number of existing elements before each block.
+    preceding: Vec<usize>,
+
+    /// The `next_index()` of the stable vector.
+    len: usize,
+
+    /// The number of existing elements.
+    num_elements: usize,
+}
+
+impl RankIndex {
+    /// Returns the `next_index()` of the stable vector when this index was
+    /// built.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the stable vector didn't contain any slots when this
+    /// index was built.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the number of existing elements when this index was built.
+    pub fn num_elements(&self) -> usize {
+        self.num_elements
+    }
+
+    /// Returns the number of existing elements at indices smaller than
+    /// `index`. This is O(1).
+    ///
+    /// If there is an element at `index`, this is its position among all
+    /// existing elements. Indices `>= len()` return `num_elements()`.
+    pub fn rank(&self, index: usize) -> usize {
+        if index >= self.len {
+            return self.num_elements;
+        }
+
+        let block = index / BLOCK_BITS;
+        let below = (1u64 << (index % BLOCK_BITS)) - 1;
+        self.preceding[block] + (self.blocks[block] & below).count_ones() as usize
+    }
+
+    /// Returns the index of the `n`-th existing element (counting from 0), or
+    /// `None` if there are no more than `n` elements. This is O(log n).
+    ///
+    /// This is the inverse of [`rank()`](#method.rank).
+    pub fn nth_present(&self, n: usize) -> Option<usize> {
+        if n >= self.num_elements {
+            return None;
+        }
+
+        // The last block with at most `n` elements before it contains the
+        // element we are looking for.
+        let block = self.preceding.partition_point(|&count| count <= n) - 1;
+
+        // Clear the lowest set bits until the one we are looking for is the
+        // lowest one.
+        let mut bits = self.blocks[block];
+        for _ in 0..n - self.preceding[block] {
+            bits &= bits - 1;
+        }
+
+        Some(block * BLOCK_BITS + bits.trailing_zeros() as usize)
+    }
+}
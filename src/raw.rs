@@ -0,0 +1,111 @@
+//! Decomposing a stable vector into its raw parts and back.
+//!
+//! Like `Vec::from_raw_parts()`, this allows handing the buffer of a
+//! [`StableVec`](../type.StableVec.html) to other code (e.g. across an FFI
+//! boundary) and reconstructing the stable vector later without copying the
+//! elements. [`into_raw_parts()`](../struct.StableVecFacade.html#method.into_raw_parts)
+//! returns a [`RawParts`](struct.RawParts.html), which consists of the
+//! buffer, its length and capacity and the
+//! [`Occupancy`](../occupancy/struct.Occupancy.html) saying which slots of
+//! the buffer contain elements.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! let mut sv = StableVec::from(&[1, 2, 3]);
+//! sv.remove(1);
+//!
+//! let parts = sv.into_raw_parts();
+//! assert_eq!(parts.len, 3);
+//! assert_eq!(unsafe { *parts.ptr.add(2) }, 3);
+//!
+//! let sv = unsafe { StableVec::from_raw_parts(parts) };
+//! assert_eq!(sv.keys().collect::<Vec<_>>(), vec![0, 2]);
+//! ```
+
+use alloc::vec::Vec;
+use core::mem::{self, MaybeUninit};
+
+use bit_vec::BitVec;
+
+use StableVec;
+use occupancy::Occupancy;
+use storage::BitmapStorage;
+
+
+/// The raw parts of a [`StableVec`](../type.StableVec.html).
+///
+/// Created by
+/// [`StableVec::into_raw_parts()`](../struct.StableVecFacade.html#method.into_raw_parts).
+/// See [the module documentation](index.html) for more information.
+#[derive(Debug)]
+pub struct RawParts<T> {
+    /// The buffer, allocated by the global allocator with the layout of a
+    /// `Vec<T>` with the given capacity.
+    pub ptr: *mut T,
+
+    /// The number of slots (elements and holes), i.e. the `next_index()` of
+    /// the stable vector.
+    pub len: usize,
+
+    /// The number of slots the buffer has room for.
+    pub capacity: usize,
+
+    /// The indices of the slots which contain an initialized element. All
+    /// other slots are uninitialized.
+    pub occupancy: Occupancy,
+}
+
+impl<T> StableVec<T> {
+    /// Decomposes this stable vector into its raw parts. The elements are
+    /// not dropped; the caller is responsible for them and the buffer, e.g.
+    /// by calling [`from_raw_parts()`](#method.from_raw_parts) later.
+    ///
+    /// Only available with the default storage. See [the `raw`
+    /// module](raw/index.html).
+    pub fn into_raw_parts(mut self) -> RawParts<T> {
+        let occupancy = self.occupancy();
+        let mut data = mem::take(&mut self.storage.data);
+        self.storage.deleted = BitVec::new();
+
+        let parts = RawParts {
+            ptr: data.as_mut_ptr() as *mut T,
+            len: data.len(),
+            capacity: data.capacity(),
+            occupancy,
+        };
+        mem::forget(data);
+        parts
+    }
+
+    /// Reconstructs a stable vector from its raw parts. The push policy is
+    /// reset to the default and all elements are treated as inserted in index
+    /// order, i.e. the generation starts at 0 again.
+    ///
+    /// Only available with the default storage. See [the `raw`
+    /// module](raw/index.html).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `parts.occupancy.len()` differs from `parts.len`.
+    ///
+    /// # Safety
+    ///
+    /// `Vec::from_raw_parts(parts.ptr, parts.len, parts.capacity)` has to be
+    /// safe (except that the slots which are not in `parts.occupancy` may be
+    /// uninitialized), which is the case if the parts were returned by
+    /// [`into_raw_parts()`](#method.into_raw_parts) and the occupancy
+    /// describes which slots are initialized.
+    pub unsafe fn from_raw_parts(parts: RawParts<T>) -> Self {
+        let RawParts { ptr, len, capacity, occupancy } = parts;
+        assert_eq!(occupancy.len(), len, "occupancy doesn't match the number of slots");
+
+        let data = Vec::from_raw_parts(ptr as *mut MaybeUninit<T>, len, capacity);
+        let deleted = BitVec::from_fn(len, |i| !occupancy.contains(i));
+        let mut sv = Self::from_storage(BitmapStorage { data, deleted });
+        sv.used_count = occupancy.count();
+        sv.free_slots = (0..len).filter(|&i| !occupancy.contains(i)).collect();
+        sv
+    }
+}
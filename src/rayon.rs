@@ -0,0 +1,429 @@
+//! Rayon parallel iterators (requires the `rayon` feature).
+//!
+//! `&StableVec<T>` and `&mut StableVec<T>` implement `IntoParallelIterator`,
+//! so `par_iter()` and `par_iter_mut()` from rayon's prelude can be used.
+//! Both iterators yield the index together with the element. Work is split
+//! by index range; holes are simply skipped.
+//!
+//! ```
+//! # extern crate rayon;
+//! # extern crate stable_vec;
+//! # use stable_vec::StableVec;
+//! use rayon::prelude::*;
+//!
+//! # fn main() {
+//! let mut sv = StableVec::from(&[1usize, 2, 3, 4]);
+//! sv.remove(1);
+//!
+//! sv.par_iter_mut().for_each(|(i, e)| *e *= i);
+//! assert_eq!(sv, &[0usize, 6, 12] as &[_]);
+//!
+//! let sum: usize = sv.par_iter().map(|(_, e)| *e).sum();
+//! assert_eq!(sum, 18);
+//! # }
+//! ```
+//!
+//! Additionally, `StableVec<T>` implements `FromParallelIterator` and
+//! `ParallelExtend`, so parallel producers can feed a stable vector directly.
+//! Like with `Vec<T>`, the order of the produced elements is preserved.
+//!
+//! ```
+//! # extern crate rayon;
+//! # extern crate stable_vec;
+//! # use stable_vec::StableVec;
+//! use rayon::prelude::*;
+//!
+//! # fn main() {
+//! let mut sv: StableVec<_> = (0..4).into_par_iter().map(|i| i * 2).collect();
+//! sv.remove(0);
+//! sv.par_extend((4..6).into_par_iter().map(|i| i * 2));
+//!
+//! assert_eq!(sv, &[2, 4, 6, 8, 10] as &[_]);
+//! assert_eq!(sv.next_index(), 6);
+//! # }
+//! ```
+
+use bit_vec::BitVec;
+use rayon_crate::iter::plumbing::{bridge_unindexed, Folder, UnindexedConsumer, UnindexedProducer};
+use rayon_crate::iter::{
+    FromParallelIterator, IntoParallelIterator, ParallelExtend, ParallelIterator,
+};
+
+use alloc::vec::Vec;
+use core::mem::{self, MaybeUninit};
+use core::{ptr, slice};
+
+use StableVec;
+use journal::ChangeKind;
+
+
+impl<'a, T: Sync> IntoParallelIterator for &'a StableVec<T> {
+    type Item = (usize, &'a T);
+    type Iter = ParIter<'a, T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIter { sv: self }
+    }
+}
+
+impl<'a, T: Send> IntoParallelIterator for &'a mut StableVec<T> {
+    type Item = (usize, &'a mut T);
+    type Iter = ParIterMut<'a, T>;
+
+    fn into_par_iter(self) -> Self::Iter {
+        ParIterMut { sv: self }
+    }
+}
+
+impl<T: Send> FromParallelIterator<T> for StableVec<T> {
+    fn from_par_iter<I>(par_iter: I) -> Self
+        where I: IntoParallelIterator<Item = T>
+    {
+        // Collecting into a `Vec` first is done in parallel and converting the
+        // `Vec` afterwards reuses its allocation.
+        Vec::from_par_iter(par_iter).into_iter().collect()
+    }
+}
+
+impl<T: Send> ParallelExtend<T> for StableVec<T> {
+    fn par_extend<I>(&mut self, par_iter: I)
+        where I: IntoParallelIterator<Item = T>
+    {
+        let mut new_elements = Vec::new();
+        new_elements.par_extend(par_iter);
+        self.extend(new_elements);
+    }
+}
+
+impl<T: Send> StableVec<T> {
+    /// Retains only the elements specified by the given predicate, evaluating
+    /// the predicate in parallel.
+    ///
+    /// This is the parallel version of [`retain()`](../struct.StableVecFacade.html#method.retain):
+    /// each element `e` for which `predicate(&e)` returns `false` is removed.
+    /// The predicate is evaluated in parallel for all elements, then the
+    /// removals are applied in a final serial pass.
+    ///
+    /// ```
+    /// # extern crate stable_vec;
+    /// # use stable_vec::StableVec;
+    /// # fn main() {
+    /// let mut sv: StableVec<_> = (0..10).collect();
+    /// sv.par_retain(|&e| e % 3 == 0);
+    ///
+    /// assert_eq!(sv, &[0, 3, 6, 9] as &[_]);
+    /// assert_eq!(sv.get(3), Some(&3));
+    /// # }
+    /// ```
+    pub fn par_retain<P>(&mut self, predicate: P)
+        where P: Fn(&T) -> bool + Sync,
+              T: Sync,
+    {
+        let to_remove: Vec<_> = (&*self).into_par_iter()
+            .filter(|&(_, e)| !predicate(e))
+            .map(|(i, _)| i)
+            .collect();
+
+        for i in to_remove {
+            self.remove(i);
+        }
+    }
+
+    /// Removes all elements from the stable vector and returns them (together
+    /// with their indices) as a parallel iterator.
+    ///
+    /// Afterwards, the stable vector is empty and
+    /// [`next_index()`](../struct.StableVecFacade.html#method.next_index) returns
+    /// 0. The capacity stays the same. Elements which are not consumed by the
+    /// parallel iterator are dropped.
+    ///
+    /// ```
+    /// # extern crate rayon;
+    /// # extern crate stable_vec;
+    /// # use stable_vec::StableVec;
+    /// use rayon::prelude::*;
+    ///
+    /// # fn main() {
+    /// let mut sv = StableVec::from(&["a", "b", "c"]);
+    /// sv.remove(1);
+    ///
+    /// let mut drained: Vec<_> = sv.par_drain().collect();
+    /// drained.sort();
+    ///
+    /// assert_eq!(drained, vec![(0, "a"), (2, "c")]);
+    /// assert!(sv.is_empty());
+    /// # }
+    /// ```
+    pub fn par_drain(&mut self) -> ParDrain<'_, T> {
+        ParDrain { sv: self }
+    }
+}
+
+/// Parallel iterator over the indices and immutable references of all
+/// existing elements of a `StableVec`.
+///
+/// Obtain it via `par_iter()` or `into_par_iter()` on `&StableVec<T>`.
+pub struct ParIter<'a, T: 'a> {
+    sv: &'a StableVec<T>,
+}
+
+impl<'a, T: Sync> ParallelIterator for ParIter<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+        where C: UnindexedConsumer<Self::Item>
+    {
+        let producer = IterProducer {
+            data: &self.sv.storage.data,
+            deleted: &self.sv.storage.deleted,
+            offset: 0,
+        };
+        bridge_unindexed(producer, consumer)
+    }
+}
+
+/// Parallel iterator over the indices and mutable references of all existing
+/// elements of a `StableVec`.
+///
+/// Obtain it via `par_iter_mut()` or `into_par_iter()` on
+/// `&mut StableVec<T>`.
+pub struct ParIterMut<'a, T: 'a> {
+    sv: &'a mut StableVec<T>,
+}
+
+impl<'a, T: Send> ParallelIterator for ParIterMut<'a, T> {
+    type Item = (usize, &'a mut T);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+        where C: UnindexedConsumer<Self::Item>
+    {
+        if self.sv.is_journal_enabled() {
+            for i in self.sv.keys().collect::<Vec<_>>() {
+                self.sv.record(i, ChangeKind::Modified);
+            }
+        }
+
+        let producer = IterMutProducer {
+            data: &mut self.sv.storage.data,
+            deleted: &self.sv.storage.deleted,
+            offset: 0,
+        };
+        bridge_unindexed(producer, consumer)
+    }
+}
+
+/// Producer for `ParIter`: `data` is a part of the stable vector's data,
+/// starting at index `offset`.
+struct IterProducer<'a, T: 'a> {
+    data: &'a [MaybeUninit<T>],
+    deleted: &'a BitVec,
+    offset: usize,
+}
+
+impl<'a, T: Sync> UnindexedProducer for IterProducer<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn split(self) -> (Self, Option<Self>) {
+        if self.data.len() < 2 {
+            return (self, None);
+        }
+
+        let mid = self.data.len() / 2;
+        let (left, right) = self.data.split_at(mid);
+        let right = IterProducer {
+            data: right,
+            deleted: self.deleted,
+            offset: self.offset + mid,
+        };
+        (IterProducer { data: left, ..self }, Some(right))
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+        where F: Folder<Self::Item>
+    {
+        let offset = self.offset;
+        let deleted = self.deleted;
+        let data = self.data;
+        let it = (0..data.len())
+            .filter(|&i| !deleted[offset + i])
+            .map(|i| (offset + i, unsafe { &*data[i].as_ptr() }));
+        folder.consume_iter(it)
+    }
+}
+
+/// Producer for `ParIterMut`: `data` is a part of the stable vector's data,
+/// starting at index `offset`.
+struct IterMutProducer<'a, T: 'a> {
+    data: &'a mut [MaybeUninit<T>],
+    deleted: &'a BitVec,
+    offset: usize,
+}
+
+impl<'a, T: Send> UnindexedProducer for IterMutProducer<'a, T> {
+    type Item = (usize, &'a mut T);
+
+    fn split(self) -> (Self, Option<Self>) {
+        if self.data.len() < 2 {
+            return (self, None);
+        }
+
+        let mid = self.data.len() / 2;
+        let (left, right) = self.data.split_at_mut(mid);
+        let right = IterMutProducer {
+            data: right,
+            deleted: self.deleted,
+            offset: self.offset + mid,
+        };
+        let left = IterMutProducer {
+            data: left,
+            deleted: self.deleted,
+            offset: self.offset,
+        };
+        (left, Some(right))
+    }
+
+    fn fold_with<F>(self, folder: F) -> F
+        where F: Folder<Self::Item>
+    {
+        let offset = self.offset;
+        let deleted = self.deleted;
+        let it = self.data.iter_mut()
+            .enumerate()
+            .filter(|&(i, _)| !deleted[offset + i])
+            .map(|(i, e)| (offset + i, unsafe { &mut *e.as_mut_ptr() }));
+        folder.consume_iter(it)
+    }
+}
+
+/// Parallel iterator moving all elements (and their indices) out of a
+/// `StableVec`.
+///
+/// Obtain it via [`StableVec::par_drain()`](../struct.StableVecFacade.html#method.par_drain).
+pub struct ParDrain<'a, T: 'a> {
+    sv: &'a mut StableVec<T>,
+}
+
+impl<'a, T: Send> ParallelIterator for ParDrain<'a, T> {
+    type Item = (usize, T);
+
+    fn drive_unindexed<C>(self, consumer: C) -> C::Result
+        where C: UnindexedConsumer<Self::Item>
+    {
+        // We take ownership of all elements here and leave the stable vector
+        // in an empty state. From now on, the producers are responsible for
+        // either moving elements out or dropping them. If anything panics,
+        // the remaining elements are leaked, but nothing is dropped twice.
+        if self.sv.is_journal_enabled() {
+            for i in self.sv.keys().collect::<Vec<_>>() {
+                self.sv.record(i, ChangeKind::Removed);
+            }
+        }
+        let deleted = mem::replace(&mut self.sv.storage.deleted, BitVec::new());
+        let len = self.sv.storage.data.len();
+        #[cfg(feature = "stats")]
+        {
+            self.sv.metrics.removals += self.sv.used_count;
+        }
+        self.sv.used_count = 0;
+        self.sv.free_slots.clear();
+        self.sv.generation = self.sv.generation.wrapping_add(1);
+        let data = unsafe {
+            self.sv.storage.data.set_len(0);
+            slice::from_raw_parts_mut(self.sv.storage.data.as_mut_ptr(), len)
+        };
+
+        let producer = DrainProducer {
+            data,
+            deleted: &deleted,
+            offset: 0,
+        };
+        let result = bridge_unindexed(producer, consumer);
+
+        // Reuse the memory of the bit vector.
+        let mut deleted = deleted;
+        deleted.truncate(0);
+        self.sv.storage.deleted = deleted;
+
+        result
+    }
+}
+
+impl<'a, T> Drop for ParDrain<'a, T> {
+    fn drop(&mut self) {
+        // If the iterator was never driven, we still have to remove all
+        // elements. Otherwise the stable vector is already empty.
+        self.sv.truncate(0);
+    }
+}
+
+/// Producer for `ParDrain`. It owns all existing elements in `data` (as
+/// defined by `deleted`) and drops all of them which are not moved out.
+struct DrainProducer<'a, T: 'a> {
+    data: &'a mut [MaybeUninit<T>],
+    deleted: &'a BitVec,
+    offset: usize,
+}
+
+impl<'a, T: Send> UnindexedProducer for DrainProducer<'a, T> {
+    type Item = (usize, T);
+
+    fn split(mut self) -> (Self, Option<Self>) {
+        if self.data.len() < 2 {
+            return (self, None);
+        }
+
+        let data = mem::take(&mut self.data);
+        let mid = data.len() / 2;
+        let (left, right) = data.split_at_mut(mid);
+        let right = DrainProducer {
+            data: right,
+            deleted: self.deleted,
+            offset: self.offset + mid,
+        };
+        let left = DrainProducer {
+            data: left,
+            deleted: self.deleted,
+            offset: self.offset,
+        };
+        (left, Some(right))
+    }
+
+    fn fold_with<F>(mut self, mut folder: F) -> F
+        where F: Folder<Self::Item>
+    {
+        // We take the slice out of `self`, so that our `drop()` doesn't touch
+        // the elements. The elements which are not moved out are dropped at
+        // the end of this method.
+        let data = mem::take(&mut self.data);
+        let offset = self.offset;
+
+        let mut i = 0;
+        while i < data.len() && !folder.full() {
+            if !self.deleted[offset + i] {
+                let elem = unsafe { data[i].as_ptr().read() };
+                folder = folder.consume((offset + i, elem));
+            }
+            i += 1;
+        }
+
+        drop_existing(&mut data[i..], self.deleted, offset + i);
+        folder
+    }
+}
+
+impl<'a, T> Drop for DrainProducer<'a, T> {
+    fn drop(&mut self) {
+        drop_existing(self.data, self.deleted, self.offset);
+    }
+}
+
+/// Drops all existing elements in `data` which starts at index `offset`.
+fn drop_existing<T>(data: &mut [MaybeUninit<T>], deleted: &BitVec, offset: usize) {
+    for (i, elem) in data.iter_mut().enumerate() {
+        if !deleted[offset + i] {
+            unsafe {
+                ptr::drop_in_place(elem.as_mut_ptr());
+            }
+        }
+    }
+}
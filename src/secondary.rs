@@ -0,0 +1,655 @@
+//! Maps which attach additional data to the indices of a stable vector.
+//!
+//! Often, only some code needs some data about the elements of a stable
+//! vector (e.g. the position of each node of a graph, for drawing it).
+//! Instead of storing that data in the elements themselves, it can be stored
+//! in a secondary map, keyed by the indices of the stable vector (or by any
+//! other [`Key`](../typed/trait.Key.html) type).
+//!
+//! There are two kinds of secondary maps with the same API:
+//!
+//! - [`SecondaryMap`](struct.SecondaryMap.html) stores the values in a
+//!   vector indexed by the keys. Choose this if most elements have a value.
+//! - [`SparseSecondaryMap`](struct.SparseSecondaryMap.html) stores the values
+//!   in a hash map. Choose this if only a few elements have a value. This
+//!   one needs the `std` feature.
+//!
+//! The [`Secondary`](trait.Secondary.html) trait contains the methods both
+//! maps share, to write code which works with either of them. The iterators
+//! have different types (and orders), so they are not part of it.
+//!
+//! Inserting data into a secondary map doesn't check whether the stable
+//! vector has an element at that index. When elements are removed from the
+//! stable vector, their entries stay in the map until
+//! `remove_stale()` is called.
+//!
+//! # Example
+//!
+//! ```
+//! use stable_vec::StableVec;
+//! use stable_vec::secondary::SecondaryMap;
+//!
+//! let mut names = StableVec::new();
+//! let alice = names.push("Alice");
+//! let bob = names.push("Bob");
+//!
+//! let mut ages = SecondaryMap::new();
+//! ages.insert(alice, 31);
+//! ages.insert(bob, 27);
+//! assert_eq!(ages[bob], 27);
+//!
+//! names.remove(alice);
+//! ages.remove_stale(&names);
+//! assert_eq!(ages.get(alice), None);
+//! assert_eq!(ages.iter().collect::<Vec<_>>(), vec![(bob, &27)]);
+//! ```
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::{Enumerate, FromIterator};
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+use core::slice;
+#[cfg(feature = "std")]
+use std::collections::hash_map::{self, HashMap};
+
+use {Key, StableVecFacade};
+use storage::Storage;
+
+
+/// The methods shared by [`SecondaryMap`](struct.SecondaryMap.html) and
+/// [`SparseSecondaryMap`](struct.SparseSecondaryMap.html). See the
+/// inherent methods of those types for their documentation.
+pub trait Secondary<T, K: Key = usize> {
+    /// Inserts a value for the given key and returns the old value, if any.
+    fn insert(&mut self, key: K, value: T) -> Option<T>;
+
+    /// Removes and returns the value for the given key, if any.
+    fn remove(&mut self, key: K) -> Option<T>;
+
+    /// Returns a reference to the value for the given key, if any.
+    fn get(&self, key: K) -> Option<&T>;
+
+    /// Returns a mutable reference to the value for the given key, if any.
+    fn get_mut(&mut self, key: K) -> Option<&mut T>;
+
+    /// Returns the number of values in this map.
+    fn len(&self) -> usize;
+
+    /// Removes all values.
+    fn clear(&mut self);
+
+    /// Removes all values whose key doesn't refer to an existing element of
+    /// `primary`.
+    fn remove_stale<U, S: Storage<U>>(&mut self, primary: &StableVecFacade<U, S>);
+
+    /// Returns `true` if there is a value for the given key.
+    fn contains_key(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns `true` if this map contains no values.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A dense map from keys of a stable vector to values of type `T`.
+///
+/// The values are stored in a `Vec<Option<T>>` which is indexed by the keys,
+/// so this uses memory proportional to the largest key. If only a few keys
+/// have a value, a [`SparseSecondaryMap`](struct.SparseSecondaryMap.html) is
+/// the better choice. See [the module documentation](index.html) for more
+/// information.
+pub struct SecondaryMap<T, K: Key = usize> {
+    slots: Vec<Option<T>>,
+
+    /// The number of slots which are `Some`.
+    len: usize,
+    marker: PhantomData<fn(K) -> K>,
+}
+
+impl<T, K: Key> SecondaryMap<T, K> {
+    /// Constructs a new, empty `SecondaryMap`.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Constructs a new, empty `SecondaryMap` which can hold values for the
+    /// keys `0..capacity` without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            len: 0,
+            marker: PhantomData,
+        }
+    }
+
+    /// Inserts a value for the given key and returns the old value, if any.
+    pub fn insert(&mut self, key: K, value: T) -> Option<T> {
+        let index = key.into_usize();
+        if index >= self.slots.len() {
+            self.slots.resize_with(index + 1, || None);
+        }
+
+        let old = self.slots[index].replace(value);
+        if old.is_none() {
+            self.len += 1;
+        }
+        old
+    }
+
+    /// Removes and returns the value for the given key, or `None` if there
+    /// is no such value.
+    pub fn remove(&mut self, key: K) -> Option<T> {
+        let old = self.slots.get_mut(key.into_usize()).and_then(Option::take);
+        if old.is_some() {
+            self.len -= 1;
+        }
+        old
+    }
+
+    /// Returns a reference to the value for the given key, or `None` if
+    /// there is no such value.
+    pub fn get(&self, key: K) -> Option<&T> {
+        self.slots.get(key.into_usize()).and_then(Option::as_ref)
+    }
+
+    /// Returns a mutable reference to the value for the given key, or `None`
+    /// if there is no such value.
+    pub fn get_mut(&mut self, key: K) -> Option<&mut T> {
+        self.slots.get_mut(key.into_usize()).and_then(Option::as_mut)
+    }
+
+    /// Returns `true` if there is a value for the given key.
+    pub fn contains_key(&self, key: K) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Returns the number of values in this map.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if this map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Removes all values.
+    pub fn clear(&mut self) {
+        self.slots.clear();
+        self.len = 0;
+    }
+
+    /// Retains only the values specified by the given predicate.
+    pub fn retain<P>(&mut self, mut predicate: P)
+        where P: FnMut(K, &mut T) -> bool,
+    {
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            let keep = match *slot {
+                Some(ref mut value) => predicate(K::from_usize(index), value),
+                None => true,
+            };
+            if !keep {
+                *slot = None;
+                self.len -= 1;
+            }
+        }
+    }
+
+    /// Removes all values whose key doesn't refer to an existing element of
+    /// `primary`.
+    ///
+    /// Call this after removing elements from the stable vector this map
+    /// belongs to, to drop the data of those elements.
+    pub fn remove_stale<U, S: Storage<U>>(&mut self, primary: &StableVecFacade<U, S>) {
+        self.retain(|key, _| primary.has_element_at(key.into_usize()));
+        self.slots.truncate(primary.next_index());
+    }
+
+    /// Returns an iterator over all keys and references to their values, in
+    /// ascending key order.
+    pub fn iter(&self) -> Iter<'_, T, K> {
+        Iter {
+            slots: self.slots.iter().enumerate(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over all keys and mutable references to their
+    /// values, in ascending key order.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T, K> {
+        IterMut {
+            slots: self.slots.iter_mut().enumerate(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over all keys which have a value, in ascending
+    /// order.
+    pub fn keys(&self) -> Keys<'_, T, K> {
+        Keys { inner: self.iter() }
+    }
+}
+
+impl<T, K: Key> Secondary<T, K> for SecondaryMap<T, K> {
+    fn insert(&mut self, key: K, value: T) -> Option<T> {
+        SecondaryMap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: K) -> Option<T> {
+        SecondaryMap::remove(self, key)
+    }
+
+    fn get(&self, key: K) -> Option<&T> {
+        SecondaryMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: K) -> Option<&mut T> {
+        SecondaryMap::get_mut(self, key)
+    }
+
+    fn len(&self) -> usize {
+        SecondaryMap::len(self)
+    }
+
+    fn clear(&mut self) {
+        SecondaryMap::clear(self)
+    }
+
+    fn remove_stale<U, S: Storage<U>>(&mut self, primary: &StableVecFacade<U, S>) {
+        SecondaryMap::remove_stale(self, primary)
+    }
+}
+
+impl<T, K: Key> Index<K> for SecondaryMap<T, K> {
+    type Output = T;
+
+    fn index(&self, key: K) -> &T {
+        self.get(key).expect("no value for this key in SecondaryMap")
+    }
+}
+
+impl<T, K: Key> IndexMut<K> for SecondaryMap<T, K> {
+    fn index_mut(&mut self, key: K) -> &mut T {
+        self.get_mut(key).expect("no value for this key in SecondaryMap")
+    }
+}
+
+impl<T, K: Key> Default for SecondaryMap<T, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, K: Key> Clone for SecondaryMap<T, K> {
+    fn clone(&self) -> Self {
+        Self {
+            slots: self.slots.clone(),
+            len: self.len,
+            marker: PhantomData,
+        }
+    }
+}
+
+impl<T, K: Key> FromIterator<(K, T)> for SecondaryMap<T, K> {
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item = (K, T)>
+    {
+        let mut out = Self::new();
+        out.extend(iter);
+        out
+    }
+}
+
+impl<T, K: Key> Extend<(K, T)> for SecondaryMap<T, K> {
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = (K, T)>
+    {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+impl<T: fmt::Debug, K: Key + fmt::Debug> fmt::Debug for SecondaryMap<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SecondaryMap ")?;
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over the keys and values of a `SecondaryMap`.
+///
+/// Use the method [`SecondaryMap::iter()`](struct.SecondaryMap.html#method.iter)
+/// to obtain an iterator of this kind.
+pub struct Iter<'a, T: 'a, K> {
+    slots: Enumerate<slice::Iter<'a, Option<T>>>,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<'a, T, K: Key> Iterator for Iter<'a, T, K> {
+    type Item = (K, &'a T);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots.by_ref()
+            .filter_map(|(index, slot)| slot.as_ref().map(|v| (K::from_usize(index), v)))
+            .next()
+    }
+}
+
+/// Iterator over the keys and mutable references to the values of a
+/// `SecondaryMap`.
+///
+/// Use the method [`SecondaryMap::iter_mut()`](struct.SecondaryMap.html#method.iter_mut)
+/// to obtain an iterator of this kind.
+pub struct IterMut<'a, T: 'a, K> {
+    slots: Enumerate<slice::IterMut<'a, Option<T>>>,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<'a, T, K: Key> Iterator for IterMut<'a, T, K> {
+    type Item = (K, &'a mut T);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.slots.by_ref()
+            .filter_map(|(index, slot)| slot.as_mut().map(|v| (K::from_usize(index), v)))
+            .next()
+    }
+}
+
+/// Iterator over the keys of a `SecondaryMap`.
+///
+/// Use the method [`SecondaryMap::keys()`](struct.SecondaryMap.html#method.keys)
+/// to obtain an iterator of this kind.
+pub struct Keys<'a, T: 'a, K> {
+    inner: Iter<'a, T, K>,
+}
+
+impl<'a, T, K: Key> Iterator for Keys<'a, T, K> {
+    type Item = K;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
+
+/// A sparse map from keys of a stable vector to values of type `T`.
+///
+/// The values are stored in a `HashMap`, so this only uses memory for the
+/// keys which actually have a value; iteration order is unspecified. If most
+/// keys have a value, a [`SecondaryMap`](struct.SecondaryMap.html) is faster
+/// and more compact. See [the module documentation](index.html) for more
+/// information.
+///
+/// Only available with the `std` feature.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::StableVec;
+/// use stable_vec::secondary::SparseSecondaryMap;
+///
+/// let mut sv = StableVec::from(&["a", "b", "c", "d"]);
+///
+/// let mut comments = SparseSecondaryMap::new();
+/// comments.insert(1, "second letter");
+/// comments.insert(3, "fourth letter");
+/// assert_eq!(comments[3], "fourth letter");
+///
+/// sv.remove(3);
+/// comments.remove_stale(&sv);
+/// assert_eq!(comments.keys().collect::<Vec<usize>>(), vec![1]);
+/// ```
+#[cfg(feature = "std")]
+pub struct SparseSecondaryMap<T, K: Key = usize> {
+    values: HashMap<usize, T>,
+    marker: PhantomData<fn(K) -> K>,
+}
+
+#[cfg(feature = "std")]
+impl<T, K: Key> SparseSecondaryMap<T, K> {
+    /// Constructs a new, empty `SparseSecondaryMap`.
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    /// Constructs a new, empty `SparseSecondaryMap` which can hold
+    /// `capacity` values without reallocating.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            values: HashMap::with_capacity(capacity),
+            marker: PhantomData,
+        }
+    }
+
+    /// Inserts a value for the given key and returns the old value, if any.
+    pub fn insert(&mut self, key: K, value: T) -> Option<T> {
+        self.values.insert(key.into_usize(), value)
+    }
+
+    /// Removes and returns the value for the given key, or `None` if there
+    /// is no such value.
+    pub fn remove(&mut self, key: K) -> Option<T> {
+        self.values.remove(&key.into_usize())
+    }
+
+    /// Returns a reference to the value for the given key, or `None` if
+    /// there is no such value.
+    pub fn get(&self, key: K) -> Option<&T> {
+        self.values.get(&key.into_usize())
+    }
+
+    /// Returns a mutable reference to the value for the given key, or `None`
+    /// if there is no such value.
+    pub fn get_mut(&mut self, key: K) -> Option<&mut T> {
+        self.values.get_mut(&key.into_usize())
+    }
+
+    /// Returns `true` if there is a value for the given key.
+    pub fn contains_key(&self, key: K) -> bool {
+        self.values.contains_key(&key.into_usize())
+    }
+
+    /// Returns the number of values in this map.
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    /// Returns `true` if this map contains no values.
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// Removes all values.
+    pub fn clear(&mut self) {
+        self.values.clear();
+    }
+
+    /// Retains only the values specified by the given predicate.
+    pub fn retain<P>(&mut self, mut predicate: P)
+        where P: FnMut(K, &mut T) -> bool,
+    {
+        self.values.retain(|&index, value| predicate(K::from_usize(index), value));
+    }
+
+    /// Removes all values whose key doesn't refer to an existing element of
+    /// `primary`.
+    ///
+    /// Call this after removing elements from the stable vector this map
+    /// belongs to, to drop the data of those elements.
+    pub fn remove_stale<U, S: Storage<U>>(&mut self, primary: &StableVecFacade<U, S>) {
+        self.values.retain(|&index, _| primary.has_element_at(index));
+    }
+
+    /// Returns an iterator over all keys and references to their values, in
+    /// unspecified order.
+    pub fn iter(&self) -> SparseIter<'_, T, K> {
+        SparseIter {
+            inner: self.values.iter(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over all keys and mutable references to their
+    /// values, in unspecified order.
+    pub fn iter_mut(&mut self) -> SparseIterMut<'_, T, K> {
+        SparseIterMut {
+            inner: self.values.iter_mut(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns an iterator over all keys which have a value, in unspecified
+    /// order.
+    pub fn keys(&self) -> SparseKeys<'_, T, K> {
+        SparseKeys { inner: self.iter() }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, K: Key> Secondary<T, K> for SparseSecondaryMap<T, K> {
+    fn insert(&mut self, key: K, value: T) -> Option<T> {
+        SparseSecondaryMap::insert(self, key, value)
+    }
+
+    fn remove(&mut self, key: K) -> Option<T> {
+        SparseSecondaryMap::remove(self, key)
+    }
+
+    fn get(&self, key: K) -> Option<&T> {
+        SparseSecondaryMap::get(self, key)
+    }
+
+    fn get_mut(&mut self, key: K) -> Option<&mut T> {
+        SparseSecondaryMap::get_mut(self, key)
+    }
+
+    fn len(&self) -> usize {
+        SparseSecondaryMap::len(self)
+    }
+
+    fn clear(&mut self) {
+        SparseSecondaryMap::clear(self)
+    }
+
+    fn remove_stale<U, S: Storage<U>>(&mut self, primary: &StableVecFacade<U, S>) {
+        SparseSecondaryMap::remove_stale(self, primary)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, K: Key> Index<K> for SparseSecondaryMap<T, K> {
+    type Output = T;
+
+    fn index(&self, key: K) -> &T {
+        self.get(key).expect("no value for this key in SparseSecondaryMap")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, K: Key> IndexMut<K> for SparseSecondaryMap<T, K> {
+    fn index_mut(&mut self, key: K) -> &mut T {
+        self.get_mut(key).expect("no value for this key in SparseSecondaryMap")
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, K: Key> Default for SparseSecondaryMap<T, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: Clone, K: Key> Clone for SparseSecondaryMap<T, K> {
+    fn clone(&self) -> Self {
+        Self {
+            values: self.values.clone(),
+            marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, K: Key> FromIterator<(K, T)> for SparseSecondaryMap<T, K> {
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item = (K, T)>
+    {
+        let mut out = Self::new();
+        out.extend(iter);
+        out
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, K: Key> Extend<(K, T)> for SparseSecondaryMap<T, K> {
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = (K, T)>
+    {
+        for (key, value) in iter {
+            self.insert(key, value);
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T: fmt::Debug, K: Key + fmt::Debug> fmt::Debug for SparseSecondaryMap<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SparseSecondaryMap ")?;
+        f.debug_map().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over the keys and values of a `SparseSecondaryMap`.
+///
+/// Use the method [`SparseSecondaryMap::iter()`](struct.SparseSecondaryMap.html#method.iter)
+/// to obtain an iterator of this kind.
+#[cfg(feature = "std")]
+pub struct SparseIter<'a, T: 'a, K> {
+    inner: hash_map::Iter<'a, usize, T>,
+    marker: PhantomData<fn() -> K>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, T, K: Key> Iterator for SparseIter<'a, T, K> {
+    type Item = (K, &'a T);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(&index, v)| (K::from_usize(index), v))
+    }
+}
+
+/// Iterator over the keys and mutable references to the values of a
+/// `SparseSecondaryMap`.
+///
+/// Use the method
+/// [`SparseSecondaryMap::iter_mut()`](struct.SparseSecondaryMap.html#method.iter_mut)
+/// to obtain an iterator of this kind.
+#[cfg(feature = "std")]
+pub struct SparseIterMut<'a, T: 'a, K> {
+    inner: hash_map::IterMut<'a, usize, T>,
+    marker: PhantomData<fn() -> K>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, T, K: Key> Iterator for SparseIterMut<'a, T, K> {
+    type Item = (K, &'a mut T);
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(&index, v)| (K::from_usize(index), v))
+    }
+}
+
+/// Iterator over the keys of a `SparseSecondaryMap`.
+///
+/// Use the method [`SparseSecondaryMap::keys()`](struct.SparseSecondaryMap.html#method.keys)
+/// to obtain an iterator of this kind.
+#[cfg(feature = "std")]
+pub struct SparseKeys<'a, T: 'a, K> {
+    inner: SparseIter<'a, T, K>,
+}
+
+#[cfg(feature = "std")]
+impl<'a, T, K: Key> Iterator for SparseKeys<'a, T, K> {
+    type Item = K;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(key, _)| key)
+    }
+}
@@ -0,0 +1,143 @@
+//! Serde support (requires the `serde` feature).
+//!
+//! A `StableVec<T>` is serialized as a sequence of `Option<T>` with one entry
+//! per slot: existing elements are serialized as `Some(elem)`, holes as
+//! `None`. Deserializing this sequence results in a stable vector with
+//! exactly the same holes, so all indices remain valid after a round trip.
+//!
+//! ```
+//! # extern crate serde_json;
+//! # extern crate stable_vec;
+//! # use stable_vec::StableVec;
+//! # fn main() {
+//! let mut sv = StableVec::from(&[1, 2, 3]);
+//! sv.remove(1);
+//!
+//! let json = serde_json::to_string(&sv).unwrap();
+//! assert_eq!(json, "[1,null,3]");
+//!
+//! let sv: StableVec<i32> = serde_json::from_str(&json).unwrap();
+//! assert_eq!(sv.get(0), Some(&1));
+//! assert_eq!(sv.get(1), None);
+//! assert_eq!(sv.get(2), Some(&3));
+//! # }
+//! ```
+
+use serde_crate::de::{Deserialize, Deserializer, SeqAccess, Visitor};
+use serde_crate::ser::{Serialize, Serializer};
+
+use core::fmt;
+use core::marker::PhantomData;
+
+use StableVec;
+
+
+impl<T> Serialize for StableVec<T>
+    where T: Serialize
+{
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+        where S: Serializer
+    {
+        serializer.collect_seq((0..self.next_index()).map(|i| self.get(i)))
+    }
+}
+
+impl<'de, T> Deserialize<'de> for StableVec<T>
+    where T: Deserialize<'de>
+{
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+        where D: Deserializer<'de>
+    {
+        deserializer.deserialize_seq(StableVecVisitor(PhantomData))
+    }
+}
+
+/// Visitor building a `StableVec<T>` from a sequence of `Option<T>`.
+struct StableVecVisitor<T>(PhantomData<T>);
+
+impl<'de, T> Visitor<'de> for StableVecVisitor<T>
+    where T: Deserialize<'de>
+{
+    type Value = StableVec<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a sequence of optional elements")
+    }
+
+    fn visit_seq<A>(self, mut seq: A) -> Result<Self::Value, A::Error>
+        where A: SeqAccess<'de>
+    {
+        // We don't trust the size hint too much: a malicious input shouldn't
+        // be able to make us allocate huge amounts of memory upfront.
+        let capacity = seq.size_hint().unwrap_or(0).min(4096);
+        let mut sv = StableVec::with_capacity(capacity);
+
+        while let Some(slot) = seq.next_element::<Option<T>>()? {
+            match slot {
+                Some(elem) => { sv.push(elem); }
+                None => sv.push_hole(),
+            }
+        }
+
+        Ok(sv)
+    }
+}
+
+/// Serializes only the existing elements in a dense sequence, for use with
+/// `#[serde(with = "stable_vec::serde::compact")]`.
+///
+/// Holes are skipped entirely, which makes the serialized form smaller, but
+/// also means that indices are **not** preserved: after deserializing, the
+/// stable vector is compact and indices are as if
+/// [`make_compact()`](../../struct.StableVecFacade.html#method.make_compact) was
+/// called before serializing.
+///
+/// ```
+/// # #[macro_use] extern crate serde_derive;
+/// # extern crate serde_json;
+/// # extern crate stable_vec;
+/// # use stable_vec::StableVec;
+/// #[derive(Serialize, Deserialize)]
+/// struct Scene {
+///     #[serde(with = "stable_vec::serde::compact")]
+///     names: StableVec<String>,
+/// }
+///
+/// # fn main() {
+/// let mut names = StableVec::new();
+/// names.push("Alice".to_string());
+/// let bob_idx = names.push("Bob".to_string());
+/// names.push("Carol".to_string());
+/// names.remove(bob_idx);
+///
+/// let json = serde_json::to_string(&Scene { names }).unwrap();
+/// assert_eq!(json, r#"{"names":["Alice","Carol"]}"#);
+///
+/// let scene: Scene = serde_json::from_str(&json).unwrap();
+/// assert_eq!(scene.names, &["Alice", "Carol"] as &[_]);
+/// # }
+/// ```
+pub mod compact {
+    use serde_crate::de::{Deserialize, Deserializer};
+    use serde_crate::ser::{Serialize, Serializer};
+
+    use alloc::vec::Vec;
+
+    use StableVec;
+
+    /// Serializes all existing elements of `sv` as a sequence, skipping holes.
+    pub fn serialize<T, S>(sv: &StableVec<T>, serializer: S) -> Result<S::Ok, S::Error>
+        where T: Serialize,
+              S: Serializer,
+    {
+        serializer.collect_seq(sv)
+    }
+
+    /// Deserializes a sequence of elements into a compact stable vector.
+    pub fn deserialize<'de, T, D>(deserializer: D) -> Result<StableVec<T>, D::Error>
+        where T: Deserialize<'de>,
+              D: Deserializer<'de>,
+    {
+        Vec::deserialize(deserializer).map(|v| v.into_iter().collect())
+    }
+}
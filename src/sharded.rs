@@ -0,0 +1,207 @@
+//! A stable vector split into independently locked shards.
+//!
+//! See [`ShardedStableVec`](struct.ShardedStableVec.html) for more
+//! information.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, PoisonError};
+
+use StableVec;
+
+
+/// A stable vector which allows concurrent insertion and removal by
+/// partitioning the index space across multiple independently locked shards.
+///
+/// Index `i` lives in shard `i % shard_count()` at the local index
+/// `i / shard_count()`. Each shard is a `StableVec<T>` protected by its own
+/// mutex, so threads working on different shards never block each other.
+/// [`push()`](#method.push) picks shards in a round-robin fashion and prefers
+/// shards which are not locked by another thread at the moment.
+///
+/// The stable-index contract is the same as for `StableVec`: the index
+/// returned by `push()` refers to the same element until that element is
+/// removed. Indices are *not* handed out in increasing order, though.
+///
+/// As elements live behind locks, they can't be borrowed directly. Instead,
+/// [`with()`](#method.with) and [`with_mut()`](#method.with_mut) execute a
+/// closure while holding the lock of the corresponding shard.
+///
+/// A panic while a shard is locked doesn't poison the vector: all operations
+/// leave the shard in a consistent state.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::ShardedStableVec;
+/// use std::thread;
+///
+/// let sv = ShardedStableVec::new(4);
+/// let sv_ref = &sv;
+///
+/// thread::scope(|s| {
+///     for t in 0..4 {
+///         s.spawn(move || {
+///             for i in 0..100 {
+///                 let idx = sv_ref.push(t * 100 + i);
+///                 if i % 2 == 0 {
+///                     assert_eq!(sv_ref.remove(idx), Some(t * 100 + i));
+///                 }
+///             }
+///         });
+///     }
+/// });
+///
+/// assert_eq!(sv.num_elements(), 200);
+/// ```
+pub struct ShardedStableVec<T> {
+    shards: Vec<Mutex<StableVec<T>>>,
+
+    /// Used to distribute pushes across shards.
+    next_shard: AtomicUsize,
+}
+
+impl<T> ShardedStableVec<T> {
+    /// Constructs a new, empty `ShardedStableVec<T>` with the given number of
+    /// shards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `shard_count` is 0.
+    pub fn new(shard_count: usize) -> Self {
+        assert!(shard_count > 0, "a ShardedStableVec needs at least one shard");
+
+        Self {
+            shards: (0..shard_count).map(|_| Mutex::new(StableVec::new())).collect(),
+            next_shard: AtomicUsize::new(0),
+        }
+    }
+
+    /// Returns the number of shards.
+    pub fn shard_count(&self) -> usize {
+        self.shards.len()
+    }
+
+    /// Inserts the given element into one of the shards and returns its
+    /// index.
+    pub fn push(&self, elem: T) -> usize {
+        let n = self.shards.len();
+        let start = self.next_shard.fetch_add(1, Ordering::Relaxed) % n;
+
+        // Try to find a shard nobody else is using right now. If all shards
+        // are busy, we wait for the one we started with.
+        let (shard, mut guard) = (0..n)
+            .map(|offset| (start + offset) % n)
+            .filter_map(|shard| self.shards[shard].try_lock().ok().map(|guard| (shard, guard)))
+            .next()
+            .unwrap_or_else(|| (start, self.lock(start)));
+
+        guard.push(elem) * n + shard
+    }
+
+    /// Removes and returns the element at the given index, or `None` if there
+    /// exists no element at that index.
+    pub fn remove(&self, index: usize) -> Option<T> {
+        let (shard, local) = self.locate(index);
+        self.lock(shard).remove(local)
+    }
+
+    /// Returns `true` if there exists an element at the given index.
+    pub fn has_element_at(&self, index: usize) -> bool {
+        self.with(index, |_| ()).is_some()
+    }
+
+    /// Calls `f` with a reference to the element at the given index and
+    /// returns its result, or returns `None` if there exists no element at
+    /// that index.
+    ///
+    /// The shard containing the element is locked while `f` runs.
+    pub fn with<F, R>(&self, index: usize, f: F) -> Option<R>
+        where F: FnOnce(&T) -> R
+    {
+        let (shard, local) = self.locate(index);
+        self.lock(shard).get(local).map(f)
+    }
+
+    /// Calls `f` with a mutable reference to the element at the given index
+    /// and returns its result, or returns `None` if there exists no element
+    /// at that index.
+    ///
+    /// The shard containing the element is locked while `f` runs.
+    pub fn with_mut<F, R>(&self, index: usize, f: F) -> Option<R>
+        where F: FnOnce(&mut T) -> R
+    {
+        let (shard, local) = self.locate(index);
+        self.lock(shard).get_mut(local).map(f)
+    }
+
+    /// Returns a mutable reference to the element at the given index, or
+    /// `None` if there exists no element at that index. No locking is
+    /// required, as `&mut self` guarantees exclusive access.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        let (shard, local) = self.locate(index);
+        self.shards[shard]
+            .get_mut()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get_mut(local)
+    }
+
+    /// Returns the number of existing elements.
+    ///
+    /// The shards are locked one after another, so while other threads insert
+    /// or remove elements, the result is only an approximation.
+    pub fn num_elements(&self) -> usize {
+        (0..self.shards.len()).map(|shard| self.lock(shard).num_elements()).sum()
+    }
+
+    /// Returns `true` if there are no existing elements. The same caveat as
+    /// for [`num_elements()`](#method.num_elements) applies.
+    pub fn is_empty(&self) -> bool {
+        self.num_elements() == 0
+    }
+
+    /// Converts this vector into a `StableVec<T>` with the same elements at
+    /// the same indices.
+    pub fn into_stable_vec(self) -> StableVec<T> {
+        let n = self.shards.len();
+        let mut shards: Vec<_> = self.shards
+            .into_iter()
+            .map(|shard| shard.into_inner().unwrap_or_else(PoisonError::into_inner))
+            .collect();
+
+        let len = shards.iter()
+            .enumerate()
+            .filter(|&(_, sv)| sv.next_index() > 0)
+            .map(|(shard, sv)| (sv.next_index() - 1) * n + shard + 1)
+            .max()
+            .unwrap_or(0);
+
+        let mut out = StableVec::with_capacity(len);
+        for i in 0..len {
+            match shards[i % n].remove(i / n) {
+                Some(elem) => { out.push(elem); }
+                None => out.push_hole(),
+            }
+        }
+        out
+    }
+
+    /// Returns the shard and the local index within that shard.
+    fn locate(&self, index: usize) -> (usize, usize) {
+        (index % self.shards.len(), index / self.shards.len())
+    }
+
+    /// Locks the given shard, ignoring poisoning.
+    fn lock(&self, shard: usize) -> MutexGuard<'_, StableVec<T>> {
+        self.shards[shard].lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for ShardedStableVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ShardedStableVec")
+            .field("shards", &self.shards)
+            .finish()
+    }
+}
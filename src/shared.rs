@@ -0,0 +1,313 @@
+//! A stable vector with cheap, persistent clones.
+//!
+//! See [`SharedStableVec`](struct.SharedStableVec.html) for more information.
+
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use core::iter::FromIterator;
+use core::ops::{Index, IndexMut};
+
+use StableVec;
+
+
+/// The number of slots per chunk.
+const CHUNK_LEN: usize = 64;
+
+type Chunk<T> = Arc<Vec<Option<T>>>;
+
+/// A stable vector whose `clone()` is O(1) and shares all elements with the
+/// original.
+///
+/// The elements are stored in reference counted chunks of 64 slots each.
+/// Cloning the vector only increments a single reference count. Mutating a
+/// vector whose storage is shared copies only the chunk containing the
+/// modified slot (plus the list of chunk pointers); all other chunks stay
+/// shared. This makes it cheap to take immutable snapshots, e.g. to serialize
+/// them on a background thread while the original keeps being mutated.
+///
+/// All mutating methods require `T: Clone`, as shared chunks have to be
+/// copied before they can be modified.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::SharedStableVec;
+///
+/// let mut sv = SharedStableVec::new();
+/// let a = sv.push('a');
+/// let b = sv.push('b');
+///
+/// let snapshot = sv.clone();
+/// sv.remove(a);
+/// sv[b] = 'x';
+/// sv.push('c');
+///
+/// assert_eq!(snapshot.iter().collect::<Vec<_>>(), vec![&'a', &'b']);
+/// assert_eq!(sv.iter().collect::<Vec<_>>(), vec![&'x', &'c']);
+/// ```
+pub struct SharedStableVec<T> {
+    chunks: Arc<Vec<Chunk<T>>>,
+    next_index: usize,
+    used_count: usize,
+}
+
+impl<T> SharedStableVec<T> {
+    /// Constructs a new, empty `SharedStableVec<T>`.
+    pub fn new() -> Self {
+        Self {
+            chunks: Arc::new(Vec::new()),
+            next_index: 0,
+            used_count: 0,
+        }
+    }
+
+    /// Returns a reference to the element at the given index, or `None` if
+    /// there exists no element at that index.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.slot(index).and_then(|slot| slot.as_ref())
+    }
+
+    /// Returns `true` if there exists an element at the given index.
+    pub fn has_element_at(&self, index: usize) -> bool {
+        self.get(index).is_some()
+    }
+
+    /// Returns the number of existing elements.
+    pub fn num_elements(&self) -> usize {
+        self.used_count
+    }
+
+    /// Returns `true` if there are no existing elements.
+    pub fn is_empty(&self) -> bool {
+        self.used_count == 0
+    }
+
+    /// Returns the index that would be returned by calling
+    /// [`push()`](#method.push).
+    pub fn next_index(&self) -> usize {
+        self.next_index
+    }
+
+    /// Returns an iterator over immutable references to the existing
+    /// elements.
+    pub fn iter(&self) -> Iter<'_, T> {
+        Iter {
+            chunks: &self.chunks,
+            pos: 0,
+        }
+    }
+
+    /// Returns an iterator over the indices of all existing elements.
+    pub fn keys(&self) -> Keys<'_, T> {
+        Keys {
+            chunks: &self.chunks,
+            pos: 0,
+        }
+    }
+
+    /// Returns `true` if `self` and `other` share all of their storage, i.e.
+    /// if one is an unmodified clone of the other.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.chunks, &other.chunks)
+    }
+
+    /// Returns the chunks, to check in tests which of them are shared.
+    #[cfg(test)]
+    pub(crate) fn chunks(&self) -> &[Chunk<T>] {
+        &self.chunks
+    }
+
+    /// Returns the slot at the given index, or `None` if the index is out of
+    /// bounds. Doesn't copy anything.
+    fn slot(&self, index: usize) -> Option<&Option<T>> {
+        self.chunks
+            .get(index / CHUNK_LEN)
+            .and_then(|chunk| chunk.get(index % CHUNK_LEN))
+    }
+}
+
+impl<T: Clone> SharedStableVec<T> {
+    /// Appends a new element and returns its index.
+    ///
+    /// Copies the last chunk if it is shared with another vector.
+    pub fn push(&mut self, elem: T) -> usize {
+        self.used_count += 1;
+        self.push_slot(Some(elem))
+    }
+
+    /// Returns a mutable reference to the element at the given index, or
+    /// `None` if there exists no element at that index.
+    ///
+    /// Copies the chunk containing the element if it is shared with another
+    /// vector.
+    pub fn get_mut(&mut self, index: usize) -> Option<&mut T> {
+        if !self.has_element_at(index) {
+            return None;
+        }
+
+        self.slot_mut(index).as_mut()
+    }
+
+    /// Removes and returns the element at the given index, or `None` if there
+    /// exists no element at that index.
+    ///
+    /// Copies the chunk containing the element if it is shared with another
+    /// vector.
+    pub fn remove(&mut self, index: usize) -> Option<T> {
+        if !self.has_element_at(index) {
+            return None;
+        }
+
+        self.used_count -= 1;
+        self.slot_mut(index).take()
+    }
+
+    /// Appends the given slot and returns its index. Doesn't update
+    /// `used_count`.
+    fn push_slot(&mut self, slot: Option<T>) -> usize {
+        let index = self.next_index;
+        let chunks = Arc::make_mut(&mut self.chunks);
+        if index.is_multiple_of(CHUNK_LEN) {
+            chunks.push(Arc::new(Vec::with_capacity(CHUNK_LEN)));
+        }
+        Arc::make_mut(chunks.last_mut().unwrap()).push(slot);
+
+        self.next_index += 1;
+        index
+    }
+
+    /// Returns a mutable reference to the slot at the given index, copying its
+    /// chunk if necessary. The index has to be in bounds.
+    fn slot_mut(&mut self, index: usize) -> &mut Option<T> {
+        let chunk = &mut Arc::make_mut(&mut self.chunks)[index / CHUNK_LEN];
+        &mut Arc::make_mut(chunk)[index % CHUNK_LEN]
+    }
+
+    /// Converts this vector into a `StableVec<T>` with the same elements at
+    /// the same indices. Clones elements that are still shared with another
+    /// vector.
+    pub fn into_stable_vec(self) -> StableVec<T> {
+        let mut out = StableVec::with_capacity(self.next_index);
+        for i in 0..self.next_index {
+            match *self.slot(i).unwrap() {
+                Some(ref elem) => { out.push(elem.clone()); }
+                None => out.push_hole(),
+            }
+        }
+        out
+    }
+}
+
+impl<T> Clone for SharedStableVec<T> {
+    /// Creates a vector sharing all storage with `self`. This is O(1).
+    fn clone(&self) -> Self {
+        Self {
+            chunks: self.chunks.clone(),
+            next_index: self.next_index,
+            used_count: self.used_count,
+        }
+    }
+}
+
+impl<T> Default for SharedStableVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Index<usize> for SharedStableVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        assert!(self.has_element_at(index));
+        self.get(index).unwrap()
+    }
+}
+
+impl<T: Clone> IndexMut<usize> for SharedStableVec<T> {
+    fn index_mut(&mut self, index: usize) -> &mut T {
+        assert!(self.has_element_at(index));
+        self.get_mut(index).unwrap()
+    }
+}
+
+impl<T: Clone> From<StableVec<T>> for SharedStableVec<T> {
+    fn from(mut sv: StableVec<T>) -> Self {
+        let mut out = Self::new();
+        for i in 0..sv.next_index() {
+            match sv.remove(i) {
+                Some(elem) => { out.push(elem); }
+                None => { out.push_slot(None); }
+            }
+        }
+        out
+    }
+}
+
+impl<T: Clone> FromIterator<T> for SharedStableVec<T> {
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item = T>
+    {
+        let mut out = Self::new();
+        for elem in iter {
+            out.push(elem);
+        }
+        out
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for SharedStableVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "SharedStableVec ")?;
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over immutable references to the elements of a
+/// `SharedStableVec`.
+///
+/// Use the method [`SharedStableVec::iter()`](struct.SharedStableVec.html#method.iter)
+/// to obtain an iterator of this kind.
+pub struct Iter<'a, T: 'a> {
+    chunks: &'a [Chunk<T>],
+    pos: usize,
+}
+
+impl<'a, T> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(chunk) = self.chunks.get(self.pos / CHUNK_LEN) {
+            let slot = chunk.get(self.pos % CHUNK_LEN)?;
+            self.pos += 1;
+            if let Some(ref elem) = *slot {
+                return Some(elem);
+            }
+        }
+        None
+    }
+}
+
+/// Iterator over the indices of all existing elements of a
+/// `SharedStableVec`.
+///
+/// Use the method [`SharedStableVec::keys()`](struct.SharedStableVec.html#method.keys)
+/// to obtain an iterator of this kind.
+pub struct Keys<'a, T: 'a> {
+    chunks: &'a [Chunk<T>],
+    pos: usize,
+}
+
+impl<'a, T> Iterator for Keys<'a, T> {
+    type Item = usize;
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(chunk) = self.chunks.get(self.pos / CHUNK_LEN) {
+            let slot = chunk.get(self.pos % CHUNK_LEN)?;
+            self.pos += 1;
+            if slot.is_some() {
+                return Some(self.pos - 1);
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,257 @@
+//! A simple, versioned binary snapshot format.
+//!
+//! This is meant for quickly dumping a whole stable vector to disk (e.g. for
+//! crash recovery) and restoring it later, with all holes and thus all indices
+//! preserved. It doesn't require serde and avoids building any intermediate
+//! data structures.
+//!
+//! Elements are written via the [`Encode`](trait.Encode.html) trait and read
+//! via the [`Decode`](trait.Decode.html) trait. Both are implemented for
+//! primitive types, `char`, `bool` and `String`, and can be implemented for
+//! your own types.
+//!
+//! # Format
+//!
+//! All integers are stored in little endian.
+//!
+//! - The magic bytes `b"SVEC"`
+//! - The format version as `u16` (currently `1`)
+//! - [`next_index()`](../struct.StableVecFacade.html#method.next_index) as `u64`
+//! - [`num_elements()`](../struct.StableVecFacade.html#method.num_elements) as `u64`
+//! - The occupancy bitmap: one bit per slot, set if the slot contains an
+//!   element, starting at the least significant bit of the first byte. The
+//!   last byte is padded with zeros.
+//! - All existing elements, in index order, each written with `Encode`
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! let mut sv = StableVec::from(&[1u32, 2, 3]);
+//! sv.remove(1);
+//!
+//! let mut buffer = Vec::new();
+//! sv.write_snapshot(&mut buffer).unwrap();
+//!
+//! let restored = StableVec::<u32>::read_snapshot(&buffer[..]).unwrap();
+//! assert_eq!(restored.get(0), Some(&1));
+//! assert_eq!(restored.get(1), None);
+//! assert_eq!(restored.get(2), Some(&3));
+//! ```
+//!
+//! Note that elements are written and read one by one. You usually want to
+//! wrap files in a `BufWriter` or `BufReader`.
+
+use alloc::string::String;
+use alloc::vec::Vec;
+use std::io::{self, Read, Write};
+
+use StableVec;
+
+
+/// The magic bytes every snapshot starts with.
+const MAGIC: &[u8; 4] = b"SVEC";
+
+/// The version of the snapshot format written by this crate.
+const VERSION: u16 = 1;
+
+
+/// Types that can be written into a snapshot.
+pub trait Encode {
+    /// Writes `self` into the given writer.
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()>;
+}
+
+/// Types that can be read from a snapshot.
+pub trait Decode: Sized {
+    /// Reads a value from the given reader.
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self>;
+}
+
+macro_rules! impl_for_num {
+    ($($ty:ident),*) => {
+        $(
+            impl Encode for $ty {
+                fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+                    writer.write_all(&self.to_le_bytes())
+                }
+            }
+
+            impl Decode for $ty {
+                fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+                    let mut bytes = [0; ::core::mem::size_of::<$ty>()];
+                    reader.read_exact(&mut bytes)?;
+                    Ok($ty::from_le_bytes(bytes))
+                }
+            }
+        )*
+    }
+}
+
+impl_for_num!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+impl Encode for usize {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u64).encode(writer)
+    }
+}
+
+impl Decode for usize {
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        to_usize(u64::decode(reader)?)
+    }
+}
+
+impl Encode for isize {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as i64).encode(writer)
+    }
+}
+
+impl Decode for isize {
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let v = i64::decode(reader)?;
+        if v < isize::MIN as i64 || v > isize::MAX as i64 {
+            Err(invalid_data("isize value out of range for this platform"))
+        } else {
+            Ok(v as isize)
+        }
+    }
+}
+
+impl Encode for bool {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u8).encode(writer)
+    }
+}
+
+impl Decode for bool {
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        match u8::decode(reader)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(invalid_data("invalid bool value")),
+        }
+    }
+}
+
+impl Encode for char {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        (*self as u32).encode(writer)
+    }
+}
+
+impl Decode for char {
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        ::core::char::from_u32(u32::decode(reader)?)
+            .ok_or_else(|| invalid_data("invalid char value"))
+    }
+}
+
+impl Encode for String {
+    fn encode<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        self.len().encode(writer)?;
+        writer.write_all(self.as_bytes())
+    }
+}
+
+impl Decode for String {
+    fn decode<R: Read>(reader: &mut R) -> io::Result<Self> {
+        let len = usize::decode(reader)?;
+        let bytes = read_bytes(reader, len)?;
+        String::from_utf8(bytes).map_err(|_| invalid_data("invalid UTF-8 in string"))
+    }
+}
+
+
+impl<T: Encode> StableVec<T> {
+    /// Writes a snapshot of this stable vector into the given writer.
+    ///
+    /// See [the `snapshot` module](snapshot/index.html) for more information
+    /// about the format.
+    pub fn write_snapshot<W: Write>(&self, mut writer: W) -> io::Result<()> {
+        writer.write_all(MAGIC)?;
+        VERSION.encode(&mut writer)?;
+        self.next_index().encode(&mut writer)?;
+        self.num_elements().encode(&mut writer)?;
+
+        // Write occupancy bitmap
+        let mut bitmap = vec![0u8; self.next_index().div_ceil(8)];
+        for i in self.keys() {
+            bitmap[i / 8] |= 1 << (i % 8);
+        }
+        writer.write_all(&bitmap)?;
+
+        for elem in self {
+            elem.encode(&mut writer)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Decode> StableVec<T> {
+    /// Reads a snapshot previously written by
+    /// [`write_snapshot()`](#method.write_snapshot) from the given reader.
+    ///
+    /// Returns an error of kind `InvalidData` if the snapshot is malformed or
+    /// was written with an unsupported format version.
+    pub fn read_snapshot<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut magic = [0; 4];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(invalid_data("not a stable vector snapshot"));
+        }
+
+        let version = u16::decode(&mut reader)?;
+        if version != VERSION {
+            return Err(invalid_data("unsupported snapshot version"));
+        }
+
+        let len = usize::decode(&mut reader)?;
+        let num_elements = usize::decode(&mut reader)?;
+        let bitmap = read_bytes(&mut reader, len.div_ceil(8))?;
+
+        let occupied = bitmap.iter().map(|b| b.count_ones() as usize).sum::<usize>();
+        let padding_clean = len % 8 == 0 || bitmap[len / 8] >> (len % 8) == 0;
+        if occupied != num_elements || !padding_clean {
+            return Err(invalid_data("occupancy bitmap doesn't match header"));
+        }
+
+        let mut sv = StableVec::with_capacity(len);
+        for i in 0..len {
+            if bitmap[i / 8] & (1 << (i % 8)) != 0 {
+                sv.push(T::decode(&mut reader)?);
+            } else {
+                sv.push_hole();
+            }
+        }
+
+        Ok(sv)
+    }
+}
+
+
+fn invalid_data(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg)
+}
+
+fn to_usize(v: u64) -> io::Result<usize> {
+    if v > usize::MAX as u64 {
+        Err(invalid_data("length out of range for this platform"))
+    } else {
+        Ok(v as usize)
+    }
+}
+
+/// Reads exactly `len` bytes. In contrast to allocating a buffer of size
+/// `len` upfront, this doesn't allocate huge amounts of memory for malformed
+/// inputs.
+fn read_bytes<R: Read>(reader: &mut R, len: usize) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    reader.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "snapshot is truncated"));
+    }
+    Ok(bytes)
+}
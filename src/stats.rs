@@ -0,0 +1,82 @@
+//! Operation statistics for profiling (requires the `stats` feature).
+//!
+//! With the `stats` feature, every stable vector counts how often it pushed,
+//! removed, reallocated and compacted. This helps to tune push policies and
+//! compaction strategies with production workloads, without attaching a
+//! profiler. Without the feature, nothing is counted and the stable vector
+//! doesn't get any bigger.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! let mut sv = StableVec::from(&[1, 2, 3, 4]);
+//! sv.remove(0);
+//! sv.remove(2);
+//! sv.push_reuse(5);
+//! sv.make_compact();
+//!
+//! let metrics = sv.metrics();
+//! assert_eq!(metrics.pushes, 5);
+//! assert_eq!(metrics.removals, 2);
+//! assert_eq!(metrics.hole_reuses, 1);
+//! assert_eq!(metrics.compactions, 1);
+//! assert_eq!(metrics.elements_moved, 3);
+//!
+//! sv.reset_metrics();
+//! assert_eq!(sv.metrics().pushes, 0);
+//! ```
+
+use StableVecFacade;
+use storage::Storage;
+
+
+/// Counters of the operations performed on a stable vector.
+///
+/// Returned by
+/// [`StableVec::metrics()`](../struct.StableVecFacade.html#method.metrics).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Metrics {
+    /// The number of elements inserted by `push()`, `push_reuse()` and the
+    /// methods built on them (e.g. `extend()`).
+    pub pushes: usize,
+
+    /// The number of elements removed.
+    pub removals: usize,
+
+    /// The number of pushes which filled a hole instead of appending.
+    pub hole_reuses: usize,
+
+    /// The number of times the storage changed its capacity.
+    pub reallocations: usize,
+
+    /// The number of compactions which had to move elements.
+    pub compactions: usize,
+
+    /// The number of elements moved by compactions.
+    pub elements_moved: usize,
+}
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Returns the operation counters since the creation of this stable
+    /// vector or the last call to [`reset_metrics()`](#method.reset_metrics).
+    ///
+    /// Only available with the `stats` feature. See [the `stats`
+    /// module](stats/index.html).
+    pub fn metrics(&self) -> Metrics {
+        self.metrics
+    }
+
+    /// Resets all operation counters to 0.
+    pub fn reset_metrics(&mut self) {
+        self.metrics = Metrics::default();
+    }
+
+    /// Counts a reallocation if the capacity of the storage isn't
+    /// `old_capacity` anymore.
+    pub(crate) fn count_reallocation(&mut self, old_capacity: usize) {
+        if self.storage.capacity() != old_capacity {
+            self.metrics.reallocations += 1;
+        }
+    }
+}
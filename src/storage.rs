@@ -0,0 +1,944 @@
+//! Storage strategies for the elements of a stable vector.
+//!
+//! The second type parameter of
+//! [`StableVecFacade`](../struct.StableVecFacade.html) selects how slots and
+//! their occupancy are stored. Different workloads
+//! profit from different trade-offs:
+//!
+//! - [`BitmapStorage`](struct.BitmapStorage.html) (the default) stores raw
+//!   elements plus one bit per slot. This has the smallest memory footprint
+//!   and supports contiguous slices of elements.
+//! - [`OptionStorage`](struct.OptionStorage.html) stores an `Option<T>` per
+//!   slot. For types with a niche (references, `Box`, `NonZero*`, ...), this
+//!   needs no extra memory at all and keeps element and occupancy in the same
+//!   cache line.
+//! - [`SegmentedStorage`](struct.SegmentedStorage.html) stores the slots in
+//!   segments which are never reallocated, so elements don't move when the
+//!   stable vector grows.
+//! - [`ArrayStorage`](struct.ArrayStorage.html) stores up to `N` slots in an
+//!   array and never allocates.
+//! - [`SmallStorage`](struct.SmallStorage.html) stores up to `N` slots inline
+//!   and moves them to the heap when more are needed.
+//!
+//! With the `allocator_api` feature (which requires a nightly compiler),
+//! [`AllocStorage`](struct.AllocStorage.html) stores its slots in memory
+//! obtained from a custom allocator.
+//!
+//! The [`Storage`](trait.Storage.html) trait is sealed: it can't be
+//! implemented outside of this crate, so new strategies can be added without
+//! breaking changes.
+//!
+//! # Example
+//!
+//! ```
+//! use stable_vec::{OptionStableVec, StableVecFacade};
+//! use stable_vec::storage::OptionStorage;
+//!
+//! let mut sv = OptionStableVec::new();
+//! // The same type, written out:
+//! let _: &StableVecFacade<Box<u32>, OptionStorage<_>> = &sv;
+//!
+//! let a = sv.push(Box::new(1));
+//! sv.push(Box::new(2));
+//! sv.remove(a);
+//!
+//! assert_eq!(sv.iter().map(|b| **b).collect::<Vec<_>>(), vec![2]);
+//! ```
+
+use bit_vec::BitVec;
+
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+#[cfg(feature = "allocator_api")]
+use core::alloc::Allocator;
+use core::mem::{self, ManuallyDrop, MaybeUninit};
+use core::ptr;
+
+
+mod sealed {
+    pub trait Sealed {}
+}
+
+/// A strategy to store the slots of a stable vector. See [the module
+/// documentation](index.html) for more information.
+///
+/// This trait is sealed and can't be implemented outside of this crate. It
+/// only offers the primitive slot operations; element counting and all other
+/// logic lives in `StableVecFacade`.
+pub trait Storage<T>: sealed::Sealed + Sized {
+    /// Whether the stable vector keeps a stack of holes to find the most
+    /// recently created one in O(1). Storages which must never allocate opt
+    /// out; `push_reuse()` then fills the hole with the largest index.
+    const HOLE_STACK: bool = true;
+
+    /// Returns the number of slots (including holes).
+    fn len(&self) -> usize;
+
+    /// Returns `true` if there are no slots.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the number of slots this storage can hold without
+    /// reallocating.
+    fn capacity(&self) -> usize;
+
+    /// Reserves capacity for at least `additional` more slots.
+    fn reserve(&mut self, additional: usize);
+
+    /// Shrinks the capacity as much as possible.
+    fn shrink_to_fit(&mut self);
+
+    /// Returns `true` if the slot at `index` exists and is not a hole.
+    fn has_element_at(&self, index: usize) -> bool;
+
+    /// Returns the smallest index `>= from` whose slot is not a hole.
+    fn next_occupied(&self, from: usize) -> Option<usize> {
+        (from..self.len()).find(|&i| self.has_element_at(i))
+    }
+
+    /// Returns the smallest index `>= from` whose slot is a hole.
+    fn next_hole(&self, from: usize) -> Option<usize> {
+        (from..self.len()).find(|&i| !self.has_element_at(i))
+    }
+
+    /// Appends a slot containing `elem`.
+    fn push(&mut self, elem: T);
+
+    /// Appends a hole.
+    fn push_hole(&mut self);
+
+    /// Returns a reference to the element at `index`.
+    ///
+    /// # Safety
+    ///
+    /// The slot at `index` must exist and must not be a hole.
+    unsafe fn get_unchecked(&self, index: usize) -> &T;
+
+    /// Returns a mutable reference to the element at `index`.
+    ///
+    /// # Safety
+    ///
+    /// The slot at `index` must exist and must not be a hole.
+    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut T;
+
+    /// Stores `elem` in the hole at `index`.
+    ///
+    /// # Safety
+    ///
+    /// The slot at `index` must exist and must be a hole.
+    unsafe fn insert_at(&mut self, index: usize, elem: T);
+
+    /// Moves the element at `index` out, leaving a hole.
+    ///
+    /// # Safety
+    ///
+    /// The slot at `index` must exist and must not be a hole.
+    unsafe fn remove_at(&mut self, index: usize) -> T;
+
+    /// Swaps the slots at the given indices (which might be holes).
+    fn swap(&mut self, a: usize, b: usize);
+
+    /// Shortens the storage to `len` slots.
+    ///
+    /// # Safety
+    ///
+    /// All slots at indices `>= len` must be holes.
+    unsafe fn truncate(&mut self, len: usize);
+
+    /// Moves all elements into a `Vec<T>`, preserving their order.
+    ///
+    /// # Safety
+    ///
+    /// No slot may be a hole.
+    unsafe fn into_vec(self) -> Vec<T>;
+}
+
+/// A storage which can be created without any further information.
+///
+/// This is implemented by all storages except for those which need a
+/// runtime value (like an allocator) to be created. Like `Storage`, this
+/// trait is sealed.
+pub trait NewStorage<T>: Storage<T> {
+    /// Creates an empty storage without allocating.
+    fn new() -> Self;
+
+    /// Creates an empty storage which can hold `capacity` slots without
+    /// reallocating.
+    fn with_capacity(capacity: usize) -> Self;
+}
+
+
+/// The default storage: raw elements plus an occupancy bitmap.
+///
+/// Needs one bit per slot in addition to the elements themselves. Ranges of
+/// slots without holes can be accessed as contiguous slices (see
+/// [`StableVec::slice()`](../struct.StableVecFacade.html#method.slice)).
+pub struct BitmapStorage<T> {
+    /// The slots. Slots whose `deleted` flag is set are uninitialized.
+    pub(crate) data: Vec<MaybeUninit<T>>,
+
+    /// A flag for each slot saying whether it is a hole.
+    pub(crate) deleted: BitVec,
+}
+
+impl<T> sealed::Sealed for BitmapStorage<T> {}
+
+impl<T> NewStorage<T> for BitmapStorage<T> {
+    fn new() -> Self {
+        Self {
+            data: Vec::new(),
+            deleted: BitVec::new(),
+        }
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            data: Vec::with_capacity(capacity),
+            deleted: BitVec::with_capacity(capacity),
+        }
+    }
+}
+
+impl<T> Storage<T> for BitmapStorage<T> {
+    fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    fn capacity(&self) -> usize {
+        self.data.capacity()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.data.reserve(additional);
+        self.deleted.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        self.data.shrink_to_fit();
+        self.deleted.shrink_to_fit();
+    }
+
+    fn has_element_at(&self, index: usize) -> bool {
+        index < self.data.len() && !self.deleted[index]
+    }
+
+    fn next_occupied(&self, from: usize) -> Option<usize> {
+        // A set bit in `deleted` means "hole", so we flip all bits.
+        find_bit(&self.deleted, self.data.len(), from, !0)
+    }
+
+    fn next_hole(&self, from: usize) -> Option<usize> {
+        find_bit(&self.deleted, self.data.len(), from, 0)
+    }
+
+    fn push(&mut self, elem: T) {
+        self.data.push(MaybeUninit::new(elem));
+        self.deleted.push(false);
+    }
+
+    fn push_hole(&mut self) {
+        self.data.push(MaybeUninit::uninit());
+        self.deleted.push(true);
+    }
+
+    unsafe fn get_unchecked(&self, index: usize) -> &T {
+        &*(*self.data.as_ptr().add(index)).as_ptr()
+    }
+
+    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut T {
+        // We don't create a reference to the whole slice here, as references
+        // to other elements might still be alive (e.g. in `IterMut`).
+        &mut *(*self.data.as_mut_ptr().add(index)).as_mut_ptr()
+    }
+
+    unsafe fn insert_at(&mut self, index: usize, elem: T) {
+        self.data[index] = MaybeUninit::new(elem);
+        self.deleted.set(index, false);
+    }
+
+    unsafe fn remove_at(&mut self, index: usize) -> T {
+        // We move the element out without removing the slot. The slot is
+        // uninitialized afterwards, so we mark it as deleted to prevent any
+        // further access.
+        self.deleted.set(index, true);
+        self.data[index].as_ptr().read()
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.data.swap(a, b);
+        let deleted_a = self.deleted[a];
+        let deleted_b = self.deleted[b];
+        self.deleted.set(a, deleted_b);
+        self.deleted.set(b, deleted_a);
+    }
+
+    unsafe fn truncate(&mut self, len: usize) {
+        // `MaybeUninit` slots are never dropped, which is exactly what we
+        // want for holes.
+        self.data.truncate(len);
+        self.deleted.truncate(len);
+    }
+
+    unsafe fn into_vec(mut self) -> Vec<T> {
+        // We reset the bitmap, so that our `drop()` doesn't do anything
+        // (except for freeing the memory of `deleted`).
+        self.deleted.truncate(0);
+
+        // `MaybeUninit<T>` has the same layout as `T` and all slots are
+        // initialized.
+        let data = ManuallyDrop::new(mem::take(&mut self.data));
+        Vec::from_raw_parts(data.as_ptr() as *mut T, data.len(), data.capacity())
+    }
+}
+
+impl<T> Drop for BitmapStorage<T> {
+    fn drop(&mut self) {
+        // The Vec never drops its `MaybeUninit<T>` slots, so we drop all
+        // elements that have not been removed manually. The Vec frees its
+        // memory afterwards.
+        let living_indices = self.deleted.iter()
+            .enumerate()
+            .filter_map(|(i, deleted)| if deleted { None } else { Some(i) });
+        for i in living_indices {
+            unsafe {
+                ptr::drop_in_place(self.data[i].as_mut_ptr());
+            }
+        }
+    }
+}
+
+
+/// The number of slots in the first segment of a `SegmentedStorage` is
+/// `1 << FIRST_SEGMENT_BITS`.
+const FIRST_SEGMENT_BITS: u32 = 5;
+
+/// Storage which never moves its elements.
+///
+/// The slots are stored in separately allocated segments, segment `i` having
+/// `32 << i` slots. Growing allocates a new segment and leaves all existing
+/// ones untouched, so pointers to elements stay valid until the element is
+/// removed (see
+/// [`SegmentedStableVec::get_ptr()`](../struct.StableVecFacade.html#method.get_ptr)).
+/// Like [`BitmapStorage`](struct.BitmapStorage.html), it uses one bit per
+/// slot to mark holes.
+///
+/// Accessing an element is slightly slower than with the default storage,
+/// as the segment has to be computed from the index first.
+pub struct SegmentedStorage<T> {
+    /// Pointers to the allocated segments. Slots whose `deleted` flag is set
+    /// and slots at indices `>= len` are uninitialized.
+    segments: Vec<*mut MaybeUninit<T>>,
+
+    /// The number of slots.
+    len: usize,
+
+    /// A flag for each slot saying whether it is a hole.
+    deleted: BitVec,
+}
+
+unsafe impl<T: Send> Send for SegmentedStorage<T> {}
+unsafe impl<T: Sync> Sync for SegmentedStorage<T> {}
+
+impl<T> SegmentedStorage<T> {
+    /// Returns a pointer to the given slot, which has to be within the
+    /// capacity.
+    fn slot_ptr(&self, index: usize) -> *mut MaybeUninit<T> {
+        let shifted = index + (1 << FIRST_SEGMENT_BITS);
+        let bit = usize::BITS - 1 - shifted.leading_zeros();
+        let segment = (bit - FIRST_SEGMENT_BITS) as usize;
+        unsafe { self.segments[segment].add(shifted - (1 << bit)) }
+    }
+
+    /// Allocates segments until there are at least `capacity` slots.
+    fn grow_to(&mut self, capacity: usize) {
+        while self.capacity() < capacity {
+            let len = segment_len(self.segments.len());
+            let segment: Box<[MaybeUninit<T>]> = (0..len).map(|_| MaybeUninit::uninit()).collect();
+            self.segments.push(Box::into_raw(segment) as *mut MaybeUninit<T>);
+        }
+    }
+
+    /// Appends a slot with the given content and `deleted` flag.
+    fn push_slot(&mut self, slot: MaybeUninit<T>, deleted: bool) {
+        let index = self.len;
+        self.grow_to(index + 1);
+        unsafe {
+            self.slot_ptr(index).write(slot);
+        }
+        self.deleted.push(deleted);
+        self.len += 1;
+    }
+}
+
+impl<T> sealed::Sealed for SegmentedStorage<T> {}
+
+impl<T> NewStorage<T> for SegmentedStorage<T> {
+    fn new() -> Self {
+        Self {
+            segments: Vec::new(),
+            len: 0,
+            deleted: BitVec::new(),
+        }
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        let mut out = Self::new();
+        out.reserve(capacity);
+        out
+    }
+}
+
+impl<T> Storage<T> for SegmentedStorage<T> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        // The segments have 32, 64, 128, ... slots.
+        segment_len(self.segments.len()) - segment_len(0)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        let capacity = self.len.checked_add(additional).expect("capacity overflow");
+        self.grow_to(capacity);
+        self.deleted.reserve(additional);
+    }
+
+    fn shrink_to_fit(&mut self) {
+        // We can only free segments which don't contain any slots.
+        while !self.segments.is_empty()
+            && self.capacity() - segment_len(self.segments.len() - 1) >= self.len
+        {
+            let last = self.segments.pop().unwrap();
+            let len = segment_len(self.segments.len());
+            unsafe {
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(last, len)));
+            }
+        }
+    }
+
+    fn has_element_at(&self, index: usize) -> bool {
+        index < self.len && !self.deleted[index]
+    }
+
+    fn next_occupied(&self, from: usize) -> Option<usize> {
+        find_bit(&self.deleted, self.len, from, !0)
+    }
+
+    fn next_hole(&self, from: usize) -> Option<usize> {
+        find_bit(&self.deleted, self.len, from, 0)
+    }
+
+    fn push(&mut self, elem: T) {
+        self.push_slot(MaybeUninit::new(elem), false);
+    }
+
+    fn push_hole(&mut self) {
+        self.push_slot(MaybeUninit::uninit(), true);
+    }
+
+    unsafe fn get_unchecked(&self, index: usize) -> &T {
+        &*(*self.slot_ptr(index)).as_ptr()
+    }
+
+    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut T {
+        &mut *(*self.slot_ptr(index)).as_mut_ptr()
+    }
+
+    unsafe fn insert_at(&mut self, index: usize, elem: T) {
+        self.slot_ptr(index).write(MaybeUninit::new(elem));
+        self.deleted.set(index, false);
+    }
+
+    unsafe fn remove_at(&mut self, index: usize) -> T {
+        self.deleted.set(index, true);
+        (*self.slot_ptr(index)).as_ptr().read()
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        assert!(a < self.len && b < self.len);
+        unsafe {
+            ptr::swap(self.slot_ptr(a), self.slot_ptr(b));
+        }
+        let deleted_a = self.deleted[a];
+        let deleted_b = self.deleted[b];
+        self.deleted.set(a, deleted_b);
+        self.deleted.set(b, deleted_a);
+    }
+
+    unsafe fn truncate(&mut self, len: usize) {
+        if len < self.len {
+            self.len = len;
+            self.deleted.truncate(len);
+        }
+    }
+
+    unsafe fn into_vec(mut self) -> Vec<T> {
+        let out = (0..self.len).map(|i| (*self.slot_ptr(i)).as_ptr().read()).collect();
+
+        // All elements have been moved out, so our `drop()` must not drop
+        // them again.
+        self.len = 0;
+        self.deleted.truncate(0);
+        out
+    }
+}
+
+impl<T> Drop for SegmentedStorage<T> {
+    fn drop(&mut self) {
+        for i in 0..self.len {
+            if !self.deleted[i] {
+                unsafe {
+                    ptr::drop_in_place((*self.slot_ptr(i)).as_mut_ptr());
+                }
+            }
+        }
+
+        for (segment, &ptr) in self.segments.iter().enumerate() {
+            unsafe {
+                drop(Box::from_raw(ptr::slice_from_raw_parts_mut(ptr, segment_len(segment))));
+            }
+        }
+    }
+}
+
+/// Returns the number of slots in the given segment of a `SegmentedStorage`.
+fn segment_len(segment: usize) -> usize {
+    (1 << FIRST_SEGMENT_BITS) << segment
+}
+
+
+/// Storage which stores an `Option<T>` per slot.
+///
+/// For types where `Option<T>` is not larger than `T` (e.g. references,
+/// `Box<T>` or `NonZeroU32`), this uses less memory than
+/// [`BitmapStorage`](struct.BitmapStorage.html). Otherwise it uses up to one
+/// additional word per slot (e.g. `Option<u64>` is 16 bytes).
+pub struct OptionStorage<T> {
+    slots: Vec<Option<T>>,
+}
+
+impl<T> sealed::Sealed for OptionStorage<T> {}
+
+impl<T> NewStorage<T> for OptionStorage<T> {
+    fn new() -> Self {
+        Self { slots: Vec::new() }
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self { slots: Vec::with_capacity(capacity) }
+    }
+}
+
+/// Implements the methods of `Storage` which only access single slots, for
+/// a type with a `slots` field holding `Option<T>`s (a `Vec` or an array),
+/// where all slots at indices `>= len()` are `None`.
+macro_rules! option_slot_methods {
+    () => {
+        fn has_element_at(&self, index: usize) -> bool {
+            self.slots.get(index).is_some_and(Option::is_some)
+        }
+
+        unsafe fn get_unchecked(&self, index: usize) -> &T {
+            match *self.slots.as_ptr().add(index) {
+                Some(ref elem) => elem,
+                None => unreachable(),
+            }
+        }
+
+        unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut T {
+            // See `BitmapStorage::get_unchecked_mut()`.
+            match *self.slots.as_mut_ptr().add(index) {
+                Some(ref mut elem) => elem,
+                None => unreachable(),
+            }
+        }
+
+        unsafe fn insert_at(&mut self, index: usize, elem: T) {
+            self.slots[index] = Some(elem);
+        }
+
+        unsafe fn remove_at(&mut self, index: usize) -> T {
+            match self.slots[index].take() {
+                Some(elem) => elem,
+                None => unreachable(),
+            }
+        }
+
+        fn swap(&mut self, a: usize, b: usize) {
+            self.slots.swap(a, b);
+        }
+    };
+}
+
+/// Implements the methods of `Storage` for a type with a `slots` field of
+/// type `Vec<Option<T>, _>`, i.e. `OptionStorage` and `AllocStorage`.
+macro_rules! option_storage_methods {
+    () => {
+        option_slot_methods!();
+
+        fn len(&self) -> usize {
+            self.slots.len()
+        }
+
+        fn capacity(&self) -> usize {
+            self.slots.capacity()
+        }
+
+        fn reserve(&mut self, additional: usize) {
+            self.slots.reserve(additional);
+        }
+
+        fn shrink_to_fit(&mut self) {
+            self.slots.shrink_to_fit();
+        }
+
+        fn push(&mut self, elem: T) {
+            self.slots.push(Some(elem));
+        }
+
+        fn push_hole(&mut self) {
+            self.slots.push(None);
+        }
+
+        unsafe fn truncate(&mut self, len: usize) {
+            self.slots.truncate(len);
+        }
+
+        unsafe fn into_vec(self) -> Vec<T> {
+            self.slots.into_iter().map(|slot| slot.unwrap_or_else(|| unreachable())).collect()
+        }
+    };
+}
+
+impl<T> Storage<T> for OptionStorage<T> {
+    option_storage_methods!();
+}
+
+/// Storage which stores an `Option<T>` per slot in memory obtained from the
+/// allocator `A`, like [`OptionStorage`](struct.OptionStorage.html) does with
+/// the global allocator.
+///
+/// Only available with the `allocator_api` feature, which requires a nightly
+/// compiler. Use
+/// [`AllocStableVec::new_in()`](../struct.StableVecFacade.html#method.new_in)
+/// to create a stable vector with this storage.
+#[cfg(feature = "allocator_api")]
+pub struct AllocStorage<T, A: Allocator> {
+    slots: Vec<Option<T>, A>,
+}
+
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator> AllocStorage<T, A> {
+    /// Creates an empty storage using the given allocator.
+    pub(crate) fn new_in(alloc: A) -> Self {
+        Self { slots: Vec::new_in(alloc) }
+    }
+
+    /// Creates an empty storage which can hold `capacity` slots without
+    /// reallocating, using the given allocator.
+    pub(crate) fn with_capacity_in(capacity: usize, alloc: A) -> Self {
+        Self { slots: Vec::with_capacity_in(capacity, alloc) }
+    }
+
+    /// Returns a reference to the allocator.
+    pub(crate) fn allocator(&self) -> &A {
+        self.slots.allocator()
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator> sealed::Sealed for AllocStorage<T, A> {}
+
+#[cfg(feature = "allocator_api")]
+impl<T, A: Allocator> Storage<T> for AllocStorage<T, A> {
+    option_storage_methods!();
+}
+
+/// Storage which stores up to `N` slots in an array and never allocates.
+///
+/// Each slot is stored as an `Option<T>`. Holes count as used slots, so
+/// pushing panics once `N` slots exist, even if some of them are holes. The
+/// stable vector doesn't keep a stack of holes with this storage, so that
+/// removing elements doesn't allocate either. Use
+/// [`ArrayStableVec::try_push()`](../struct.StableVecFacade.html#method.try_push)
+/// to handle a full storage.
+pub struct ArrayStorage<T, const N: usize> {
+    /// The first `len` slots are in use; all others are `None`.
+    slots: [Option<T>; N],
+    len: usize,
+}
+
+impl<T, const N: usize> sealed::Sealed for ArrayStorage<T, N> {}
+
+impl<T, const N: usize> NewStorage<T> for ArrayStorage<T, N> {
+    fn new() -> Self {
+        Self {
+            slots: [(); N].map(|_| None),
+            len: 0,
+        }
+    }
+
+    /// The capacity is always `N`, so `capacity` is ignored.
+    fn with_capacity(_capacity: usize) -> Self {
+        Self::new()
+    }
+}
+
+impl<T, const N: usize> Storage<T> for ArrayStorage<T, N> {
+    const HOLE_STACK: bool = false;
+
+    option_slot_methods!();
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Does nothing, as the capacity is fixed.
+    fn reserve(&mut self, _additional: usize) {}
+
+    /// Does nothing, as the capacity is fixed.
+    fn shrink_to_fit(&mut self) {}
+
+    fn push(&mut self, elem: T) {
+        assert!(self.len < N, "ArrayStableVec is full (capacity {})", N);
+        self.slots[self.len] = Some(elem);
+        self.len += 1;
+    }
+
+    fn push_hole(&mut self) {
+        assert!(self.len < N, "ArrayStableVec is full (capacity {})", N);
+        self.len += 1;
+    }
+
+    unsafe fn truncate(&mut self, len: usize) {
+        // All slots from `len` on are holes, i.e. `None`, already.
+        self.len = self.len.min(len);
+    }
+
+    unsafe fn into_vec(self) -> Vec<T> {
+        IntoIterator::into_iter(self.slots)
+            .take(self.len)
+            .map(|slot| slot.unwrap_or_else(|| unreachable()))
+            .collect()
+    }
+}
+
+/// Storage which stores up to `N` slots inline and moves all slots to the
+/// heap when more are needed.
+///
+/// This avoids the heap allocation for lots of tiny stable vectors (e.g. one
+/// per node of a graph). Each slot is stored as an `Option<T>`, both inline
+/// and on the heap. Once spilled, the slots stay on the heap. Holes count as
+/// slots: removing an element doesn't make room for another inline element.
+pub struct SmallStorage<T, const N: usize> {
+    slots: SmallSlots<T, N>,
+}
+
+/// The slots of a `SmallStorage`.
+enum SmallSlots<T, const N: usize> {
+    /// The first `len` slots of `buf` are in use; all others are `None`.
+    Inline { buf: [Option<T>; N], len: usize },
+    Heap(Vec<Option<T>>),
+}
+
+impl<T, const N: usize> SmallStorage<T, N> {
+    /// Returns `true` if the slots have been moved to the heap.
+    pub(crate) fn spilled(&self) -> bool {
+        match self.slots {
+            SmallSlots::Inline { .. } => false,
+            SmallSlots::Heap(_) => true,
+        }
+    }
+
+    /// Returns all slots which are in use.
+    fn slots(&self) -> &[Option<T>] {
+        match self.slots {
+            SmallSlots::Inline { ref buf, len } => &buf[..len],
+            SmallSlots::Heap(ref vec) => vec,
+        }
+    }
+
+    /// Returns all slots which are in use.
+    fn slots_mut(&mut self) -> &mut [Option<T>] {
+        match self.slots {
+            SmallSlots::Inline { ref mut buf, len } => &mut buf[..len],
+            SmallSlots::Heap(ref mut vec) => vec,
+        }
+    }
+
+    /// Appends the given slot, moving all slots to the heap if there is no
+    /// room for another inline slot.
+    fn push_slot(&mut self, slot: Option<T>) {
+        match self.slots {
+            SmallSlots::Inline { ref mut buf, ref mut len } if *len < N => {
+                buf[*len] = slot;
+                *len += 1;
+                return;
+            }
+            SmallSlots::Inline { .. } => self.spill(2 * N + 1),
+            SmallSlots::Heap(_) => {}
+        }
+        if let SmallSlots::Heap(ref mut vec) = self.slots {
+            vec.push(slot);
+        }
+    }
+
+    /// Moves the inline slots to the heap, reserving room for `capacity`
+    /// slots. The slots have to be inline.
+    fn spill(&mut self, capacity: usize) {
+        if let SmallSlots::Inline { ref mut buf, len } = self.slots {
+            let mut vec = Vec::with_capacity(capacity.max(len));
+            vec.extend(buf[..len].iter_mut().map(Option::take));
+            self.slots = SmallSlots::Heap(vec);
+        }
+    }
+}
+
+impl<T, const N: usize> sealed::Sealed for SmallStorage<T, N> {}
+
+impl<T, const N: usize> NewStorage<T> for SmallStorage<T, N> {
+    fn new() -> Self {
+        Self {
+            slots: SmallSlots::Inline { buf: [(); N].map(|_| None), len: 0 },
+        }
+    }
+
+    fn with_capacity(capacity: usize) -> Self {
+        let mut out = Self::new();
+        if capacity > N {
+            out.spill(capacity);
+        }
+        out
+    }
+}
+
+impl<T, const N: usize> Storage<T> for SmallStorage<T, N> {
+    fn len(&self) -> usize {
+        self.slots().len()
+    }
+
+    fn capacity(&self) -> usize {
+        match self.slots {
+            SmallSlots::Inline { .. } => N,
+            SmallSlots::Heap(ref vec) => vec.capacity(),
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        match self.slots {
+            SmallSlots::Inline { len, .. } if len + additional > N => {
+                self.spill(len + additional);
+            }
+            SmallSlots::Inline { .. } => {}
+            SmallSlots::Heap(ref mut vec) => vec.reserve(additional),
+        }
+    }
+
+    /// Shrinks the heap allocation, if any. The slots stay on the heap.
+    fn shrink_to_fit(&mut self) {
+        if let SmallSlots::Heap(ref mut vec) = self.slots {
+            vec.shrink_to_fit();
+        }
+    }
+
+    fn has_element_at(&self, index: usize) -> bool {
+        self.slots().get(index).is_some_and(Option::is_some)
+    }
+
+    fn push(&mut self, elem: T) {
+        self.push_slot(Some(elem));
+    }
+
+    fn push_hole(&mut self) {
+        self.push_slot(None);
+    }
+
+    unsafe fn get_unchecked(&self, index: usize) -> &T {
+        match *self.slots().get_unchecked(index) {
+            Some(ref elem) => elem,
+            None => unreachable(),
+        }
+    }
+
+    unsafe fn get_unchecked_mut(&mut self, index: usize) -> &mut T {
+        match *self.slots_mut().get_unchecked_mut(index) {
+            Some(ref mut elem) => elem,
+            None => unreachable(),
+        }
+    }
+
+    unsafe fn insert_at(&mut self, index: usize, elem: T) {
+        self.slots_mut()[index] = Some(elem);
+    }
+
+    unsafe fn remove_at(&mut self, index: usize) -> T {
+        match self.slots_mut()[index].take() {
+            Some(elem) => elem,
+            None => unreachable(),
+        }
+    }
+
+    fn swap(&mut self, a: usize, b: usize) {
+        self.slots_mut().swap(a, b);
+    }
+
+    unsafe fn truncate(&mut self, new_len: usize) {
+        match self.slots {
+            SmallSlots::Inline { ref mut len, .. } => *len = (*len).min(new_len),
+            SmallSlots::Heap(ref mut vec) => vec.truncate(new_len),
+        }
+    }
+
+    unsafe fn into_vec(mut self) -> Vec<T> {
+        self.slots_mut()
+            .iter_mut()
+            .map(|slot| slot.take().unwrap_or_else(|| unreachable()))
+            .collect()
+    }
+}
+
+/// Returns the smallest index `>= from` and `< len` whose bit in `deleted` is
+/// set after XOR-ing its block with `flip`.
+pub(crate) fn find_bit(deleted: &BitVec, len: usize, from: usize, flip: u32) -> Option<usize> {
+    const BLOCK_BITS: usize = u32::BITS as usize;
+
+    if from >= len {
+        return None;
+    }
+
+    // Instead of checking one bit after another, we look at whole blocks
+    // of the bitmap. That way, long runs of holes (or elements) are
+    // skipped quickly.
+    let blocks = deleted.storage();
+    let mut block_index = from / BLOCK_BITS;
+
+    // We ignore the bits before `from` in the first block.
+    let mut bits = (blocks[block_index] ^ flip) & (!0 << (from % BLOCK_BITS));
+    loop {
+        if bits != 0 {
+            // The unused bits of the last block might be set after
+            // flipping. Thus we have to check the bounds.
+            let index = block_index * BLOCK_BITS + bits.trailing_zeros() as usize;
+            return if index < len { Some(index) } else { None };
+        }
+
+        block_index += 1;
+        if block_index == blocks.len() {
+            return None;
+        }
+        bits = blocks[block_index] ^ flip;
+    }
+}
+
+/// Used where the caller guaranteed that a slot is not a hole.
+#[inline(always)]
+unsafe fn unreachable() -> ! {
+    debug_assert!(false, "accessed a hole as if it were an element");
+    ::core::hint::unreachable_unchecked()
+}
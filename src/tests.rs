@@ -1,4 +1,20 @@
-use super::StableVec;
+use std::prelude::v1::*;
+
+use super::{PushPolicy, StableVec};
+
+
+/// Builds a stable vector with an element for each `Some` and a hole for
+/// each `None`.
+fn from_slots<T: Clone>(slots: &[Option<T>]) -> StableVec<T> {
+    let mut sv = StableVec::new();
+    for slot in slots {
+        match *slot {
+            Some(ref elem) => { sv.push(elem.clone()); }
+            None => sv.push_hole(),
+        }
+    }
+    sv
+}
 
 quickcheck! {
     fn reordering_compact(insertions: u16, to_delete: Vec<u16>) -> bool {
@@ -80,3 +96,1337 @@ fn compact_tiny() {
     sv.make_compact();
     assert_eq!(sv.into_vec(), &[1.0, 3.0]);
 }
+
+#[cfg(feature = "serde")]
+quickcheck! {
+    fn serde_round_trip(items: Vec<Option<u8>>) -> bool {
+        let sv = from_slots(&items);
+
+        let json = ::serde_json::to_string(&sv).unwrap();
+        let sv: StableVec<u8> = ::serde_json::from_str(&json).unwrap();
+
+        sv.next_index() == items.len()
+            && sv.num_elements() == items.iter().filter(|i| i.is_some()).count()
+            && items.iter().enumerate().all(|(i, item)| sv.get(i) == item.as_ref())
+    }
+}
+
+#[cfg(feature = "std")]
+quickcheck! {
+    fn snapshot_round_trip(items: Vec<Option<u32>>) -> bool {
+        let sv = from_slots(&items);
+
+        let mut buffer = Vec::new();
+        sv.write_snapshot(&mut buffer).unwrap();
+        let sv = StableVec::<u32>::read_snapshot(&buffer[..]).unwrap();
+
+        sv.next_index() == items.len()
+            && items.iter().enumerate().all(|(i, item)| sv.get(i) == item.as_ref())
+    }
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn snapshot_rejects_malformed_input() {
+    let mut buffer = Vec::new();
+    StableVec::from(&[1u8, 2, 3]).write_snapshot(&mut buffer).unwrap();
+
+    // Truncated
+    assert!(StableVec::<u8>::read_snapshot(&buffer[..buffer.len() - 1]).is_err());
+
+    // Wrong magic
+    let mut wrong_magic = buffer.clone();
+    wrong_magic[0] = b'X';
+    assert!(StableVec::<u8>::read_snapshot(&wrong_magic[..]).is_err());
+
+    // Unknown version
+    let mut wrong_version = buffer.clone();
+    wrong_version[4] = 99;
+    assert!(StableVec::<u8>::read_snapshot(&wrong_version[..]).is_err());
+}
+
+#[cfg(feature = "std")]
+quickcheck! {
+    fn patch_restores_current(before: Vec<Option<u8>>, after: Vec<Option<u8>>) -> bool {
+        use patch::Patch;
+        use snapshot::{Decode, Encode};
+
+        let baseline = from_slots(&before);
+        let current = from_slots(&after);
+
+        // Send the patch through the binary encoding, too.
+        let mut buffer = Vec::new();
+        Patch::between(&baseline, &current).encode(&mut buffer).unwrap();
+        let patch = Patch::<u8>::decode(&mut &buffer[..]).unwrap();
+
+        let mut replica = baseline.clone();
+        patch.apply_to(&mut replica);
+
+        replica.next_index() == current.next_index()
+            && replica.num_elements() == current.num_elements()
+            && (0..current.next_index()).all(|i| replica.get(i) == current.get(i))
+    }
+}
+
+#[cfg(feature = "arbitrary")]
+quickcheck! {
+    fn arbitrary_is_consistent(bytes: Vec<u8>) -> bool {
+        use arbitrary::{Arbitrary, Unstructured};
+
+        let sv = match StableVec::<u16>::arbitrary(&mut Unstructured::new(&bytes)) {
+            Ok(sv) => sv,
+            Err(_) => return true,
+        };
+
+        sv.num_elements() == sv.keys().count()
+            && sv.num_elements() == sv.iter().count()
+            && sv.keys().all(|i| i < sv.next_index())
+    }
+}
+
+#[cfg(feature = "bytemuck")]
+#[test]
+fn slice_bytes_of_zero_sized_elements() {
+    let mut sv = StableVec::from(&[(), (), ()]);
+    assert_eq!(sv.slice_bytes(..), Some(&[][..]));
+    assert_eq!(sv.slice_bytes_mut(1..).map(|b| b.len()), Some(0));
+
+    sv.remove(1);
+    assert_eq!(sv.slice_bytes(..), None);
+    assert_eq!(sv.slice_bytes_mut(..), None);
+}
+
+#[cfg(feature = "proptest")]
+#[test]
+fn proptest_shrinking_preserves_indices() {
+    use proptest_crate::prelude::*;
+    use proptest_crate::strategy::ValueTree;
+    use proptest_crate::test_runner::TestRunner;
+
+    let mut runner = TestRunner::deterministic();
+    let strategy = ::proptest::stable_vec(any::<u32>(), 20..30, 0.2);
+    for _ in 0..20 {
+        let mut tree = strategy.new_tree(&mut runner).unwrap();
+        let original = tree.current();
+
+        while tree.simplify() {
+            let sv = tree.current();
+            assert_eq!(sv.next_index(), original.next_index());
+            assert!(sv.keys().all(|i| original.has_element_at(i)));
+        }
+
+        // Everything was removed in the end.
+        assert!(tree.current().is_empty());
+    }
+}
+
+#[cfg(feature = "quickcheck")]
+quickcheck! {
+    fn quickcheck_shrinking_preserves_indices(sv: StableVec<String>) -> bool {
+        use quickcheck::Arbitrary;
+
+        sv.shrink().take(100).all(|shrunk| {
+            shrunk.next_index() == sv.next_index()
+                && shrunk.keys().all(|i| sv.has_element_at(i))
+        })
+    }
+}
+
+#[cfg(feature = "rayon")]
+quickcheck! {
+    fn par_iter_matches_iter(items: Vec<Option<u32>>) -> bool {
+        use rayon_crate::prelude::*;
+
+        let mut sv = from_slots(&items);
+
+        let expected: Vec<_> = sv.keys().map(|i| (i, sv[i])).collect();
+        let actual: Vec<_> = sv.par_iter().map(|(i, &e)| (i, e)).collect();
+
+        sv.par_iter_mut().for_each(|(i, e)| *e = i as u32);
+
+        actual == expected && sv.keys().all(|i| sv[i] == i as u32)
+    }
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn par_drain_drops_every_element_once() {
+    use rayon_crate::prelude::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    struct CountDrop(Arc<AtomicUsize>);
+    impl Drop for CountDrop {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let drops = Arc::new(AtomicUsize::new(0));
+    let mut sv: StableVec<_> = (0..1000).map(|_| CountDrop(drops.clone())).collect();
+    for i in (0..1000).step_by(3) {
+        sv.remove(i);
+    }
+    assert_eq!(drops.load(Ordering::SeqCst), 334);
+
+    // Stop early: all elements that are not consumed have to be dropped, too.
+    assert!(sv.par_drain().find_any(|&(i, _)| i == 500).is_some());
+    assert_eq!(drops.load(Ordering::SeqCst), 1000);
+    assert!(sv.is_empty());
+    assert_eq!(sv.next_index(), 0);
+
+    // Never driven at all
+    sv.extend((0..10).map(|_| CountDrop(drops.clone())));
+    drop(sv.par_drain());
+    assert_eq!(drops.load(Ordering::SeqCst), 1010);
+}
+
+#[test]
+fn concurrent_push_from_many_threads() {
+    use std::thread;
+    use ConcurrentStableVec;
+
+    let mut sv = ConcurrentStableVec::new();
+    let sv_ref = &sv;
+    let indices: Vec<Vec<usize>> = thread::scope(|s| {
+        let handles: Vec<_> = (0..8)
+            .map(|t| s.spawn(move || (0..1000).map(|i| sv_ref.push(t * 1000 + i)).collect()))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut all: Vec<_> = indices.iter().flatten().cloned().collect();
+    all.sort();
+    assert_eq!(all, (0..8000).collect::<Vec<_>>());
+    for (t, thread_indices) in indices.iter().enumerate() {
+        for (i, &idx) in thread_indices.iter().enumerate() {
+            assert_eq!(sv.get(idx), Some(&(t * 1000 + i)));
+        }
+    }
+
+    for i in (0..8000).step_by(2) {
+        assert!(sv.remove(i).is_some());
+    }
+    assert_eq!(sv.num_elements(), 4000);
+    assert_eq!(sv.iter().count(), 4000);
+
+    let before: Vec<_> = sv.iter().map(|(_, &e)| e).collect();
+    sv.make_compact();
+    assert_eq!(sv.next_index(), 4000);
+    assert_eq!(sv.into_stable_vec().into_vec(), before);
+}
+
+#[cfg(feature = "std")]
+#[test]
+fn sharded_insert_and_remove_from_many_threads() {
+    use std::thread;
+    use ShardedStableVec;
+
+    let sv = ShardedStableVec::new(3);
+    let sv_ref = &sv;
+    let kept: Vec<Vec<(usize, u32)>> = thread::scope(|s| {
+        let handles: Vec<_> = (0..6)
+            .map(|t| s.spawn(move || {
+                let mut kept = Vec::new();
+                for i in 0..500 {
+                    let elem = t * 500 + i;
+                    let idx = sv_ref.push(elem);
+                    if i % 3 == 0 {
+                        assert_eq!(sv_ref.remove(idx), Some(elem));
+                    } else {
+                        kept.push((idx, elem));
+                    }
+                }
+                kept
+            }))
+            .collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut kept: Vec<_> = kept.into_iter().flatten().collect();
+    kept.sort();
+    assert_eq!(sv.num_elements(), kept.len());
+    for &(idx, elem) in &kept {
+        assert_eq!(sv.with(idx, |&e| e), Some(elem));
+    }
+
+    let out = sv.into_stable_vec();
+    assert_eq!(out.num_elements(), kept.len());
+    assert!(kept.iter().all(|&(idx, elem)| out.get(idx) == Some(&elem)));
+}
+
+quickcheck! {
+    fn option_storage_matches_bitmap_storage(items: Vec<u8>, to_delete: Vec<u16>) -> bool {
+        use OptionStableVec;
+
+        let mut a = StableVec::from(&items);
+        let mut b = OptionStableVec::from(&items);
+        for i in to_delete {
+            let i = i as usize % (items.len() + 1);
+            if a.remove(i) != b.remove(i) {
+                return false;
+            }
+        }
+
+        let same_layout = a.keys().eq(b.keys()) && a.iter().eq(b.iter());
+        a.retain(|&e| e % 2 == 0);
+        b.retain(|&e| e % 2 == 0);
+        let same_retained = a.keys().eq(b.keys());
+        a.make_compact();
+        b.make_compact();
+
+        same_layout && same_retained && a.into_vec() == b.into_vec()
+    }
+
+    fn segmented_storage_matches_bitmap_storage(items: Vec<u8>, to_delete: Vec<u16>) -> bool {
+        use SegmentedStableVec;
+
+        let mut a = StableVec::from(&items);
+        let mut b = SegmentedStableVec::from(&items);
+        for i in to_delete {
+            let i = i as usize % (items.len() + 1);
+            if a.remove(i) != b.remove(i) {
+                return false;
+            }
+        }
+
+        let same_layout = a.keys().eq(b.keys()) && a.iter().eq(b.iter());
+        a.reordering_make_compact();
+        b.reordering_make_compact();
+        b.shrink_to_fit();
+        let enough_capacity = b.capacity() >= b.num_elements();
+
+        same_layout && enough_capacity && a.into_vec() == b.into_vec()
+    }
+}
+
+quickcheck! {
+    fn keys_skip_holes(len: u16, to_keep: Vec<u16>) -> bool {
+        // Mostly holes, with long runs of them.
+        let len = len as usize;
+        let mut sv = StableVec::from(vec![0; len]);
+        let keep: Vec<_> = to_keep.iter().map(|&i| i as usize % (len + 1)).collect();
+        for i in 0..len {
+            if !keep.contains(&i) {
+                sv.remove(i);
+            }
+        }
+
+        sv.keys().eq((0..len).filter(|&i| sv.has_element_at(i)))
+            && sv.iter().count() == sv.num_elements()
+    }
+}
+
+quickcheck! {
+    fn push_reuse_fills_holes_first(len: u8, to_remove: Vec<u8>) -> bool {
+        let len = len as usize;
+        let mut sv = StableVec::from(vec![0; len]);
+        for &i in &to_remove {
+            sv.remove(i as usize);
+        }
+        let holes = len - sv.num_elements();
+
+        let mut reused: Vec<_> = (0..holes).map(|_| sv.push_reuse(1)).collect();
+        reused.sort();
+        reused.dedup();
+
+        reused.len() == holes
+            && sv.is_compact()
+            && sv.push_reuse(2) == len
+    }
+}
+
+quickcheck! {
+    fn free_slots_stay_bounded(len: u8, ops: Vec<(u8, u8)>) -> bool {
+        let len = len as usize + 1;
+        let mut sv = StableVec::from(vec![0; len]);
+        sv.set_push_policy(PushPolicy::PreferLowest);
+
+        // The unpruned stack of holes, to check that pruning doesn't change
+        // which hole `push_reuse()` fills.
+        let mut model = Vec::new();
+        let mut ok = true;
+        for (op, n) in ops {
+            let i = n as usize % len;
+            match op % 4 {
+                0 => if sv.remove(i).is_some() {
+                    model.push(i);
+                },
+                1 => { let _ = sv.try_insert_at(i, n); }
+                2 => if sv.num_elements() < len {
+                    sv.push(n);
+                },
+                _ => {
+                    while let Some(&j) = model.last() {
+                        if !sv.has_element_at(j) {
+                            break;
+                        }
+                        model.pop();
+                    }
+                    if let Some(j) = model.pop() {
+                        ok &= sv.push_reuse(n) == j;
+                    }
+                }
+            }
+            ok &= sv.next_index() == len && sv.free_slots.len() <= 2 * len + 1;
+        }
+        ok && sv.validate().is_ok()
+    }
+}
+
+#[test]
+fn free_slots_stay_bounded_under_churn() {
+    let mut sv = StableVec::with_push_policy(PushPolicy::PreferLowest);
+    sv.extend(0..10);
+    for n in 0..1000 {
+        sv.remove(0);
+        sv.push(n);
+        sv.remove(5);
+        sv.try_insert_at(5, n).unwrap();
+    }
+    assert_eq!(sv.next_index(), 10);
+    assert!(sv.free_slots.len() <= 2);
+}
+
+quickcheck! {
+    fn push_policies_fill_holes(len: u8, to_remove: Vec<u8>) -> bool {
+        let len = len as usize;
+        [PushPolicy::Append, PushPolicy::PreferHoles, PushPolicy::PreferLowest]
+            .iter()
+            .all(|&policy| {
+                let mut sv = StableVec::from(vec![0; len]);
+                sv.set_push_policy(policy);
+                for &i in &to_remove {
+                    sv.remove(i as usize);
+                }
+
+                let expected = sv.next_push_index();
+                let lowest_hole = (0..len).find(|&i| !sv.has_element_at(i));
+                let index = sv.push(1);
+                index == expected && match policy {
+                    PushPolicy::Append => index == len,
+                    PushPolicy::PreferHoles => lowest_hole.is_some() == (index < len),
+                    PushPolicy::PreferLowest => Some(index) == lowest_hole.or(Some(len)),
+                }
+            })
+    }
+}
+
+quickcheck! {
+    fn rank_index_matches_keys(len: u16, to_remove: Vec<u16>) -> bool {
+        let len = len as usize;
+        let mut sv = StableVec::from(vec![0; len]);
+        for &i in &to_remove {
+            sv.remove(i as usize);
+        }
+
+        let ranks = sv.rank_index();
+        let keys: Vec<_> = sv.keys().collect();
+        let all_match = keys.iter()
+            .enumerate()
+            .all(|(n, &i)| ranks.nth_present(n) == Some(i) && ranks.rank(i) == n);
+
+        all_match && ranks.nth_present(keys.len()).is_none() && ranks.rank(len) == keys.len()
+    }
+}
+
+#[test]
+fn stable_vec32_from_stable_vec() {
+    use StableVec32;
+
+    let mut sv = StableVec::from(&[1, 2, 3]);
+    sv.remove(1);
+
+    let sv32 = StableVec32::from_stable_vec(sv).ok().unwrap();
+    assert_eq!(sv32.keys().collect::<Vec<u32>>(), vec![0, 2]);
+    assert_eq!(sv32.next_index(), 3);
+    assert!(!sv32.has_element_at(1));
+    assert_eq!(sv32[2], 3);
+}
+
+quickcheck! {
+    fn small_stable_vec_matches_stable_vec(items: Vec<u8>, to_delete: Vec<u16>) -> bool {
+        use SmallStableVec;
+
+        let mut a = StableVec::from(&items);
+        let mut b: SmallStableVec<_, 4> = items.iter().cloned().collect();
+        for i in to_delete {
+            let i = i as usize % (items.len() + 1);
+            if a.remove(i) != b.remove(i) {
+                return false;
+            }
+        }
+
+        b.spilled() == (items.len() > 4)
+            && a.keys().eq(b.keys())
+            && a.iter().eq(b.iter())
+            && a.next_index() == b.next_index()
+            && b.validate().is_ok()
+    }
+}
+
+#[cfg(feature = "allocator_api")]
+#[test]
+fn alloc_stable_vec_uses_allocator() {
+    use std::alloc::{AllocError, Allocator, Global, Layout};
+    use std::cell::Cell;
+    use std::ptr::NonNull;
+    use AllocStableVec;
+
+    struct Counting<'a>(&'a Cell<usize>);
+
+    unsafe impl<'a> Allocator for Counting<'a> {
+        fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+            self.0.set(self.0.get() + 1);
+            Global.allocate(layout)
+        }
+
+        unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+            Global.deallocate(ptr, layout)
+        }
+    }
+
+    let count = Cell::new(0);
+    let mut sv = AllocStableVec::with_capacity_in(4, Counting(&count));
+    let a = sv.push(1);
+    sv.push(2);
+    sv.remove(a);
+
+    assert_eq!(count.get(), 1);
+    assert_eq!(sv.iter().collect::<Vec<_>>(), vec![&2]);
+}
+
+#[test]
+fn array_stable_vec_holes_use_capacity() {
+    use ArrayStableVec;
+
+    let mut sv: ArrayStableVec<_, 3> = ArrayStableVec::new();
+    for i in 0..3 {
+        sv.push(i);
+    }
+    sv.remove(1);
+
+    assert!(sv.is_full());
+    assert_eq!(sv.try_push(3), Err(3));
+    assert_eq!(sv.keys().collect::<Vec<_>>(), vec![0, 2]);
+
+    // Without a stack of holes, the hole with the largest index is reused.
+    sv.remove(0);
+    assert_eq!(sv.push_reuse(4), 1);
+    sv.set_push_policy(PushPolicy::PreferHoles);
+    assert_eq!(sv.next_push_index(), 0);
+    assert_eq!(sv.try_push(5), Ok(0));
+    assert!(sv.free_slots.is_empty());
+    assert_eq!(sv.validate(), Ok(()));
+}
+
+#[test]
+fn zero_sized_elements() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    static DROPS: AtomicUsize = AtomicUsize::new(0);
+
+    struct Zst;
+    impl Drop for Zst {
+        fn drop(&mut self) {
+            DROPS.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    let mut sv: StableVec<_> = (0..10).map(|_| Zst).collect();
+    assert_eq!(sv.capacity(), usize::MAX);
+
+    sv.remove(2);
+    sv.remove(5);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+    assert_eq!(sv.keys().collect::<Vec<_>>(), vec![0, 1, 3, 4, 6, 7, 8, 9]);
+
+    sv.make_compact();
+    assert!(sv.is_compact());
+    assert_eq!(sv.next_index(), 8);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 2);
+
+    assert_eq!(sv.into_vec().len(), 8);
+    assert_eq!(DROPS.load(Ordering::SeqCst), 10);
+}
+
+#[test]
+fn shared_stable_vec_copies_only_mutated_chunks() {
+    use std::sync::Arc;
+    use SharedStableVec;
+
+    // Four chunks of 64 slots, the last one partially filled.
+    let original: SharedStableVec<_> = (0..200).collect();
+    let mut clone = original.clone();
+    assert!(clone.ptr_eq(&original));
+
+    clone[70] = 1000;
+    clone.remove(150);
+    clone.push(200);
+
+    assert!(original.iter().cloned().eq(0..200));
+    assert_eq!(original.next_index(), 200);
+    assert_eq!(clone[70], 1000);
+    assert!(!clone.has_element_at(150));
+    assert!(!clone.ptr_eq(&original));
+
+    let (a, b) = (original.chunks(), clone.chunks());
+    assert!(Arc::ptr_eq(&a[0], &b[0]));
+    assert!(!Arc::ptr_eq(&a[1], &b[1]));
+    assert!(!Arc::ptr_eq(&a[2], &b[2]));
+    assert!(!Arc::ptr_eq(&a[3], &b[3]));
+    assert_eq!(Arc::strong_count(&a[0]), 2);
+    assert_eq!(Arc::strong_count(&a[1]), 1);
+}
+
+#[test]
+fn typed_stable_vec_with_u32_keys() {
+    use TypedStableVec;
+
+    let mut sv: TypedStableVec<_, u32> = vec!['a', 'b', 'c'].into_iter().collect();
+    sv.remove(1);
+    assert_eq!(sv.push_reuse('x'), 1);
+    assert_eq!(sv.push('d'), 3);
+    assert_eq!(sv.keys().collect::<Vec<u32>>(), vec![0, 1, 2, 3]);
+    assert_eq!(sv[1], 'x');
+}
+
+#[test]
+fn typed_stable_vec_forwards_index_methods() {
+    use {AccessError, Key, TypedStableVec};
+
+    #[derive(Clone, Copy, Debug, PartialEq)]
+    struct Small(u8);
+
+    impl Key for Small {
+        const MAX_INDEX: usize = 3;
+
+        fn from_usize(index: usize) -> Self {
+            assert!(index <= Self::MAX_INDEX);
+            Small(index as u8)
+        }
+
+        fn into_usize(self) -> usize {
+            self.0 as usize
+        }
+    }
+
+    let too_long = StableVec::from(&['a'; 5]);
+    let too_long = TypedStableVec::<_, Small>::from_stable_vec(too_long).err().unwrap();
+    assert_eq!(too_long.next_index(), 5);
+
+    let sv = StableVec::from(&['a', 'b', 'c', 'd']);
+    let mut sv = TypedStableVec::<_, Small>::from_stable_vec(sv).ok().unwrap();
+    assert_eq!(sv.try_remove(Small(1)), Ok('b'));
+    assert_eq!(sv.get_checked(Small(1)), Err(AccessError::Hole));
+    assert!(!sv.has_element_at(Small(1)));
+    assert_eq!(sv.try_insert_at(Small(1), 'x'), Ok(()));
+    assert_eq!(sv.try_replace(Small(1), 'y'), Ok('x'));
+    assert_eq!(sv.pop(), Some('d'));
+    assert_eq!(sv.next_index(), 4);
+
+    sv.remove(Small(0));
+    let mut moves = Vec::new();
+    sv.make_compact_with(|old, new| moves.push((old, new)));
+    assert_eq!(moves, vec![(Small(1), Small(0)), (Small(2), Small(1))]);
+    assert!(sv.is_compact());
+    assert_eq!(sv.next_push_index(), Small(2));
+}
+
+quickcheck! {
+    fn gen_stable_vec_detects_stale_keys(ops: Vec<(bool, u8)>) -> bool {
+        use GenStableVec;
+
+        let mut sv = GenStableVec::new();
+        let mut live = Vec::new();
+        let mut dead = Vec::new();
+        for (insert, n) in ops {
+            if insert || live.is_empty() {
+                live.push(sv.push(n));
+            } else {
+                let key = live.swap_remove(n as usize % live.len());
+                if sv.remove(key).is_none() {
+                    return false;
+                }
+                dead.push(key);
+            }
+        }
+
+        live.iter().all(|&k| sv.contains_key(k))
+            && dead.iter().all(|&k| sv.get(k).is_none())
+            && sv.keys().count() == live.len()
+    }
+}
+
+#[cfg(feature = "std")]
+quickcheck! {
+    fn secondary_maps_agree(len: u8, attached: Vec<u8>, to_remove: Vec<u8>) -> bool {
+        use secondary::{Secondary, SecondaryMap, SparseSecondaryMap};
+
+        // Written against the shared trait, so both maps get the same calls.
+        fn fill<M: Secondary<usize>>(map: &mut M, attached: &[u8], len: usize) {
+            for &i in attached {
+                let i = i as usize % len;
+                map.insert(i, i);
+            }
+        }
+
+        let len = len as usize + 1;
+        let mut sv = StableVec::from(vec![(); len]);
+        let mut dense = SecondaryMap::new();
+        let mut sparse = SparseSecondaryMap::new();
+        fill(&mut dense, &attached, len);
+        fill(&mut sparse, &attached, len);
+        for i in to_remove {
+            sv.remove(i as usize % len);
+        }
+        Secondary::remove_stale(&mut dense, &sv);
+        Secondary::remove_stale(&mut sparse, &sv);
+
+        let mut sparse_keys = sparse.keys().collect::<Vec<_>>();
+        sparse_keys.sort();
+        dense.len() == sparse.len()
+            && dense.keys().collect::<Vec<_>>() == sparse_keys
+            && dense.iter().all(|(k, v)| sv.has_element_at(k) && sparse[k] == *v)
+    }
+}
+
+quickcheck! {
+    fn occupancy_set_operations(a: Vec<Option<()>>, b: Vec<Option<()>>) -> bool {
+        let (a, b) = (from_slots(&a), from_slots(&b));
+        let (oa, ob) = (a.occupancy(), b.occupancy());
+        let upper = a.next_index().max(b.next_index());
+
+        (0..upper).all(|i| {
+            let (x, y) = (a.has_element_at(i), b.has_element_at(i));
+            oa.intersection(&ob).contains(i) == (x && y)
+                && oa.union(&ob).contains(i) == (x || y)
+                && oa.difference(&ob).contains(i) == (x && !y)
+        })
+            && oa.iter().eq(a.keys())
+            && oa.count() == a.num_elements()
+    }
+}
+
+quickcheck! {
+    fn sort_keeps_holes(items: Vec<(u8, u8)>, to_remove: Vec<u8>) -> bool {
+        let mut sv = StableVec::from(items.clone());
+        for i in to_remove {
+            if !items.is_empty() {
+                sv.remove(i as usize % items.len());
+            }
+        }
+        let keys_before = sv.keys().collect::<Vec<_>>();
+        let mut expected = sv.iter().cloned().collect::<Vec<_>>();
+        expected.sort_by_key(|&(a, _)| a);
+
+        let mut unstable = sv.clone();
+        sv.sort_by(|a, b| a.0.cmp(&b.0));
+        unstable.sort_unstable_by(|a, b| a.cmp(b));
+        expected.iter().cloned().eq(sv.iter().cloned())
+            && unstable.iter().zip(unstable.iter().skip(1)).all(|(a, b)| a <= b)
+            && sv.keys().eq(keys_before.iter().cloned())
+            && unstable.keys().eq(keys_before.iter().cloned())
+    }
+}
+
+quickcheck! {
+    fn dedup_matches_vec(items: Vec<u8>, to_remove: Vec<u8>) -> bool {
+        let items = items.into_iter().map(|x| x % 4).collect::<Vec<_>>();
+        let mut sv = StableVec::from(items.clone());
+        for i in to_remove {
+            if !items.is_empty() {
+                sv.remove(i as usize % items.len());
+            }
+        }
+        let mut expected = sv.iter().cloned().collect::<Vec<_>>();
+        expected.dedup();
+
+        let before = sv.num_elements();
+        let removed = sv.dedup();
+        removed == before - expected.len() && sv.iter().cloned().eq(expected)
+    }
+}
+
+quickcheck! {
+    fn binary_search_finds_stable_index(items: Vec<u8>, to_remove: Vec<u8>, target: u8) -> bool {
+        let mut items = items;
+        items.sort();
+        let mut sv = StableVec::from(items.clone());
+        for i in to_remove {
+            if !items.is_empty() {
+                sv.remove(i as usize % items.len());
+            }
+        }
+
+        let first_not_less = sv.keys().find(|&i| sv[i] >= target).unwrap_or(sv.next_index());
+        let found = match sv.binary_search(&target) {
+            Ok(i) => sv[i] == target,
+            Err(i) => i == first_not_less && sv.get(i) != Some(&target),
+        };
+        found && sv.partition_point(|&e| e < target) == first_not_less
+    }
+}
+
+quickcheck! {
+    fn map_preserves_indices(items: Vec<u8>, to_remove: Vec<u8>) -> bool {
+        let mut sv = StableVec::from(items.clone());
+        for i in to_remove {
+            if !items.is_empty() {
+                sv.remove(i as usize % items.len());
+            }
+        }
+
+        let by_ref = sv.map_ref(|i, &e| (i, e));
+        let by_value = sv.clone().map(|i, e| (i, e));
+        by_ref == by_value
+            && by_ref.next_index() == sv.next_index()
+            && by_ref.keys().eq(sv.keys())
+            && sv.keys().all(|i| by_ref[i] == (i, sv[i]))
+    }
+}
+
+quickcheck! {
+    fn zip_by_index_visits_common_indices(a: Vec<bool>, b: Vec<bool>) -> bool {
+        // Each element is its own index.
+        let build = |slots: Vec<bool>| {
+            let slots = slots.into_iter().enumerate().map(|(i, p)| p.then_some(i));
+            from_slots(&slots.collect::<Vec<_>>())
+        };
+        let (mut a, b) = (build(a), build(b));
+        let common = a.occupancy().intersection(&b.occupancy());
+
+        let zipped = a.zip_by_index(&b).all(|(i, &x, &y)| i == x && i == y)
+            && a.zip_by_index(&b).map(|(i, _, _)| i).eq(common.iter());
+        for (_, x, &y) in a.zip_by_index_mut(&b) {
+            *x += y;
+        }
+        zipped && common.iter().all(|i| a[i] == 2 * i)
+    }
+}
+
+quickcheck! {
+    fn merge_with_unions_indices(a: Vec<Option<u8>>, b: Vec<Option<u8>>) -> bool {
+        let build = |slots: &[Option<u8>]| {
+            from_slots(&slots.iter().map(|s| s.map(u16::from)).collect::<Vec<_>>())
+        };
+        let mut merged = build(&a);
+        merged.merge_with(build(&b), |_, x, y| x + y);
+
+        let len = a.len().max(b.len());
+        let expected = (0..len).map(|i| {
+            match (a.get(i).cloned().and_then(|x| x), b.get(i).cloned().and_then(|x| x)) {
+                (Some(x), Some(y)) => Some(x as u16 + y as u16),
+                (x, y) => x.or(y).map(|x| x as u16),
+            }
+        });
+        merged.next_index() == len
+            && expected.enumerate().all(|(i, e)| merged.get(i).cloned() == e)
+            && merged.num_elements() == merged.iter().count()
+    }
+}
+
+quickcheck! {
+    fn view_chunks_visit_captured_elements(items: Vec<Option<u8>>, max: u8) -> bool {
+        let mut sv = from_slots(&items);
+        let max = max as usize % 4 + 1;
+        let mut view = sv.view();
+        let mut visited = Vec::new();
+        while !view.is_finished() {
+            let before = visited.len();
+            visited.extend(view.next_chunk(&sv, max).unwrap().map(|(i, _)| i));
+            if visited.len() == before {
+                return false;
+            }
+            sv.push(0);
+        }
+
+        view.next_chunk(&sv, max).unwrap().next().is_none()
+            && visited == (0..items.len()).filter(|&i| items[i].is_some()).collect::<Vec<_>>()
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic]
+fn versioned_index_is_invalidated_by_sorting() {
+    let mut sv = StableVec::from(&[3, 1, 2]);
+    let i = sv.versioned_index(0);
+    sv.sort();
+    let _ = sv[i];
+}
+
+#[test]
+fn merge_with_records_conflicts_as_modified() {
+    use journal::ChangeKind;
+
+    let mut sv = StableVec::from(&[1, 2]);
+    sv.enable_journal();
+    sv.merge_with(from_slots(&[Some(10), None, Some(30)]), |_, x, y| x + y);
+
+    assert_eq!(sv, vec![11, 2, 30]);
+    assert_eq!(sv.take_changes(), vec![(0, ChangeKind::Modified), (2, ChangeKind::Inserted)]);
+}
+
+#[test]
+fn cursor_mut_moves_both_ways() {
+    let mut sv = StableVec::from(&['a', 'b', 'c', 'd']);
+    sv.remove(1);
+
+    let mut cursor = sv.cursor_mut_at(3);
+    cursor.move_prev();
+    assert_eq!(cursor.index(), Some(2));
+    cursor.move_prev();
+    cursor.move_prev();
+    assert_eq!(cursor.index(), None);
+    cursor.move_prev();
+    assert_eq!(cursor.index(), Some(3));
+    assert_eq!(cursor.remove_current(), Some('d'));
+    assert_eq!(cursor.index(), None);
+    assert_eq!(cursor.replace_current('x'), Err('x'));
+    assert_eq!(cursor.insert('e'), 4);
+    cursor.move_next();
+    assert_eq!(cursor.current(), Some(&mut 'a'));
+
+    assert_eq!(sv, &['a', 'c', 'e'] as &[_]);
+}
+
+quickcheck! {
+    fn compaction_reports_moves(len: u8, to_remove: Vec<u8>, reordering: bool) -> bool {
+        let len = len as usize + 1;
+        let mut sv = StableVec::from((0..len).collect::<Vec<_>>());
+        for i in to_remove {
+            sv.remove(i as usize % len);
+        }
+
+        // Replay the reported moves on a copy of the slots.
+        let mut mirror = (0..len).map(|i| sv.get(i).cloned()).collect::<Vec<_>>();
+        let mut on_move = |from: usize, to: usize| {
+            mirror[to] = mirror[from].take();
+        };
+        if reordering {
+            sv.reordering_make_compact_with(&mut on_move);
+        } else {
+            sv.make_compact_with(&mut on_move);
+        }
+
+        mirror.truncate(sv.next_index());
+        mirror.iter().enumerate().all(|(i, e)| sv.get(i) == e.as_ref())
+    }
+}
+
+quickcheck! {
+    fn journal_replays_changes(len: u8, ops: Vec<(u8, u8)>) -> bool {
+        use journal::ChangeKind;
+
+        let mut sv = StableVec::from(vec![0u8; len as usize]);
+        let mut replica = sv.clone();
+        sv.enable_journal();
+        for (op, n) in ops {
+            let i = n as usize % (sv.next_index() + 1);
+            match op % 5 {
+                0 => { sv.push(n); }
+                1 => { sv.remove(i); }
+                2 => if let Some(e) = sv.get_mut(i) { *e = n; },
+                3 => { sv.push_reuse(n); }
+                _ => sv.make_compact(),
+            }
+        }
+
+        for (i, kind) in sv.take_changes() {
+            match kind {
+                ChangeKind::Removed => { replica.remove(i); }
+                _ => replica.set_slot(i, sv[i]),
+            }
+        }
+        replica.truncate(sv.next_index());
+        replica.next_index() <= sv.next_index()
+            && (0..sv.next_index()).all(|i| replica.get(i) == sv.get(i))
+            && sv.take_changes().is_empty()
+    }
+}
+
+quickcheck! {
+    fn undo_restores_checkpoints(ops: Vec<(u8, u8)>) -> bool {
+        use UndoableStableVec;
+
+        fn slots(sv: &UndoableStableVec<u8>, len: usize) -> Vec<Option<u8>> {
+            (0..len).map(|i| sv.get(i).cloned()).collect()
+        }
+
+        let mut sv = UndoableStableVec::new();
+        let len = ops.len();
+        let mut states = vec![slots(&sv, len)];
+        let mut changed = false;
+        for (op, n) in ops {
+            let i = n as usize % (sv.next_index() + 1);
+            match op % 4 {
+                0 => { sv.push(n); changed = true; }
+                1 => changed |= sv.remove(i),
+                2 => changed |= sv.replace(i, n),
+                _ => {}
+            }
+
+            // Steps without changes are not recorded.
+            if op % 4 == 3 && changed {
+                sv.checkpoint();
+                states.push(slots(&sv, len));
+                changed = false;
+            }
+        }
+        if changed {
+            states.push(slots(&sv, len));
+        }
+
+        let undone = states.iter().rev().skip(1).all(|state| {
+            sv.undo() && slots(&sv, len) == *state
+        }) && !sv.can_undo();
+        let redone = states.iter().skip(1).all(|state| {
+            sv.redo() && slots(&sv, len) == *state
+        }) && !sv.can_redo();
+        undone && redone
+    }
+}
+
+#[test]
+fn undo_replace_overwrites_in_place() {
+    use UndoableStableVec;
+
+    let mut sv = UndoableStableVec::new();
+    let a = sv.push(0);
+    for n in 1..100 {
+        sv.checkpoint();
+        sv.replace(a, n);
+        sv.undo();
+        sv.redo();
+    }
+    assert_eq!(sv[a], 99);
+
+    // Replacing doesn't create holes, so there is nothing to remember.
+    let sv = sv.into_stable_vec();
+    assert!(sv.free_slots.is_empty());
+    assert!(sv.validate().is_ok());
+}
+
+quickcheck! {
+    fn failed_transaction_changes_nothing(
+        len: u8,
+        to_remove: Vec<u8>,
+        ops: Vec<(u8, u8)>,
+        prefer_holes: bool
+    ) -> bool {
+        let len = len as usize + 1;
+        let build = || {
+            let mut sv = StableVec::from((0..len as u8).collect::<Vec<_>>());
+            if prefer_holes {
+                sv.set_push_policy(PushPolicy::PreferHoles);
+            }
+            sv.enable_journal();
+            for &i in &to_remove {
+                sv.remove(i as usize % len);
+            }
+            sv
+        };
+        let mut sv = build();
+        let before = sv.clone();
+        let generation = sv.generation();
+        let next_push_index = sv.next_push_index();
+        #[cfg(feature = "stats")]
+        let metrics = sv.metrics();
+
+        let result = sv.transaction(|txn| {
+            for &(op, n) in &ops {
+                let i = n as usize % (txn.as_stable_vec().next_index() + 1);
+                match op % 3 {
+                    0 => { txn.push(n); }
+                    1 => { txn.remove(i); }
+                    _ => { txn.replace(i, n); }
+                }
+            }
+            Err::<(), _>(())
+        });
+
+        #[cfg(feature = "stats")]
+        {
+            if sv.metrics() != metrics {
+                return false;
+            }
+        }
+        result.is_err()
+            && sv == before
+            && sv.generation() == generation
+            && sv.next_push_index() == next_push_index
+            && sv.take_changes() == build().take_changes()
+    }
+}
+
+quickcheck! {
+    fn operations_keep_invariants(ops: Vec<(u8, u8)>, journal: bool) -> bool {
+        let mut sv = StableVec::new();
+        if journal {
+            sv.enable_journal();
+        }
+        ops.into_iter().all(|(op, n)| {
+            let i = n as usize % (sv.next_index() + 1);
+            match op % 8 {
+                0 => { sv.push(n); }
+                1 => { sv.push_reuse(n); }
+                2 => { sv.remove(i); }
+                3 => { sv.pop(); }
+                4 => sv.retain(|&e| e != n),
+                5 => sv.make_compact(),
+                6 => sv.reordering_make_compact(),
+                _ => { sv.dedup(); }
+            }
+            sv.validate().is_ok()
+        })
+    }
+}
+
+quickcheck! {
+    fn fallible_ops_match_model(ops: Vec<(u8, u8)>) -> bool {
+        use std::mem;
+
+        let mut sv = StableVec::new();
+        let mut model = Vec::<Option<u8>>::new();
+        ops.into_iter().all(|(op, n)| {
+            let i = n as usize % (model.len() + 2);
+            let ok = match op % 4 {
+                0 => {
+                    model.push(Some(n));
+                    sv.push(n) == model.len() - 1
+                }
+                1 => {
+                    let expected = model.get_mut(i).and_then(|slot| slot.take());
+                    sv.try_remove(i).ok() == expected
+                }
+                2 => match model.get_mut(i) {
+                    Some(&mut Some(ref mut e)) => sv.try_replace(i, n) == Ok(mem::replace(e, n)),
+                    _ => sv.try_replace(i, n) == Err(n),
+                },
+                _ => match model.get_mut(i) {
+                    Some(slot @ &mut None) => {
+                        *slot = Some(n);
+                        sv.try_insert_at(i, n) == Ok(())
+                    }
+                    _ => sv.try_insert_at(i, n) == Err(n),
+                },
+            };
+            ok && sv.validate().is_ok()
+                && model.iter().enumerate().all(|(i, e)| sv.get(i) == e.as_ref())
+        })
+    }
+}
+
+quickcheck! {
+    fn raw_parts_roundtrip(items: Vec<Option<String>>) -> bool {
+        use occupancy::Occupancy;
+
+        let sv = from_slots(&items);
+
+        // Rebuild the occupancy from its raw blocks, as FFI code would.
+        let mut parts = sv.into_raw_parts();
+        parts.occupancy = Occupancy::from_blocks(
+            parts.occupancy.as_blocks().to_vec(),
+            parts.occupancy.len(),
+        );
+        let mut sv = unsafe { StableVec::from_raw_parts(parts) };
+
+        let same = items.iter().enumerate().all(|(i, e)| sv.get(i) == e.as_ref());
+        sv.push("new".to_string());
+        same && sv.next_index() == items.len() + 1 && sv.validate().is_ok()
+    }
+}
+
+#[cfg(feature = "rand")]
+quickcheck! {
+    fn choose_picks_existing_elements(items: Vec<Option<u8>>, seed: u64, amount: u8) -> bool {
+        use rand_crate::SeedableRng;
+        use rand_crate::rngs::SmallRng;
+
+        let sv = from_slots(&items);
+        let mut rng = SmallRng::seed_from_u64(seed);
+
+        let single = match sv.choose(&mut rng) {
+            Some((i, e)) => sv.get(i) == Some(e),
+            None => sv.is_empty(),
+        };
+
+        let chosen = sv.choose_multiple(&mut rng, amount as usize);
+        let expected_len = (amount as usize).min(sv.num_elements());
+        single
+            && chosen.len() == expected_len
+            && chosen.windows(2).all(|w| w[0].0 < w[1].0)
+            && chosen.iter().all(|&(i, e)| sv.get(i) == Some(e))
+    }
+}
+
+#[test]
+fn arena_references_survive_growth() {
+    use StableArena;
+
+    let mut arena = StableArena::new();
+    {
+        let refs = (0..1000).map(|i| arena.alloc(i.to_string())).collect::<Vec<_>>();
+        assert!(refs.iter().enumerate().all(|(i, &(index, s))| index == i && *s == i.to_string()));
+    }
+
+    for i in (0..1000).step_by(2) {
+        arena.remove(i);
+    }
+
+    // Allocating while iterating reuses the holes without invalidating the
+    // iterator.
+    for s in arena.iter().take(250) {
+        let (index, new) = arena.alloc(format!("{}!", s));
+        assert!(index < 1000 && index % 2 == 0);
+        assert!(new.ends_with('!'));
+    }
+    assert_eq!(arena.num_elements(), 750);
+    assert_eq!(arena.next_index(), 1000);
+}
+
+quickcheck! {
+    fn linked_iterates_in_insertion_order(ops: Vec<(bool, u8)>) -> bool {
+        use LinkedStableVec;
+
+        let mut sv = LinkedStableVec::with_push_policy(PushPolicy::PreferHoles);
+        let mut order = Vec::new();
+        for (push, n) in ops {
+            if push {
+                let index = sv.push(n);
+                order.push((index, n));
+            } else if !order.is_empty() {
+                let (index, n) = order.remove(n as usize % order.len());
+                if sv.remove(index) != Some(n) {
+                    return false;
+                }
+            }
+        }
+
+        sv.keys().eq(order.iter().map(|&(i, _)| i))
+            && sv.iter().eq(order.iter().map(|(_, n)| n))
+            && sv.iter_mut().len() == order.len()
+            && sv.first_index() == order.first().map(|&(i, _)| i)
+            && sv.last_index() == order.last().map(|&(i, _)| i)
+    }
+}
+
+quickcheck! {
+    fn diff_reports_differing_indices(a: Vec<Option<u8>>, b: Vec<Option<u8>>) -> bool {
+        use diff::Difference;
+
+        let (old, new) = (from_slots(&a), from_slots(&b));
+
+        let len = a.len().max(b.len());
+        let expected = (0..len).filter(|&i| old.get(i) != new.get(i));
+        old.diff(&new).map(|(i, _)| i).eq(expected)
+            && old.diff(&new).all(|(i, d)| match d {
+                Difference::Added(x) => old.get(i).is_none() && new.get(i) == Some(x),
+                Difference::Removed(x) => old.get(i) == Some(x) && new.get(i).is_none(),
+                Difference::Changed { old: x, new: y } => old[i] == *x && new[i] == *y,
+            })
+            && old.diff(&old).next().is_none()
+    }
+}
+
+quickcheck! {
+    fn retain_in_range_only_touches_range(slots: Vec<Option<u8>>, start: u8, len: u8) -> bool {
+        let mut sv = from_slots(&slots);
+        let start = start as usize % (slots.len() + 2);
+        let end = start + len as usize % (slots.len() + 2);
+
+        let mut expected = sv.clone();
+        for i in start..end.min(sv.next_index()) {
+            if expected.get(i).is_some_and(|&x| x % 3 == 0) {
+                expected.remove(i);
+            }
+        }
+
+        let mut visited = Vec::new();
+        sv.retain_in_range(start..end, |x| {
+            visited.push(*x);
+            *x % 3 != 0
+        });
+        let in_range = (start..end).filter_map(|i| slots.get(i).cloned().and_then(|s| s));
+
+        sv == expected
+            && (0..sv.next_index()).all(|i| sv.get(i) == expected.get(i))
+            && visited.into_iter().eq(in_range)
+    }
+}
+
+quickcheck! {
+    fn clone_subset_keeps_selected_elements(slots: Vec<Option<u8>>, indices: Vec<u8>) -> bool {
+        let sv = from_slots(&slots);
+        let indices = indices.into_iter().map(|i| i as usize).collect::<Vec<_>>();
+
+        let mut expected = indices
+            .iter()
+            .cloned()
+            .filter(|&i| sv.has_element_at(i))
+            .collect::<Vec<_>>();
+        expected.sort();
+        expected.dedup();
+
+        let subset = sv.clone_subset(indices.iter().cloned());
+        let (compact, remap) = sv.clone_subset_compact(indices);
+
+        subset.keys().eq(expected.iter().cloned())
+            && subset.keys().all(|i| subset[i] == sv[i])
+            && remap == expected
+            && compact.is_compact()
+            && compact.iter().eq(remap.iter().map(|&i| &sv[i]))
+    }
+}
+
+#[cfg(feature = "std")]
+quickcheck! {
+    fn indexed_finds_values(ops: Vec<(bool, u8)>) -> bool {
+        use IndexedStableVec;
+
+        let mut sv = IndexedStableVec::new();
+        for (insert, n) in ops {
+            if insert {
+                let index = sv.insert(n % 16);
+                if sv[index] != n % 16 {
+                    return false;
+                }
+            } else {
+                sv.remove(n as usize % 16);
+            }
+        }
+
+        let lookup = sv.as_stable_vec().build_value_index();
+        lookup.len() == sv.num_elements()
+            && (0..16).all(|n| {
+                let expected = sv.keys().find(|&i| sv[i] == n);
+                sv.index_of(&n) == expected && lookup.get(&n).cloned() == expected
+            })
+    }
+}
+
+quickcheck! {
+    fn dense_mirror_stays_in_sync(ops: Vec<(u8, u8)>) -> bool {
+        use DenseMirror;
+
+        // A capturing closure, which isn't coercible to a `fn` pointer.
+        let factor = 2;
+        let mut sv = DenseMirror::new(move |&x: &u8| x as u16 * factor);
+        for (op, n) in ops {
+            match op % 3 {
+                0 => { sv.push(n); }
+                1 => { sv.remove(n as usize % (sv.next_index() + 1)); }
+                _ => { sv.update(n as usize % (sv.next_index() + 1), |x| *x = x.wrapping_add(1)); }
+            }
+        }
+
+        let elements = sv.as_stable_vec();
+        sv.num_elements() == elements.num_elements()
+            && sv.stable_indices().len() == sv.dense().len()
+            && elements.keys().all(|i| {
+                let pos = sv.dense_index(i).unwrap();
+                sv.stable_index(pos) == Some(i) && sv.dense()[pos] == sv[i] as u16 * factor
+            })
+    }
+}
@@ -0,0 +1,220 @@
+//! All-or-nothing batches of changes.
+//!
+//! [`StableVec::transaction()`](../struct.StableVecFacade.html#method.transaction)
+//! passes a [`Transaction`](struct.Transaction.html) to a closure which
+//! pushes, removes and replaces elements through it. If the closure returns
+//! `Err` (or panics), all of these changes are rolled back: removed elements
+//! are put back at their original indices and the slots added by the
+//! transaction are removed again. The [journal](../journal/index.html) and
+//! the [operation counters](../stats/index.html) are restored as well.
+//! Rolling back is O(number of changes), so it's much cheaper than cloning
+//! the stable vector up front. Only with
+//! [`PushPolicy::PreferHoles`](../enum.PushPolicy.html#variant.PreferHoles),
+//! the first push of a transaction has to save the list of holes, which is
+//! O(number of holes). The same happens for a removal which prunes outdated
+//! entries from that list.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! let mut sv = StableVec::from(&['a', 'b', 'c']);
+//!
+//! let result = sv.transaction(|txn| {
+//!     txn.remove(0);
+//!     txn.replace(1, 'x');
+//!     txn.push('d');
+//!     Err("invalid batch")
+//! });
+//! assert_eq!(result, Err::<(), _>("invalid batch"));
+//! assert_eq!(sv, &['a', 'b', 'c'] as &[_]);
+//!
+//! let d = sv.transaction(|txn| {
+//!     txn.remove(0);
+//!     Ok::<_, ()>(txn.push('d'))
+//! }).unwrap();
+//! assert_eq!(sv[d], 'd');
+//! assert_eq!(sv.get(0), None);
+//! ```
+
+use alloc::vec::Vec;
+use core::mem;
+
+use {PushPolicy, StableVecFacade};
+use journal::ChangeKind;
+#[cfg(feature = "stats")]
+use stats::Metrics;
+use storage::{BitmapStorage, Storage};
+use undo::Edit;
+
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Calls `f` with a [`Transaction`](transaction/struct.Transaction.html)
+    /// to change this stable vector, and rolls back all changes if `f`
+    /// returns `Err` or panics. See [the `transaction`
+    /// module](transaction/index.html).
+    pub fn transaction<F, R, E>(&mut self, f: F) -> Result<R, E>
+        where F: FnOnce(&mut Transaction<'_, T, S>) -> Result<R, E>,
+    {
+        let mut txn = Transaction {
+            len: self.storage.len(),
+            free_slots_len: self.free_slots.len(),
+            free_slots: None,
+            generation: self.generation,
+            journal: Vec::new(),
+            #[cfg(feature = "stats")]
+            metrics: self.metrics,
+            sv: self,
+            log: Vec::new(),
+            committed: false,
+        };
+
+        let result = f(&mut txn);
+        txn.committed = result.is_ok();
+        result
+    }
+}
+
+/// A handle to change a stable vector inside of a transaction.
+///
+/// Created by
+/// [`StableVec::transaction()`](../struct.StableVecFacade.html#method.transaction).
+/// The changes are rolled back when the transaction is dropped without being
+/// committed. Elements can only be changed through
+/// [`push()`](#method.push), [`remove()`](#method.remove) and
+/// [`replace()`](#method.replace), so that every change can be reverted.
+pub struct Transaction<'a, T: 'a, S: 'a + Storage<T> = BitmapStorage<T>> {
+    sv: &'a mut StableVecFacade<T, S>,
+
+    /// The edits reverting the changes so far, in the order they were made.
+    log: Vec<Edit<T>>,
+
+    /// The bookkeeping of the stable vector before the transaction.
+    len: usize,
+    generation: usize,
+    #[cfg(feature = "stats")]
+    metrics: Metrics,
+
+    /// The length of `free_slots` before the transaction. Removing only
+    /// appends to it, so truncating restores it, unless a push popped
+    /// entries or a removal pruned the list. Before that happens, the whole
+    /// list is saved in `free_slots`.
+    free_slots_len: usize,
+    free_slots: Option<Vec<usize>>,
+
+    /// The journal entries of all touched indices before they were first
+    /// touched, if the journal is enabled.
+    journal: Vec<(usize, Option<ChangeKind>)>,
+
+    committed: bool,
+}
+
+impl<'a, T, S: Storage<T>> Transaction<'a, T, S> {
+    /// Inserts a new element as
+    /// [`StableVec::push()`](../struct.StableVecFacade.html#method.push) does
+    /// and returns its index.
+    pub fn push(&mut self, elem: T) -> usize {
+        if self.sv.push_policy == PushPolicy::PreferHoles && self.free_slots.is_none() {
+            self.free_slots = Some(self.sv.free_slots.clone());
+        }
+        if self.sv.is_journal_enabled() {
+            let index = self.sv.next_push_index();
+            self.save_journal_entry(index);
+        }
+
+        let index = self.sv.push(elem);
+        self.log.push(Edit::Clear(index));
+        index
+    }
+
+    /// Removes the element at the given index and returns `true`, or returns
+    /// `false` if there exists no element at that index. The element is
+    /// dropped when the transaction is committed.
+    pub fn remove(&mut self, index: usize) -> bool {
+        // The removal adds a hole, so this might save the list although it
+        // isn't pruned.
+        if self.free_slots.is_none() && self.sv.free_slots_prune_due() {
+            self.free_slots = Some(self.sv.free_slots.clone());
+        }
+        self.save_journal_entry(index);
+        match self.sv.remove(index) {
+            Some(elem) => {
+                self.log.push(Edit::Restore(index, elem));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the element at the given index and returns `true`, or
+    /// returns `false` and drops `elem` if there exists no element at that
+    /// index. The old element is dropped when the transaction is committed.
+    pub fn replace(&mut self, index: usize, elem: T) -> bool {
+        self.save_journal_entry(index);
+        match self.sv.get_mut(index) {
+            Some(slot) => {
+                let old = mem::replace(slot, elem);
+                self.log.push(Edit::Restore(index, old));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns a reference to the element at the given index, or `None` if
+    /// there exists no element at that index.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.sv.get(index)
+    }
+
+    /// Returns the stable vector with all changes made so far.
+    pub fn as_stable_vec(&self) -> &StableVecFacade<T, S> {
+        self.sv
+    }
+
+    /// Remembers the journal entry of the given index, to restore it when
+    /// rolling back.
+    fn save_journal_entry(&mut self, index: usize) {
+        if let Some(ref journal) = self.sv.journal {
+            self.journal.push((index, journal.get(&index).cloned()));
+        }
+    }
+}
+
+impl<'a, T, S: Storage<T>> Drop for Transaction<'a, T, S> {
+    fn drop(&mut self) {
+        if self.committed {
+            return;
+        }
+
+        // Whatever rolling back pushes onto the list of holes is discarded,
+        // so it must not prune the entries from before the transaction.
+        let mut free_slots = mem::take(&mut self.sv.free_slots);
+        while let Some(edit) = self.log.pop() {
+            edit.apply(self.sv);
+        }
+        self.sv.truncate(self.len);
+        self.sv.free_slots = match self.free_slots.take() {
+            Some(saved) => saved,
+            None => {
+                free_slots.truncate(self.free_slots_len);
+                free_slots
+            }
+        };
+        self.sv.generation = self.generation;
+        #[cfg(feature = "stats")]
+        {
+            self.sv.metrics = self.metrics;
+        }
+
+        // Restore the earliest saved entry of each index last.
+        if let Some(ref mut journal) = self.sv.journal {
+            for (index, entry) in self.journal.drain(..).rev() {
+                match entry {
+                    Some(kind) => { journal.insert(index, kind); }
+                    None => { journal.remove(&index); }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,501 @@
+//! Stable vectors with typed keys.
+//!
+//! Plain `usize` indices of different stable vectors can easily be confused.
+//! A [`TypedStableVec<T, K>`](struct.TypedStableVec.html) returns and accepts
+//! keys of type `K` instead, which makes using a key of a node vector to
+//! index an edge vector a compile time error. Any type implementing
+//! [`Key`](trait.Key.html) can be used as key; the easiest way to declare
+//! such a type is the [`new_index_type!`](../macro.new_index_type.html)
+//! macro.
+//!
+//! # Example
+//!
+//! ```
+//! use stable_vec::{Key, TypedStableVec};
+//!
+//! #[derive(Clone, Copy, Debug, PartialEq)]
+//! struct NodeIdx(u32);
+//!
+//! impl Key for NodeIdx {
+//!     fn from_usize(index: usize) -> Self {
+//!         NodeIdx(index as u32)
+//!     }
+//!
+//!     fn into_usize(self) -> usize {
+//!         self.0 as usize
+//!     }
+//! }
+//!
+//! let mut nodes = TypedStableVec::new();
+//! let a: NodeIdx = nodes.push("a");
+//! let b = nodes.push("b");
+//! nodes.remove(a);
+//!
+//! assert_eq!(nodes[b], "b");
+//! assert_eq!(nodes.keys().collect::<Vec<_>>(), vec![NodeIdx(1)]);
+//! ```
+
+use core::fmt;
+use core::iter::FromIterator;
+use core::marker::PhantomData;
+use core::ops::{Index, IndexMut};
+
+use {AccessError, Iter, IterMut, Keys, PushPolicy, StableVec};
+
+
+/// A type which can be used as the key of a
+/// [`TypedStableVec`](struct.TypedStableVec.html).
+///
+/// Converting an index to a key and back has to return the original index.
+pub trait Key: Copy {
+    /// The largest index which can be converted to a key.
+    const MAX_INDEX: usize = usize::MAX;
+
+    /// Creates a key from an index.
+    ///
+    /// This may panic if the index is too large for the key type.
+    fn from_usize(index: usize) -> Self;
+
+    /// Returns the index of this key.
+    fn into_usize(self) -> usize;
+}
+
+impl Key for usize {
+    fn from_usize(index: usize) -> Self {
+        index
+    }
+
+    fn into_usize(self) -> usize {
+        self
+    }
+}
+
+impl Key for u32 {
+    const MAX_INDEX: usize = u32::MAX as usize;
+
+    /// Panics if `index > u32::MAX`.
+    fn from_usize(index: usize) -> Self {
+        assert!(index <= u32::MAX as usize, "u32 key overflow");
+        index as u32
+    }
+
+    fn into_usize(self) -> usize {
+        self as usize
+    }
+}
+
+/// Declares one or more key types for
+/// [`TypedStableVec`](typed/struct.TypedStableVec.html).
+///
+/// Each declared type is a newtype around the given integer type (`u32` or
+/// `usize`), derives `Clone`, `Copy`, `PartialEq`, `Eq`, `PartialOrd`, `Ord`,
+/// `Hash` and `Debug`, and implements [`Key`](typed/trait.Key.html).
+/// Attributes (including doc comments) are passed through.
+///
+/// # Example
+///
+/// ```
+/// #[macro_use]
+/// extern crate stable_vec;
+///
+/// use stable_vec::TypedStableVec;
+///
+/// new_index_type! {
+///     /// Refers to a node of the graph.
+///     pub struct NodeIdx(u32);
+///     struct EdgeIdx(usize);
+/// }
+///
+/// # fn main() {
+/// let mut nodes = TypedStableVec::new();
+/// let a: NodeIdx = nodes.push("a");
+/// assert_eq!(nodes[a], "a");
+///
+/// let mut edges: TypedStableVec<_, EdgeIdx> = TypedStableVec::new();
+/// let e = edges.push((a, a));
+/// assert_eq!(edges[e], (a, a));
+/// # }
+/// ```
+#[macro_export]
+macro_rules! new_index_type {
+    ($( $(#[$attr:meta])* $vis:vis struct $name:ident($int:ty); )*) => {
+        $(
+            $(#[$attr])*
+            #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug)]
+            $vis struct $name($int);
+
+            impl $crate::Key for $name {
+                const MAX_INDEX: usize = <$int as $crate::Key>::MAX_INDEX;
+
+                fn from_usize(index: usize) -> Self {
+                    $name(<$int as $crate::Key>::from_usize(index))
+                }
+
+                fn into_usize(self) -> usize {
+                    $crate::Key::into_usize(self.0)
+                }
+            }
+        )*
+    };
+}
+
+/// A stable vector whose indices are keys of type `K`.
+///
+/// This is a thin wrapper around a [`StableVec<T>`](../type.StableVec.html)
+/// which converts between `K` and `usize` in each method. See
+/// [the module documentation](index.html) for more information.
+pub struct TypedStableVec<T, K: Key> {
+    inner: StableVec<T>,
+    marker: PhantomData<fn(K) -> K>,
+}
+
+impl<T, K: Key> TypedStableVec<T, K> {
+    /// Constructs a new, empty `TypedStableVec<T, K>`.
+    pub fn new() -> Self {
+        Self::wrap(StableVec::new())
+    }
+
+    /// Constructs a new, empty `TypedStableVec<T, K>` with the specified
+    /// capacity.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self::wrap(StableVec::with_capacity(capacity))
+    }
+
+    /// Constructs a new, empty `TypedStableVec<T, K>` whose
+    /// [`push()`](#method.push) follows the given policy.
+    pub fn with_push_policy(policy: PushPolicy) -> Self {
+        Self::wrap(StableVec::with_push_policy(policy))
+    }
+
+    /// Wraps the given stable vector, using its indices as keys, or returns
+    /// it unchanged if it contains slots whose index is larger than
+    /// [`K::MAX_INDEX`](trait.Key.html#associatedconstant.MAX_INDEX).
+    // Returning the stable vector itself is the point of the `Err` variant.
+    #[allow(clippy::result_large_err)]
+    pub fn from_stable_vec(sv: StableVec<T>) -> Result<Self, StableVec<T>> {
+        if sv.next_index() > 0 && sv.next_index() - 1 > K::MAX_INDEX {
+            Err(sv)
+        } else {
+            Ok(Self::wrap(sv))
+        }
+    }
+
+    /// Wraps the given stable vector without checking its length.
+    fn wrap(sv: StableVec<T>) -> Self {
+        Self {
+            inner: sv,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the underlying stable vector, which uses `usize` indices.
+    pub fn as_stable_vec(&self) -> &StableVec<T> {
+        &self.inner
+    }
+
+    /// Unwraps the underlying stable vector, which uses `usize` indices.
+    pub fn into_stable_vec(self) -> StableVec<T> {
+        self.inner
+    }
+
+    /// Returns the policy which determines where [`push()`](#method.push)
+    /// inserts new elements.
+    pub fn push_policy(&self) -> PushPolicy {
+        self.inner.push_policy()
+    }
+
+    /// Changes the policy which determines where [`push()`](#method.push)
+    /// inserts new elements. See
+    /// [`StableVec::set_push_policy()`](../struct.StableVecFacade.html#method.set_push_policy).
+    pub fn set_push_policy(&mut self, policy: PushPolicy) {
+        self.inner.set_push_policy(policy);
+    }
+
+    /// Inserts a new element and returns its key. See
+    /// [`StableVec::push()`](../struct.StableVecFacade.html#method.push).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `K::from_usize()` panics for the new index. In that case,
+    /// the element is not inserted.
+    pub fn push(&mut self, elem: T) -> K {
+        let key = K::from_usize(self.inner.next_push_index());
+        self.inner.push(elem);
+        key
+    }
+
+    /// Inserts the element into a hole and returns its key. See
+    /// [`StableVec::push_reuse()`](../struct.StableVecFacade.html#method.push_reuse).
+    pub fn push_reuse(&mut self, elem: T) -> K {
+        K::from_usize(self.inner.push_reuse(elem))
+    }
+
+    /// Removes and returns the element with the given key, or `None` if there
+    /// exists no such element.
+    pub fn remove(&mut self, key: K) -> Option<T> {
+        self.inner.remove(key.into_usize())
+    }
+
+    /// Removes and returns the element with the largest key, or `None` if
+    /// the vector is empty. See
+    /// [`StableVec::pop()`](../struct.StableVecFacade.html#method.pop).
+    pub fn pop(&mut self) -> Option<T> {
+        self.inner.pop()
+    }
+
+    /// Removes and returns the element with the given key, or returns why
+    /// there is no such element. See
+    /// [`StableVec::try_remove()`](../struct.StableVecFacade.html#method.try_remove).
+    pub fn try_remove(&mut self, key: K) -> Result<T, AccessError> {
+        self.inner.try_remove(key.into_usize())
+    }
+
+    /// Replaces the element with the given key and returns the old one, or
+    /// returns `Err(elem)` if there exists no such element.
+    pub fn try_replace(&mut self, key: K, elem: T) -> Result<T, T> {
+        self.inner.try_replace(key.into_usize(), elem)
+    }
+
+    /// Inserts the element into the hole with the given key, or returns
+    /// `Err(elem)` if the slot isn't a hole. See
+    /// [`StableVec::try_insert_at()`](../struct.StableVecFacade.html#method.try_insert_at).
+    pub fn try_insert_at(&mut self, key: K, elem: T) -> Result<(), T> {
+        self.inner.try_insert_at(key.into_usize(), elem)
+    }
+
+    /// Returns a reference to the element with the given key, or `None` if
+    /// there exists no such element.
+    pub fn get(&self, key: K) -> Option<&T> {
+        self.inner.get(key.into_usize())
+    }
+
+    /// Returns a mutable reference to the element with the given key, or
+    /// `None` if there exists no such element.
+    pub fn get_mut(&mut self, key: K) -> Option<&mut T> {
+        self.inner.get_mut(key.into_usize())
+    }
+
+    /// Returns a reference to the element with the given key, or returns
+    /// why there is no such element.
+    pub fn get_checked(&self, key: K) -> Result<&T, AccessError> {
+        self.inner.get_checked(key.into_usize())
+    }
+
+    /// Returns a mutable reference to the element with the given key, or
+    /// returns why there is no such element.
+    pub fn get_mut_checked(&mut self, key: K) -> Result<&mut T, AccessError> {
+        self.inner.get_mut_checked(key.into_usize())
+    }
+
+    /// Returns `true` if there exists an element with the given key.
+    pub fn has_element_at(&self, key: K) -> bool {
+        self.inner.has_element_at(key.into_usize())
+    }
+
+    /// Returns `true` if there exists an element equal to `item`.
+    pub fn contains<U>(&self, item: &U) -> bool
+        where U: PartialEq<T>
+    {
+        self.inner.contains(item)
+    }
+
+    /// Returns the number of existing elements.
+    pub fn num_elements(&self) -> usize {
+        self.inner.num_elements()
+    }
+
+    /// Returns `true` if there are no existing elements.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns the number of slots (elements and holes), i.e. the index of
+    /// the next slot. See
+    /// [`StableVec::next_index()`](../struct.StableVecFacade.html#method.next_index).
+    pub fn next_index(&self) -> usize {
+        self.inner.next_index()
+    }
+
+    /// Returns the key which would be returned by calling
+    /// [`push()`](#method.push).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `K::from_usize()` panics for that index.
+    pub fn next_push_index(&self) -> K {
+        K::from_usize(self.inner.next_push_index())
+    }
+
+    /// Returns a counter which changes every time an element is removed or
+    /// moved to another key. See
+    /// [`StableVec::generation()`](../struct.StableVecFacade.html#method.generation).
+    pub fn generation(&self) -> usize {
+        self.inner.generation()
+    }
+
+    /// Returns the number of elements the vector can hold without
+    /// reallocating.
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity()
+    }
+
+    /// Reserves capacity for at least `additional` more elements to be
+    /// inserted.
+    pub fn reserve(&mut self, additional: usize) {
+        self.inner.reserve(additional);
+    }
+
+    /// Shrinks the capacity as much as possible. Doesn't invalidate keys.
+    pub fn shrink_to_fit(&mut self) {
+        self.inner.shrink_to_fit();
+    }
+
+    /// Returns `true` if there are no holes.
+    pub fn is_compact(&self) -> bool {
+        self.inner.is_compact()
+    }
+
+    /// Removes all holes while keeping the order of the elements.
+    /// **Invalidates keys!** See
+    /// [`StableVec::make_compact()`](../struct.StableVecFacade.html#method.make_compact).
+    pub fn make_compact(&mut self) {
+        self.inner.make_compact();
+    }
+
+    /// Like [`make_compact()`](#method.make_compact), but calls
+    /// `on_move(old, new)` for every element which gets a new key.
+    pub fn make_compact_with<F>(&mut self, mut on_move: F)
+        where F: FnMut(K, K)
+    {
+        self.inner.make_compact_with(|old, new| on_move(K::from_usize(old), K::from_usize(new)));
+    }
+
+    /// Removes all holes by moving the last elements into them, like
+    /// `StableVec::reordering_make_compact()`. **Invalidates keys!**
+    pub fn reordering_make_compact(&mut self) {
+        self.inner.reordering_make_compact();
+    }
+
+    /// Like [`reordering_make_compact()`](#method.reordering_make_compact),
+    /// but calls `on_move(old, new)` for every element which gets a new key.
+    pub fn reordering_make_compact_with<F>(&mut self, mut on_move: F)
+        where F: FnMut(K, K)
+    {
+        self.inner.reordering_make_compact_with(|old, new| {
+            on_move(K::from_usize(old), K::from_usize(new))
+        });
+    }
+
+    /// Returns an iterator over immutable references to the existing
+    /// elements.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.inner.iter()
+    }
+
+    /// Returns an iterator over mutable references to the existing elements.
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        self.inner.iter_mut()
+    }
+
+    /// Returns an iterator over the keys of all existing elements.
+    pub fn keys(&self) -> TypedKeys<'_, T, K> {
+        TypedKeys {
+            inner: self.inner.keys(),
+            marker: PhantomData,
+        }
+    }
+
+    /// Removes all elements for which the predicate returns `false`. See
+    /// [`StableVec::retain()`](../struct.StableVecFacade.html#method.retain).
+    pub fn retain<P>(&mut self, predicate: P)
+        where P: FnMut(&T) -> bool
+    {
+        self.inner.retain(predicate);
+    }
+
+    /// Like [`retain()`](#method.retain), but passes each removed element
+    /// together with its key to `on_remove`.
+    pub fn retain_with<P, F>(&mut self, predicate: P, mut on_remove: F)
+        where P: FnMut(&T) -> bool,
+              F: FnMut(K, T),
+    {
+        self.inner.retain_with(predicate, |i, elem| on_remove(K::from_usize(i), elem));
+    }
+}
+
+impl<T, K: Key> Index<K> for TypedStableVec<T, K> {
+    type Output = T;
+
+    fn index(&self, key: K) -> &T {
+        &self.inner[key.into_usize()]
+    }
+}
+
+impl<T, K: Key> IndexMut<K> for TypedStableVec<T, K> {
+    fn index_mut(&mut self, key: K) -> &mut T {
+        &mut self.inner[key.into_usize()]
+    }
+}
+
+impl<T, K: Key> Default for TypedStableVec<T, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: Clone, K: Key> Clone for TypedStableVec<T, K> {
+    fn clone(&self) -> Self {
+        Self::wrap(self.inner.clone())
+    }
+}
+
+impl<T: PartialEq, K: Key> PartialEq for TypedStableVec<T, K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<T: Eq, K: Key> Eq for TypedStableVec<T, K> {}
+
+impl<T, K: Key> FromIterator<T> for TypedStableVec<T, K> {
+    fn from_iter<I>(iter: I) -> Self
+        where I: IntoIterator<Item = T>
+    {
+        let mut out = Self::new();
+        out.extend(iter);
+        out
+    }
+}
+
+impl<T, K: Key> Extend<T> for TypedStableVec<T, K> {
+    fn extend<I>(&mut self, iter: I)
+        where I: IntoIterator<Item = T>
+    {
+        for elem in iter {
+            self.push(elem);
+        }
+    }
+}
+
+impl<T: fmt::Debug, K: Key> fmt::Debug for TypedStableVec<T, K> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "TypedStableVec ")?;
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
+
+/// Iterator over the keys of all existing elements of a `TypedStableVec`.
+///
+/// Use the method [`TypedStableVec::keys()`](struct.TypedStableVec.html#method.keys)
+/// to obtain an iterator of this kind.
+pub struct TypedKeys<'a, T: 'a, K> {
+    inner: Keys<'a, T>,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<'a, T, K: Key> Iterator for TypedKeys<'a, T, K> {
+    type Item = K;
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(K::from_usize)
+    }
+}
@@ -0,0 +1,288 @@
+//! A stable vector which can undo and redo its changes.
+//!
+//! See [`UndoableStableVec`](struct.UndoableStableVec.html) for more
+//! information.
+
+use alloc::vec::Vec;
+use core::fmt;
+use core::mem;
+use core::ops::Index;
+
+use {Iter, Keys, StableVec, StableVecFacade};
+use storage::Storage;
+
+
+/// One change of a slot which is needed to revert a change.
+pub(crate) enum Edit<T> {
+    /// The slot has to become a hole.
+    Clear(usize),
+
+    /// The slot has to contain the given element.
+    Restore(usize, T),
+}
+
+impl<T> Edit<T> {
+    /// Applies this edit to the given stable vector and returns the edit
+    /// reverting it.
+    pub(crate) fn apply<S: Storage<T>>(self, sv: &mut StableVecFacade<T, S>) -> Edit<T> {
+        match self {
+            Edit::Clear(index) => {
+                let elem = sv.remove(index).expect("edit log out of sync");
+                Edit::Restore(index, elem)
+            }
+            Edit::Restore(index, elem) => match sv.get_mut(index) {
+                Some(slot) => Edit::Restore(index, mem::replace(slot, elem)),
+                None => {
+                    sv.set_slot(index, elem);
+                    Edit::Clear(index)
+                }
+            },
+        }
+    }
+}
+
+/// A stable vector which logs its changes, so that they can be undone and
+/// redone.
+///
+/// Changes are grouped into steps: [`checkpoint()`](#method.checkpoint)
+/// ends the current step, [`undo()`](#method.undo) reverts all changes of
+/// the last step and [`redo()`](#method.redo) applies them again. Undoing
+/// a removal puts the element back at its original index, so all indices
+/// stay valid. Any new change discards the steps which could be redone.
+///
+/// To be able to revert every change, elements can only be modified
+/// through [`push()`](#method.push), [`remove()`](#method.remove) and
+/// [`replace()`](#method.replace). Removed and replaced elements are kept in
+/// the log until it is cleared with
+/// [`clear_history()`](#method.clear_history). After undoing a `push()`, the
+/// slot of the element is a hole, i.e. it is not reused.
+///
+/// # Example
+///
+/// ```
+/// use stable_vec::UndoableStableVec;
+///
+/// let mut sv = UndoableStableVec::new();
+/// let a = sv.push('a');
+/// let b = sv.push('b');
+/// sv.checkpoint();
+///
+/// sv.remove(a);
+/// sv.replace(b, 'x');
+/// assert_eq!(sv.iter().collect::<Vec<_>>(), vec![&'x']);
+///
+/// sv.undo();
+/// assert_eq!(sv[a], 'a');
+/// assert_eq!(sv[b], 'b');
+///
+/// sv.redo();
+/// assert_eq!(sv.get(a), None);
+/// assert_eq!(sv[b], 'x');
+/// ```
+pub struct UndoableStableVec<T> {
+    inner: StableVec<T>,
+
+    /// The steps which can be undone, the last one first. Each step contains
+    /// the edits to revert it in the order they have to be applied.
+    undo: Vec<Vec<Edit<T>>>,
+
+    /// The steps which can be redone, in the same format as `undo`.
+    redo: Vec<Vec<Edit<T>>>,
+
+    /// Whether the next change starts a new step.
+    step_finished: bool,
+}
+
+impl<T> UndoableStableVec<T> {
+    /// Constructs a new, empty `UndoableStableVec<T>` without history.
+    pub fn new() -> Self {
+        Self::from_stable_vec(StableVec::new())
+    }
+
+    /// Wraps the given stable vector. The history starts empty.
+    pub fn from_stable_vec(sv: StableVec<T>) -> Self {
+        Self {
+            inner: sv,
+            undo: Vec::new(),
+            redo: Vec::new(),
+            step_finished: true,
+        }
+    }
+
+    /// Returns the underlying stable vector.
+    pub fn as_stable_vec(&self) -> &StableVec<T> {
+        &self.inner
+    }
+
+    /// Unwraps the underlying stable vector, discarding the history.
+    pub fn into_stable_vec(self) -> StableVec<T> {
+        self.inner
+    }
+
+    /// Appends a new element and returns its index.
+    pub fn push(&mut self, elem: T) -> usize {
+        let index = self.inner.next_index();
+        self.inner.set_slot(index, elem);
+        self.log(Edit::Clear(index));
+        index
+    }
+
+    /// Removes the element at the given index and returns `true`, or returns
+    /// `false` if there exists no element at that index. The element is kept
+    /// in the log.
+    pub fn remove(&mut self, index: usize) -> bool {
+        match self.inner.remove(index) {
+            Some(elem) => {
+                self.log(Edit::Restore(index, elem));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Replaces the element at the given index and returns `true`, or
+    /// returns `false` and drops `elem` if there exists no element at that
+    /// index. The old element is kept in the log.
+    pub fn replace(&mut self, index: usize, elem: T) -> bool {
+        match self.inner.get_mut(index) {
+            Some(slot) => {
+                let old = mem::replace(slot, elem);
+                self.log(Edit::Restore(index, old));
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Ends the current step: the next change starts a new one. Does nothing
+    /// if there were no changes since the last checkpoint.
+    pub fn checkpoint(&mut self) {
+        self.step_finished = true;
+    }
+
+    /// Reverts all changes of the last step and returns `true`, or returns
+    /// `false` if there is nothing to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop() {
+            Some(step) => {
+                let inverse = self.apply(step);
+                self.redo.push(inverse);
+                self.step_finished = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the last undone step again and returns `true`, or returns
+    /// `false` if there is nothing to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(step) => {
+                let inverse = self.apply(step);
+                self.undo.push(inverse);
+                self.step_finished = true;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Returns `true` if there is a step which can be undone.
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    /// Returns `true` if there is a step which can be redone.
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    /// Discards all steps which could be undone or redone, dropping all
+    /// elements which are only kept in the log.
+    pub fn clear_history(&mut self) {
+        self.undo.clear();
+        self.redo.clear();
+        self.step_finished = true;
+    }
+
+    /// Returns a reference to the element at the given index, or `None` if
+    /// there exists no element at that index.
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.inner.get(index)
+    }
+
+    /// Returns `true` if there exists an element at the given index.
+    pub fn has_element_at(&self, index: usize) -> bool {
+        self.inner.has_element_at(index)
+    }
+
+    /// Returns the number of existing elements.
+    pub fn num_elements(&self) -> usize {
+        self.inner.num_elements()
+    }
+
+    /// Returns `true` if there are no existing elements.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Returns the number of slots (elements and holes). See
+    /// [`StableVec::next_index()`](../struct.StableVecFacade.html#method.next_index).
+    pub fn next_index(&self) -> usize {
+        self.inner.next_index()
+    }
+
+    /// Returns an iterator over immutable references to the existing
+    /// elements.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.inner.iter()
+    }
+
+    /// Returns an iterator over the indices of all existing elements.
+    pub fn keys(&self) -> Keys<'_, T> {
+        self.inner.keys()
+    }
+
+    /// Adds the edit reverting a change to the current step and discards the
+    /// steps which could be redone.
+    fn log(&mut self, edit: Edit<T>) {
+        self.redo.clear();
+        if self.step_finished {
+            self.undo.push(Vec::new());
+            self.step_finished = false;
+        }
+        self.undo.last_mut().unwrap().push(edit);
+    }
+
+    /// Applies the edits of a step in reverse order and returns the edits
+    /// reverting them.
+    fn apply(&mut self, step: Vec<Edit<T>>) -> Vec<Edit<T>> {
+        let mut inverse = Vec::with_capacity(step.len());
+        for edit in step.into_iter().rev() {
+            inverse.push(edit.apply(&mut self.inner));
+        }
+        inverse
+    }
+}
+
+impl<T> Index<usize> for UndoableStableVec<T> {
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
+        &self.inner[index]
+    }
+}
+
+impl<T> Default for UndoableStableVec<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: fmt::Debug> fmt::Debug for UndoableStableVec<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "UndoableStableVec ")?;
+        f.debug_list().entries(self.iter()).finish()
+    }
+}
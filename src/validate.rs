@@ -0,0 +1,145 @@
+//! Checking the internal invariants of a stable vector.
+//!
+//! A stable vector keeps some bookkeeping next to its storage: the number of
+//! elements, the list of free slots and (if enabled) the journal. Bugs in
+//! unsafe code built on top of this crate can make them disagree with the
+//! storage. [`StableVec::validate()`](../struct.StableVecFacade.html#method.validate)
+//! checks all of them and reports the first inconsistency it finds, which is
+//! useful when fuzzing and for bug reports.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! let mut sv = StableVec::from(&['a', 'b', 'c']);
+//! sv.remove(1);
+//! sv.push('d');
+//!
+//! assert_eq!(sv.validate(), Ok(()));
+//! sv.assert_invariants();
+//! ```
+
+use core::fmt;
+
+use StableVecFacade;
+use journal::ChangeKind;
+use storage::Storage;
+
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Checks the internal invariants of this stable vector and returns the
+    /// first violation found. This is O(n). See [the `validate`
+    /// module](validate/index.html).
+    ///
+    /// If this ever returns `Err` and you didn't use any `unsafe` code on
+    /// this stable vector, please report a bug.
+    pub fn validate(&self) -> Result<(), InvariantError> {
+        let len = self.storage.len();
+        let capacity = self.storage.capacity();
+        if len > capacity {
+            return Err(InvariantError::LenExceedsCapacity { len, capacity });
+        }
+
+        let occupied = (0..len).filter(|&i| self.storage.has_element_at(i)).count();
+        if occupied != self.used_count {
+            return Err(InvariantError::CountMismatch {
+                num_elements: self.used_count,
+                occupied,
+            });
+        }
+
+        // The free list may contain stale entries (which are skipped when
+        // pushing), but it must contain every hole. Storages without a hole
+        // stack keep the list empty.
+        if S::HOLE_STACK {
+            let mut listed = vec![false; len];
+            for &i in &self.free_slots {
+                if i < len {
+                    listed[i] = true;
+                }
+            }
+            let missing = (0..len).find(|&i| !self.storage.has_element_at(i) && !listed[i]);
+            if let Some(index) = missing {
+                return Err(InvariantError::HoleNotInFreeList { index });
+            }
+        }
+
+        if let Some(ref journal) = self.journal {
+            for (&index, &kind) in journal.iter() {
+                let occupied = index < len && self.storage.has_element_at(index);
+                if occupied != (kind != ChangeKind::Removed) {
+                    return Err(InvariantError::JournalMismatch { index, kind });
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Like [`validate()`](#method.validate), but panics with a description
+    /// of the violated invariant.
+    pub fn assert_invariants(&self) {
+        if let Err(e) = self.validate() {
+            panic!("stable vector invariant violated: {}", e);
+        }
+    }
+}
+
+/// A violated invariant of a stable vector.
+///
+/// Returned by [`StableVec::validate()`](../struct.StableVecFacade.html#method.validate).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum InvariantError {
+    /// The storage has more slots than it has capacity for.
+    LenExceedsCapacity {
+        len: usize,
+        capacity: usize,
+    },
+
+    /// The stored number of elements differs from the number of occupied
+    /// slots.
+    CountMismatch {
+        num_elements: usize,
+        occupied: usize,
+    },
+
+    /// The hole at `index` is missing from the list of free slots, so it
+    /// would never be reused.
+    HoleNotInFreeList {
+        index: usize,
+    },
+
+    /// The journal has recorded `kind` for the slot at `index`, which
+    /// contradicts whether the slot is occupied.
+    JournalMismatch {
+        index: usize,
+        kind: ChangeKind,
+    },
+}
+
+impl fmt::Display for InvariantError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            InvariantError::LenExceedsCapacity { len, capacity } => {
+                write!(f, "{} slots exceed the capacity of {}", len, capacity)
+            }
+            InvariantError::CountMismatch { num_elements, occupied } => {
+                write!(
+                    f,
+                    "{} elements are counted, but {} slots are occupied",
+                    num_elements,
+                    occupied,
+                )
+            }
+            InvariantError::HoleNotInFreeList { index } => {
+                write!(f, "the hole at index {} is not in the list of free slots", index)
+            }
+            InvariantError::JournalMismatch { index, kind } => {
+                write!(f, "the journal records {:?} for the slot at index {}", kind, index)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for InvariantError {}
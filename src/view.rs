@@ -0,0 +1,174 @@
+//! Consistent, incremental read passes over a stable vector which is mutated
+//! in between.
+//!
+//! A [`View`](struct.View.html) captures the occupancy of a stable vector at
+//! one point in time. It doesn't borrow the stable vector, so you can process
+//! the captured elements in chunks and push new elements in between. Each
+//! chunk yields exactly the elements that existed when the view was created;
+//! elements pushed later are not visited. If an element was removed or moved
+//! in the meantime, the view is stale and the next chunk returns an error.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! let mut sv = StableVec::from(&[1, 2, 3, 4, 5]);
+//! sv.remove(1);
+//!
+//! let mut view = sv.view();
+//! let mut sum = 0;
+//! while !view.is_finished() {
+//!     for (_, &e) in view.next_chunk(&sv, 2).unwrap() {
+//!         sum += e;
+//!     }
+//!
+//!     // The writer can push between chunks without disturbing the view.
+//!     sv.push(100);
+//! }
+//! assert_eq!(sum, 1 + 3 + 4 + 5);
+//!
+//! // Removing elements makes the view stale.
+//! let mut view = sv.view();
+//! sv.remove(0);
+//! assert!(view.is_stale(&sv));
+//! assert!(view.next_chunk(&sv, 2).is_err());
+//! ```
+
+use bit_vec::BitVec;
+
+use core::fmt;
+
+use StableVec;
+use storage::find_bit;
+
+
+impl<T> StableVec<T> {
+    /// Captures the current occupancy of this stable vector in a
+    /// [`View`](view/struct.View.html), which can be used to iterate over
+    /// the current elements in multiple chunks.
+    ///
+    /// This copies the internal bitmap (one bit per slot), but no elements.
+    pub fn view(&self) -> View {
+        let deleted = self.storage.deleted.clone();
+        View {
+            next: find_bit(&deleted, deleted.len(), 0, !0),
+            deleted,
+            generation: self.generation,
+            pos: 0,
+        }
+    }
+}
+
+/// A snapshot of the occupancy of a stable vector, used to iterate over its
+/// elements in chunks.
+///
+/// Created by [`StableVec::view()`](../struct.StableVecFacade.html#method.view).
+/// See [the module documentation](index.html) for more information.
+#[derive(Clone, Debug)]
+pub struct View {
+    /// The `deleted` bitmap at the time of creation.
+    deleted: BitVec,
+
+    /// The generation at the time of creation.
+    generation: usize,
+
+    /// The index from which the next chunk continues.
+    pos: usize,
+
+    /// The first index `>= pos` which contained an element, if any.
+    next: Option<usize>,
+}
+
+impl View {
+    /// Returns the `next_index()` of the stable vector when this view was
+    /// created. Elements at this index or above are never visited.
+    pub fn len(&self) -> usize {
+        self.deleted.len()
+    }
+
+    /// Returns `true` if the stable vector didn't contain any slots when this
+    /// view was created.
+    pub fn is_empty(&self) -> bool {
+        self.deleted.is_empty()
+    }
+
+    /// Returns the index from which the next chunk will continue.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns `true` if all captured elements have been visited.
+    pub fn is_finished(&self) -> bool {
+        self.next.is_none()
+    }
+
+    /// Returns `true` if an element was removed from or moved within `sv`
+    /// since this view was created.
+    ///
+    /// The result is only meaningful if `sv` is the stable vector this view
+    /// was created from.
+    pub fn is_stale<T>(&self, sv: &StableVec<T>) -> bool {
+        sv.generation() != self.generation
+    }
+
+    /// Returns an iterator over the indices and references of the next (at
+    /// most) `max` captured elements of `sv`.
+    ///
+    /// The iterator advances this view: the following call continues where
+    /// this iterator stopped. Returns an error if the view
+    /// [is stale](#method.is_stale).
+    pub fn next_chunk<'a, T>(&'a mut self, sv: &'a StableVec<T>, max: usize)
+        -> Result<Chunk<'a, T>, StaleView>
+    {
+        if self.is_stale(sv) {
+            return Err(StaleView);
+        }
+
+        Ok(Chunk {
+            view: self,
+            sv,
+            remaining: max,
+        })
+    }
+}
+
+/// Iterator over one chunk of a [`View`](struct.View.html). Created by
+/// [`View::next_chunk()`](struct.View.html#method.next_chunk).
+pub struct Chunk<'a, T: 'a> {
+    view: &'a mut View,
+    sv: &'a StableVec<T>,
+    remaining: usize,
+}
+
+impl<'a, T> Iterator for Chunk<'a, T> {
+    type Item = (usize, &'a T);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+
+        let view = &mut *self.view;
+        let index = view.next?;
+        view.pos = index + 1;
+        view.next = find_bit(&view.deleted, view.deleted.len(), view.pos, !0);
+        self.remaining -= 1;
+
+        // The generation is unchanged, so the element is still there.
+        Some((index, &self.sv[index]))
+    }
+}
+
+/// The error returned by [`View::next_chunk()`](struct.View.html#method.next_chunk)
+/// if an element was removed or moved since the view was created.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StaleView;
+
+impl fmt::Display for StaleView {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "the stable vector was modified since the view was created")
+    }
+}
+
+#[cfg(feature = "std")]
+impl ::std::error::Error for StaleView {}
@@ -0,0 +1,134 @@
+//! Iterating over two stable vectors with the same indices at once.
+//!
+//! If two stable vectors share their indices (e.g. a stable vector of
+//! entities and a stable vector with additional data per entity),
+//! [`StableVec::zip_by_index()`](../struct.StableVecFacade.html#method.zip_by_index)
+//! yields the elements of both at each index which is occupied in both.
+//!
+//! # Example
+//!
+//! ```
+//! # use stable_vec::StableVec;
+//! let mut names = StableVec::from(&["Alice", "Bob", "Carol"]);
+//! let mut scores = StableVec::from(&[10, 20, 30]);
+//! names.remove(0);
+//! scores.remove(2);
+//!
+//! let joined = names.zip_by_index(&scores).collect::<Vec<_>>();
+//! assert_eq!(joined, vec![(1, &"Bob", &20)]);
+//!
+//! for (_, score, name) in scores.zip_by_index_mut(&names) {
+//!     *score += name.len();
+//! }
+//! assert_eq!(scores[1], 23);
+//! ```
+
+use StableVecFacade;
+use journal::ChangeKind;
+use storage::Storage;
+
+
+impl<T, S: Storage<T>> StableVecFacade<T, S> {
+    /// Returns an iterator over `(index, &self[index], &other[index])` for
+    /// all indices at which both stable vectors have an element, in
+    /// ascending order.
+    ///
+    /// See [the module documentation](zip/index.html) for an example.
+    pub fn zip_by_index<'a, U, S2>(
+        &'a self,
+        other: &'a StableVecFacade<U, S2>,
+    ) -> ZipByIndex<'a, T, U, S, S2>
+        where S2: Storage<U>,
+    {
+        ZipByIndex {
+            a: self,
+            b: other,
+            pos: 0,
+        }
+    }
+
+    /// Returns an iterator over `(index, &mut self[index], &other[index])`
+    /// for all indices at which both stable vectors have an element, in
+    /// ascending order.
+    ///
+    /// To mutate the elements of `other` instead, call this method on
+    /// `other`.
+    pub fn zip_by_index_mut<'a, U, S2>(
+        &'a mut self,
+        other: &'a StableVecFacade<U, S2>,
+    ) -> ZipByIndexMut<'a, T, U, S, S2>
+        where S2: Storage<U>,
+    {
+        ZipByIndexMut {
+            a: self,
+            b: other,
+            pos: 0,
+        }
+    }
+}
+
+/// Returns the next index `>= *pos` at which both storages have an element
+/// and advances `pos` past it.
+fn next_common_index<A, B, SA, SB>(pos: &mut usize, a: &SA, b: &SB) -> Option<usize>
+    where SA: Storage<A>,
+          SB: Storage<B>,
+{
+    while let Some(i) = a.next_occupied(*pos) {
+        *pos = i + 1;
+        if b.has_element_at(i) {
+            return Some(i);
+        }
+    }
+
+    *pos = a.len();
+    None
+}
+
+/// Iterator over the elements of two stable vectors at their common indices.
+///
+/// Use the method
+/// [`StableVec::zip_by_index()`](../struct.StableVecFacade.html#method.zip_by_index)
+/// to obtain an iterator of this kind.
+pub struct ZipByIndex<'a, A: 'a, B: 'a, SA: 'a + Storage<A>, SB: 'a + Storage<B>> {
+    a: &'a StableVecFacade<A, SA>,
+    b: &'a StableVecFacade<B, SB>,
+    pos: usize,
+}
+
+impl<'a, A, B, SA: Storage<A>, SB: Storage<B>> Iterator for ZipByIndex<'a, A, B, SA, SB> {
+    type Item = (usize, &'a A, &'a B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        next_common_index(&mut self.pos, &self.a.storage, &self.b.storage).map(|i| {
+            unsafe { (i, self.a.storage.get_unchecked(i), self.b.storage.get_unchecked(i)) }
+        })
+    }
+}
+
+/// Iterator over the elements of two stable vectors at their common indices,
+/// with mutable access to the first one.
+///
+/// Use the method
+/// [`StableVec::zip_by_index_mut()`](../struct.StableVecFacade.html#method.zip_by_index_mut)
+/// to obtain an iterator of this kind.
+pub struct ZipByIndexMut<'a, A: 'a, B: 'a, SA: 'a + Storage<A>, SB: 'a + Storage<B>> {
+    a: &'a mut StableVecFacade<A, SA>,
+    b: &'a StableVecFacade<B, SB>,
+    pos: usize,
+}
+
+impl<'a, A, B, SA: Storage<A>, SB: Storage<B>> Iterator for ZipByIndexMut<'a, A, B, SA, SB> {
+    type Item = (usize, &'a mut A, &'a B);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let b = self.b;
+        next_common_index(&mut self.pos, &self.a.storage, &b.storage).map(|i| {
+            self.a.record(i, ChangeKind::Modified);
+
+            // Each slot is yielded at most once, so the returned references
+            // never alias.
+            let a = unsafe { &mut *(self.a.storage.get_unchecked_mut(i) as *mut A) };
+            (i, a, unsafe { b.storage.get_unchecked(i) })
+        })
+    }
+}